@@ -0,0 +1,60 @@
+//! The per-call cost of building SQL from metadata given with every call, as `buildSqlQuery`
+//! takes it from JS, against reusing a `CompiledSchema` built from it once.
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use super_join::{CompiledSchema, Root, build_sql_query_with_params, core::sdl::parse_sdl};
+
+const SCHEMA: &str = r#"
+type User @sqlTable(name: "users", alias: "user_1", uniqueKey: "id") {
+  id: ID!
+  name: String
+  posts: [Post!]! @sqlJoin(on: "$parent.id = $child.author_id")
+}
+type Post @sqlTable(name: "posts", alias: "post_1", uniqueKey: "id") {
+  id: ID!
+  title: String
+  author: User @sqlJoin(on: "$parent.author_id = $child.id", alias: "user_2")
+  comments: [Comment!]! @sqlJoin(on: "$parent.id = $child.post_id")
+}
+type Comment @sqlTable(name: "comments", alias: "comment_1", uniqueKey: "id") {
+  id: ID!
+  body: String
+  author: User @sqlJoin(on: "$parent.author_id = $child.id", alias: "user_3")
+}
+type Query {
+  users: [User!]!
+  posts: [Post!]!
+}
+"#;
+
+const QUERY: &str =
+    "{ users { name posts { title author { name } comments { body author { name } } } } }";
+
+fn build(c: &mut Criterion) {
+    let root = parse_sdl(SCHEMA).unwrap();
+    let json = root.to_json().unwrap();
+    let mut group = c.benchmark_group("build");
+
+    group.bench_function("metadata read every call", |b| {
+        b.iter(|| {
+            let root = Root::from_json(black_box(&json)).unwrap();
+            build_sql_query_with_params(black_box(QUERY), &root, None).unwrap()
+        })
+    });
+
+    let compiled = CompiledSchema::new(Root::from_json(&json).unwrap());
+    group.bench_function("compiled schema", |b| {
+        b.iter(|| compiled.build(black_box(QUERY), None, None).unwrap())
+    });
+
+    let cached = CompiledSchema::with_plan_cache(root, 64);
+    group.bench_function("compiled schema with plan cache", |b| {
+        b.iter(|| cached.build(black_box(QUERY), None, None).unwrap())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, build);
+criterion_main!(benches);
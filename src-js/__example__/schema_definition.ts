@@ -13,7 +13,7 @@ export const post: Node = {
         kind: "left_join",
         on: {
           kind: "raw",
-          value: `${aliases.post}".author_id = "${aliases.post_author}".id`,
+          value: "$parent.author_id = $child.id",
         },
       },
       extends: {
@@ -37,7 +37,7 @@ export const user: Node = {
       join: {
         on: {
           kind: "raw",
-          value: `"${aliases.user}".post_id = "${aliases.post}".id`,
+          value: "$parent.post_id = $child.id",
         },
         kind: "left_join",
       },
@@ -46,6 +46,7 @@ export const user: Node = {
         extends: aliases.post,
         field_name: "posts",
       },
+      many: true,
     },
   },
 };
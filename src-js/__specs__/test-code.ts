@@ -1,5 +1,23 @@
 import { metadata, schema } from "../__example__/schema.js";
-import { buildSqlQuery } from "../index.js";
+import {
+  buildRoot,
+  buildSqlQuery,
+  child,
+  col,
+  eq,
+  exists,
+  ilike,
+  node,
+  on,
+  param,
+  parent,
+  hydrateResults,
+  PersistedQueries,
+  printSdl,
+  queryHash,
+  SuperJoin,
+  validateSchema,
+} from "../index.js";
 import { aliases } from "../__example__/schema_aliases.js";
 
 export function runtime_stats(
@@ -26,6 +44,56 @@ export function runtime_stats(
   assertContains(sql, "SELECT");
 }
 
+export function runtime_stats_compiled(
+  assertContains: (str: string, substring: string) => void,
+) {
+  const query = `{ user { posts { title author { name } } } }`;
+  const superJoin = new SuperJoin(metadata);
+  superJoin.build(query);
+
+  console.time("1000 passes of buildSqlQuery (metadata deserialized every call)");
+  for (let i = 0; i < 1000; i++) {
+    buildSqlQuery(query, metadata);
+  }
+  console.timeEnd("1000 passes of buildSqlQuery (metadata deserialized every call)");
+
+  console.time("1000 passes of SuperJoin.build (metadata deserialized once)");
+  let sql = "";
+  for (let i = 0; i < 1000; i++) {
+    sql = superJoin.build(query).sql;
+  }
+  console.timeEnd("1000 passes of SuperJoin.build (metadata deserialized once)");
+
+  assertContains(sql, `"${aliases.post_author}"."name"`);
+
+  const cached = new SuperJoin(metadata, 64);
+  cached.build(query);
+  console.time("1000 passes of SuperJoin.build (plan cache)");
+  for (let i = 0; i < 1000; i++) {
+    sql = cached.build(query).sql;
+  }
+  console.timeEnd("1000 passes of SuperJoin.build (plan cache)");
+
+  assertContains(sql, `"${aliases.post_author}"."name"`);
+}
+
+export function hydrate_results(
+  assertContains: (str: string, substring: string) => void,
+) {
+  const query = `{ user { id name posts { title } } }`;
+  const rows = [
+    { [`${aliases.user}_id`]: 1, [`${aliases.user}_name`]: "Ann", [`${aliases.post}_title`]: "First" },
+    { [`${aliases.user}_id`]: 1, [`${aliases.user}_name`]: "Ann", [`${aliases.post}_title`]: "Second" },
+  ];
+
+  const hydrated = JSON.stringify(hydrateResults(query, metadata, rows));
+  assertContains(
+    hydrated,
+    `{"user":{"id":1,"name":"Ann","posts":[{"title":"First"},{"title":"Second"}]}}`,
+  );
+  assertContains(JSON.stringify(new SuperJoin(metadata).hydrate(query, rows)), hydrated);
+}
+
 export function test_1(
   assertContains: (str: string, substring: string) => void,
 ) {
@@ -53,3 +121,270 @@ export function user(assertContains: (str: string, substring: string) => void) {
   assertContains(sql, `"${aliases.post}"."title"`);
   assertContains(sql, `"${aliases.post_author}"."name"`);
 }
+
+export function print_sdl(
+  assertContains: (str: string, substring: string) => void,
+) {
+  const sdl = printSdl(metadata);
+
+  assertContains(sdl, `type User {`);
+  assertContains(sdl, `  posts: `);
+  assertContains(sdl, `type Query {`);
+}
+
+export function validate_schema(
+  assertContains: (str: string, substring: string) => void,
+) {
+  const issues = validateSchema([...metadata, schema.user]);
+
+  assertContains(JSON.stringify(issues), `"kind":"duplicate_alias"`);
+}
+
+export function structured_errors(
+  assertContains: (str: string, substring: string) => void,
+) {
+  let error: unknown;
+  try {
+    buildSqlQuery(`{ posts { title author { name } } }`, metadata, {
+      builder: "postgres",
+      max_cost: 1,
+    });
+  } catch (err) {
+    error = err;
+  }
+
+  assertContains(JSON.stringify(error), `"kind":"max_cost_exceeded"`);
+  assertContains(JSON.stringify(error), `"max_cost":1`);
+}
+
+export function persisted_queries(
+  assertContains: (str: string, substring: string) => void,
+) {
+  const query = `{ posts { title author { name } } }`;
+  const compiled = new PersistedQueries(
+    [{ name: "Posts", query }],
+    metadata,
+    { builder: "postgres" },
+    true,
+  );
+  const loaded = PersistedQueries.fromJson(compiled.toJson(), metadata);
+
+  assertContains(loaded.buildByHash(queryHash(query)).sql, `"${aliases.post}"."title"`);
+}
+
+export function root_builder(
+  assertContains: (str: string, substring: string) => void,
+) {
+  const metadata = buildRoot(
+    node("users")
+      .alias("user_1")
+      .fieldName("users")
+      .many()
+      .column("id")
+      .joinMany("posts", "post_1", on(eq(col("user_1", "id"), col("post_1", "author_id")))),
+    node("posts")
+      .alias("post_1")
+      .column("title")
+      .joinOne("author", "user_1", on(eq(col("post_1", "author_id"), col("user_2", "id"))), {
+        alias: "user_2",
+      }),
+  );
+
+  const sql = buildSqlQuery(`{ users { posts { title author { id } } } }`, metadata);
+  assertContains(sql, `LEFT JOIN "users" AS "user_2" ON "post_1"."author_id" = "user_2"."id"`);
+
+  let error = "";
+  try {
+    buildRoot(node("users").fieldName("users"), node("users").fieldName("people"));
+  } catch (err) {
+    error = String(err);
+  }
+  assertContains(error, "duplicate_alias");
+}
+
+export function sql_expressions(
+  assertContains: (str: string, substring: string) => void,
+) {
+  const metadata = buildRoot(
+    node("users")
+      .alias("user_1")
+      .fieldName("users")
+      .column("name")
+      .filter(ilike(col("user_1", "name"), "a%"))
+      .filter(exists("posts", "post_1", eq(col("post_1", "author_id"), col("user_1", "id")))),
+  );
+
+  const sql = buildSqlQuery(`{ users { name } }`, metadata, { builder: "sqlite" });
+  assertContains(sql, `LOWER("user_1"."name") LIKE LOWER(?)`);
+  assertContains(sql, `EXISTS(SELECT 1 FROM "posts" AS "post_1"`);
+}
+
+export function param_positions(
+  assertContains: (str: string, substring: string) => void,
+) {
+  const metadata = buildRoot(
+    node("users")
+      .alias("user_1")
+      .fieldName("users")
+      .column("name")
+      .filter(eq(col("user_1", "id"), param("$userId", { kind: "int", value: null }))),
+  );
+
+  const built = new SuperJoin(metadata).build(`{ users { name } }`, { userId: 5 });
+  assertContains(built.sql, `"user_1"."id" = $1`);
+  assertContains(JSON.stringify(built.params), `[{"kind":"int","value":5}]`);
+  assertContains(JSON.stringify(built.positions), `{"userId":[0]}`);
+}
+
+export function where_argument(
+  assertContains: (str: string, substring: string) => void,
+) {
+  const metadata = buildRoot(
+    node("users")
+      .alias("user_1")
+      .fieldName("users")
+      .filterable()
+      .column("name")
+      .joinMany("posts", "post_1", on(eq(col("user_1", "id"), col("post_1", "author_id")))),
+    node("posts").alias("post_1").filterable().column("title"),
+  );
+
+  const sql = buildSqlQuery(
+    `{ users(where: { posts: { title: { contains: "rust" } } }) { name } }`,
+    metadata,
+  );
+  assertContains(sql, `WHERE EXISTS(SELECT 1 FROM "posts" AS "user_1_posts"`);
+  assertContains(printSdl(metadata), `users(where: UserWhere): User`);
+}
+
+export function order_by_argument(
+  assertContains: (str: string, substring: string) => void,
+) {
+  const metadata = buildRoot(
+    node("posts")
+      .alias("post_1")
+      .fieldName("posts")
+      .sortable()
+      .column("title")
+      .joinOne("author", "user_1", on(eq(col("post_1", "author_id"), col("user_1", "id")))),
+    node("users").alias("user_1").sortable().column("name"),
+  );
+
+  const sql = buildSqlQuery(
+    `{ posts(orderBy: [{ author: { field: NAME } }, { field: TITLE, direction: DESC }]) { title } }`,
+    metadata,
+  );
+  assertContains(sql, `ORDER BY "post_1_author"."name" ASC, "post_1"."title" DESC`);
+  assertContains(printSdl(metadata), `posts(orderBy: [PostOrderBy!]): Post`);
+}
+
+export function polymorphic_nodes(
+  assertContains: (str: string, substring: string) => void,
+) {
+  const metadata = buildRoot(
+    node("search_results")
+      .alias("search_1")
+      .fieldName("search")
+      .many()
+      .uniqueKey("id")
+      .graphqlType("SearchResult")
+      .member("post_1", eq(parent("post_id"), child("id"))),
+    node("posts").alias("post_1").uniqueKey("id").column("title"),
+  );
+
+  const query = `{ search { ... on Post { title } } }`;
+  assertContains(buildSqlQuery(query, metadata), `LEFT JOIN "posts" AS "post_1"`);
+  const rows = [{ search_1_id: 1, post_1_id: 2, post_1_title: "Hi" }];
+  assertContains(
+    JSON.stringify(hydrateResults(query, metadata, rows)),
+    `{"search":[{"__typename":"Post","title":"Hi"}]}`,
+  );
+}
+
+export function typename(
+  assertContains: (str: string, substring: string) => void,
+) {
+  const metadata = buildRoot(
+    node("people").alias("user_1").fieldName("user").graphqlType("Person").column("name"),
+  );
+  const rows = [{ user_1_name: "Ann" }];
+  assertContains(
+    JSON.stringify(hydrateResults(`{ user { __typename name } }`, metadata, rows)),
+    `{"user":{"__typename":"Person","name":"Ann"}}`,
+  );
+}
+
+export function recursive_relations(
+  assertContains: (str: string, substring: string) => void,
+) {
+  const metadata = buildRoot(
+    node("categories")
+      .alias("category_1")
+      .fieldName("category")
+      .uniqueKey("id")
+      .column("name")
+      .recursive("subcategories", eq(parent("id"), child("parent_id")), { tree: true }),
+  );
+  const query = `{ category { name subcategories { name subcategories { name } } } }`;
+  assertContains(buildSqlQuery(query, metadata), `WITH RECURSIVE "category_1_subcategories"`);
+  const rows = [
+    { category_1_id: 1, category_1_name: "Books", category_1_2_id: 2, category_1_2_name: "Fiction", category_1_subcategories_depth: 1, category_1_subcategories_parent_key: 1 },
+    { category_1_id: 1, category_1_name: "Books", category_1_2_id: 4, category_1_2_name: "Fantasy", category_1_subcategories_depth: 2, category_1_subcategories_parent_key: 2 },
+  ];
+  assertContains(
+    JSON.stringify(hydrateResults(query, metadata, rows)),
+    `{"category":{"name":"Books","subcategories":[{"name":"Fiction","subcategories":[{"name":"Fantasy","subcategories":[]}]}]}}`,
+  );
+}
+
+export function mutations(
+  assertContains: (str: string, substring: string) => void,
+) {
+  const metadata = buildRoot(
+    node("posts").alias("post_1").fieldName("posts").uniqueKey("id").mutable().column("id").column("title"),
+  );
+  assertContains(
+    buildSqlQuery(`mutation { updatePost(id: 3, patch: { title: "Hi" }) { title } }`, metadata),
+    `WITH "post_1" AS (UPDATE "posts" AS "post_1" SET "title" = $1 WHERE "post_1"."id" = $2 RETURNING *) SELECT "post_1"."title" AS "post_1_title"`,
+  );
+  assertContains(
+    buildSqlQuery(`mutation { createPost(input: { title: "Hi" }) { id } }`, metadata, { builder: "mysql" }),
+    "INSERT INTO `posts` (`title`) VALUES (?);\nSELECT `post_1`.`id` AS `post_1_id` FROM `posts` AS `post_1`",
+  );
+}
+
+export function upserts(
+  assertContains: (str: string, substring: string) => void,
+) {
+  const metadata = buildRoot(
+    node("posts").alias("post_1").fieldName("posts").uniqueKey("id").mutable().upsertColumns(["title"]).column("id").column("title").column("body"),
+  );
+  const query = `mutation { upsertPost(input: { id: 3, title: "Hi", body: "Yo" }) { id } }`;
+  assertContains(
+    buildSqlQuery(query, metadata),
+    `ON CONFLICT ("id") DO UPDATE SET "title" = "excluded"."title" RETURNING *) SELECT "post_1"."id" AS "post_1_id"`,
+  );
+  assertContains(
+    buildSqlQuery(query, metadata, { builder: "mysql" }),
+    "ON DUPLICATE KEY UPDATE `title` = VALUES(`title`)",
+  );
+}
+
+export function nested_inserts(
+  assertContains: (str: string, substring: string) => void,
+) {
+  const metadata = buildRoot(
+    node("users").alias("user_1").fieldName("users").uniqueKey("id").mutable().column("id").column("name")
+      .joinMany("posts", "post_1", on(eq(parent("id"), child("author_id")))),
+    node("posts").alias("post_1").uniqueKey("id").mutable().column("id").column("title"),
+  );
+  const query = `mutation { createUser(input: { name: "Ann", posts: [{ title: "Hi" }] }) { id } }`;
+  assertContains(
+    buildSqlQuery(query, metadata),
+    `"user_1_posts_0" AS (INSERT INTO "posts" ("title", "author_id") SELECT $2, "user_1"."id" FROM "user_1")`,
+  );
+  assertContains(
+    buildSqlQuery(query, metadata, { builder: "sqlite" }),
+    `WHERE "user_1"."id" = ? ORDER BY "user_1"."id" ASC;\nINSERT INTO "posts" ("title", "author_id") VALUES (?, ?)`,
+  );
+}
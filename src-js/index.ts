@@ -6,7 +6,15 @@ import {
 } from "graphql";
 export type * from "../pkg/super_join.js";
 export * from "../pkg/super_join.js";
-import type { ColumnRef, SqlParam, Value } from "../pkg/super_join.js";
+import { validateSchema } from "../pkg/super_join.js";
+import type {
+  Field,
+  Join,
+  JoinInfo,
+  Node,
+  SqlExpr,
+  SqlValue,
+} from "../pkg/super_join.js";
 
 export function extractSubQuery(info: GraphQLResolveInfo): string {
   const fieldNode = info.fieldNodes[0];
@@ -29,3 +37,247 @@ export function extractSubQuery(info: GraphQLResolveInfo): string {
 
   return print(doc); // returns: `{ posts { title comments { content } } }`
 }
+
+/** A column of the node aliased `table`, e.g. `col("user_1", "id")`. */
+export function col(table: string, column: string): SqlExpr {
+  return { kind: "column", table, column };
+}
+
+/** A column of the node a join condition is written on, e.g. `parent("id")`. */
+export function parent(column: string): SqlExpr {
+  return col("$parent", column);
+}
+
+/** A column of the node a join condition joins, e.g. `child("author_id")`. */
+export function child(column: string): SqlExpr {
+  return col("$child", column);
+}
+
+/** A value bound at query time, overridden by the GraphQL variable `name`. */
+export function param(name: string, value: SqlValue): SqlExpr {
+  return { kind: "param", name, value };
+}
+
+export function literal(value: string): SqlExpr {
+  return { kind: "literal", value };
+}
+
+export function raw(sql: string): SqlExpr {
+  return { kind: "raw", value: sql };
+}
+
+export const eq = (left: SqlExpr, right: SqlExpr): SqlExpr => ({ kind: "eq", left, right });
+export const neq = (left: SqlExpr, right: SqlExpr): SqlExpr => ({ kind: "neq", left, right });
+export const gt = (left: SqlExpr, right: SqlExpr): SqlExpr => ({ kind: "gt", left, right });
+export const gte = (left: SqlExpr, right: SqlExpr): SqlExpr => ({ kind: "gte", left, right });
+export const lt = (left: SqlExpr, right: SqlExpr): SqlExpr => ({ kind: "lt", left, right });
+export const lte = (left: SqlExpr, right: SqlExpr): SqlExpr => ({ kind: "lte", left, right });
+export const and = (left: SqlExpr, right: SqlExpr): SqlExpr => ({ kind: "and", left, right });
+export const or = (left: SqlExpr, right: SqlExpr): SqlExpr => ({ kind: "or", left, right });
+export const not = (expr: SqlExpr): SqlExpr => ({ kind: "not", expr });
+export const isNull = (expr: SqlExpr): SqlExpr => ({ kind: "is_null", expr });
+export const isNotNull = (expr: SqlExpr): SqlExpr => ({ kind: "is_not_null", expr });
+export const add = (left: SqlExpr, right: SqlExpr): SqlExpr => ({ kind: "add", left, right });
+export const sub = (left: SqlExpr, right: SqlExpr): SqlExpr => ({ kind: "sub", left, right });
+export const mul = (left: SqlExpr, right: SqlExpr): SqlExpr => ({ kind: "mul", left, right });
+export const div = (left: SqlExpr, right: SqlExpr): SqlExpr => ({ kind: "div", left, right });
+export const like = (left: SqlExpr, pattern: string): SqlExpr => ({ kind: "like", left, right: pattern });
+/** A case-insensitive `like`, emulated with `LOWER` on SQLite and MySQL. */
+export const ilike = (left: SqlExpr, pattern: string): SqlExpr => ({ kind: "ilike", left, right: pattern });
+/** Whether the text `left` contains `right`, which unlike a `like` pattern can be a param, with `%` and `_` matching only themselves. */
+export const contains = (left: SqlExpr, right: SqlExpr): SqlExpr => ({ kind: "contains", left, right });
+export const isIn = (left: SqlExpr, values: SqlExpr[]): SqlExpr => ({ kind: "in", left, right: values });
+export const notIn = (left: SqlExpr, values: SqlExpr[]): SqlExpr => ({ kind: "not_in", left, right: values });
+export const between = (expr: SqlExpr, low: SqlExpr, high: SqlExpr): SqlExpr => ({ kind: "between", expr, low, high });
+
+/** A call of the SQL function `name`, e.g. `func("lower", [col("user_1", "name")])`. */
+export function func(name: string, args: SqlExpr[] = []): SqlExpr {
+  return { kind: "function", name, args };
+}
+
+/** Whether a row of `table` aliased `alias` matches `condition`. */
+export function exists(table: string, alias: string, condition: SqlExpr): SqlExpr {
+  return { kind: "exists", table, alias, condition };
+}
+
+/** `CASE WHEN .. THEN .. ELSE otherwise END`, null when no branch matches and there is no `otherwise`. */
+export function caseWhen(branches: [when: SqlExpr, then: SqlExpr][], otherwise?: SqlExpr): SqlExpr {
+  return {
+    kind: "case",
+    branches: branches.map(([when, then]) => ({ when, then })),
+    otherwise: otherwise ?? null,
+  };
+}
+
+/** A `LEFT JOIN` on `condition`. */
+export function on(condition: SqlExpr): Join {
+  return { on: condition, kind: "left_join" };
+}
+
+export type JoinOptions = {
+  /** The SQL alias of the joined node, needed when it is joined more than once */
+  alias?: string;
+  many?: boolean;
+  cost?: number;
+};
+
+export type RecursiveOptions = {
+  /** The deepest level followed, unlimited when omitted */
+  maxDepth?: number;
+  /** Nests each level under the one above it instead of returning a flat list */
+  tree?: boolean;
+  depthField?: string;
+};
+
+/** Builds a `Node` the same way as the Rust `Node::table(..)` builder. */
+export class NodeBuilder {
+  private node: Node;
+
+  constructor(table: string) {
+    this.node = { alias: table, field_name: "", table, fields: {} };
+  }
+
+  alias(alias: string): this {
+    this.node.alias = alias;
+    return this;
+  }
+
+  /** Makes this node selectable from the root query field `fieldName`. */
+  fieldName(fieldName: string): this {
+    this.node.field_name = fieldName;
+    return this;
+  }
+
+  /** Makes the root query field return a list. */
+  many(): this {
+    this.node.many = true;
+    return this;
+  }
+
+  uniqueKey(column: string): this {
+    this.node.unique_key = column;
+    return this;
+  }
+
+  /** Lets query fields selecting this node filter it with a `where` argument. */
+  filterable(): this {
+    this.node.filterable = true;
+    return this;
+  }
+
+  /** Lets query fields selecting this node sort it with an `orderBy` argument. */
+  sortable(): this {
+    this.node.sortable = true;
+    return this;
+  }
+
+  /** Lets `create<Type>`, `update<Type>`, `upsert<Type>` and `delete<Type>` mutations write this node. */
+  mutable(): this {
+    this.node.mutable = true;
+    return this;
+  }
+
+  /** Limits the columns `upsert<Type>` updates when the row already exists. */
+  upsertColumns(columns: string[]): this {
+    this.node.upsert_columns = columns;
+    return this;
+  }
+
+  /** Makes this node an interface whose rows are typed by their `discriminator` column. */
+  singleTable(discriminator: string, types: Record<string, string>): this {
+    this.node.polymorphic = { kind: "single_table", discriminator, types };
+    return this;
+  }
+
+  /** Makes this node a union of the node aliased `extendsAlias`, LEFT JOINed on `on`. */
+  member(extendsAlias: string, on: SqlExpr): this {
+    const member = { extends: extendsAlias, on };
+    if (this.node.polymorphic?.kind === "union") {
+      this.node.polymorphic.members.push(member);
+    } else {
+      this.node.polymorphic = { kind: "union", members: [member] };
+    }
+    return this;
+  }
+
+  graphqlType(graphqlType: string): this {
+    this.node.graphql_type = graphqlType;
+    return this;
+  }
+
+  column(name: string): this {
+    return this.columnAs(name, name);
+  }
+
+  columnAs(field: string, column: string): this {
+    return this.field(field, { kind: "column", column, table: null });
+  }
+
+  /** Filters every row of this node, ANDed with its other filters. */
+  filter(expr: SqlExpr): this {
+    const existing = this.node.fields["where"];
+    return this.field("where", {
+      kind: "where",
+      expr: existing?.kind === "where" ? and(existing.expr, expr) : expr,
+    });
+  }
+
+  joinOne(field: string, extendsAlias: string, join: Join, options: JoinOptions = {}): this {
+    return this.join(field, extendsAlias, join, { ...options, many: false });
+  }
+
+  joinMany(field: string, extendsAlias: string, join: Join, options: JoinOptions = {}): this {
+    return this.join(field, extendsAlias, join, { ...options, many: true });
+  }
+
+  join(field: string, extendsAlias: string, join: Join, options: JoinOptions = {}): this {
+    const joinInfo: JoinInfo = {
+      extends: {
+        alias: options.alias ?? extendsAlias,
+        field_name: field,
+        extends: extendsAlias,
+      },
+      join,
+      many: options.many ?? false,
+      cost: options.cost ?? null,
+    };
+    return this.field(field, { kind: "join", ...joinInfo });
+  }
+
+  /** A field following `on`, written against `$parent` and `$child`, down this node's table. */
+  recursive(field: string, on: SqlExpr, options: RecursiveOptions = {}): this {
+    return this.field(field, {
+      kind: "recursive",
+      on,
+      max_depth: options.maxDepth ?? null,
+      tree: options.tree ?? false,
+      depth_field: options.depthField ?? null,
+    });
+  }
+
+  build(): Node {
+    return structuredClone(this.node);
+  }
+
+  private field(name: string, field: Field): this {
+    this.node.fields[name] = field;
+    return this;
+  }
+}
+
+/** Starts a node for `table`, aliased as the table until `alias` is called. */
+export function node(table: string): NodeBuilder {
+  return new NodeBuilder(table);
+}
+
+/** The metadata for `nodes`, throwing when it fails `validateSchema`. */
+export function buildRoot(...nodes: (Node | NodeBuilder)[]): Node[] {
+  const metadata = nodes.map((node) =>
+    node instanceof NodeBuilder ? node.build() : node,
+  );
+  const issues = validateSchema(metadata);
+  if (issues.length > 0) {
+    throw new Error(`the schema is invalid: ${JSON.stringify(issues)}`);
+  }
+  return metadata;
+}
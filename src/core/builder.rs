@@ -0,0 +1,517 @@
+use crate::core::{
+    error::Error,
+    schema::{
+        ColumnInfo, ExtendsNode, Field, JoinInfo, Node, Polymorphic, RecursiveInfo, Root,
+        SingleTable, Union, UnionMember, WhereInfo,
+    },
+    shared_schema::{
+        AddExpr, AndExpr, BetweenExpr, CHILD, CaseExpr, CaseWhen, ColumnRef, DivExpr, EqExpr,
+        ExistsExpr, FunctionExpr, GtExpr, GteExpr, InExpr, IsNotNullExpr, IsNullExpr, Join,
+        JoinType, LikeExpr, LtExpr, LteExpr, MulExpr, NeqExpr, NotExpr, NotInExpr, OrExpr, PARENT,
+        SqlExpr, SqlParam, SqlValue, SubExpr,
+    },
+    validate::validate_nodes,
+};
+
+/// A column of the node aliased `table`, e.g. `col("user_1", "id")`.
+pub fn col(table: impl Into<String>, column: impl Into<String>) -> SqlExpr {
+    SqlExpr::Column(ColumnRef {
+        column: column.into(),
+        table: Some(table.into()),
+    })
+}
+
+/// A column of the node a join condition is written on, e.g. `parent("id")`.
+pub fn parent(column: impl Into<String>) -> SqlExpr {
+    col(PARENT, column)
+}
+
+/// A column of the node a join condition joins, e.g. `child("author_id")`.
+pub fn child(column: impl Into<String>) -> SqlExpr {
+    col(CHILD, column)
+}
+
+/// A value bound at query time, overridden by the GraphQL variable `name`.
+pub fn param(name: impl Into<String>, value: SqlValue) -> SqlExpr {
+    SqlExpr::Param(SqlParam {
+        name: name.into(),
+        value,
+    })
+}
+
+pub fn literal(value: impl Into<String>) -> SqlExpr {
+    SqlExpr::Literal(value.into().into())
+}
+
+pub fn raw(sql: impl Into<String>) -> SqlExpr {
+    SqlExpr::Raw(sql.into().into())
+}
+
+/// A call of the SQL function `name`, e.g. `func("coalesce", vec![col("user_1", "name"), literal("")])`.
+pub fn func(name: impl Into<String>, args: Vec<SqlExpr>) -> SqlExpr {
+    SqlExpr::Function(FunctionExpr {
+        name: name.into(),
+        args,
+    })
+}
+
+/// Whether a row of `table` aliased `alias` matches `condition`.
+pub fn exists(table: impl Into<String>, alias: impl Into<String>, condition: SqlExpr) -> SqlExpr {
+    SqlExpr::Exists(ExistsExpr {
+        table: table.into(),
+        alias: alias.into(),
+        condition: Box::new(condition),
+    })
+}
+
+/// Starts a `CASE WHEN when THEN then`, finished with `otherwise` or `end`.
+pub fn case_when(when: SqlExpr, then: SqlExpr) -> CaseExpr {
+    CaseExpr {
+        branches: vec![],
+        otherwise: None,
+    }
+    .when(when, then)
+}
+
+/// A `LEFT JOIN` on `condition`.
+pub fn on(condition: SqlExpr) -> Join {
+    Join {
+        on: condition,
+        kind: JoinType::LeftJoin,
+    }
+}
+
+impl SqlExpr {
+    pub fn eq(self, right: SqlExpr) -> SqlExpr {
+        SqlExpr::Eq(EqExpr {
+            left: Box::new(self),
+            right: Box::new(right),
+        })
+    }
+
+    pub fn neq(self, right: SqlExpr) -> SqlExpr {
+        SqlExpr::Neq(NeqExpr {
+            left: Box::new(self),
+            right: Box::new(right),
+        })
+    }
+
+    pub fn gt(self, right: SqlExpr) -> SqlExpr {
+        SqlExpr::Gt(GtExpr {
+            left: Box::new(self),
+            right: Box::new(right),
+        })
+    }
+
+    pub fn gte(self, right: SqlExpr) -> SqlExpr {
+        SqlExpr::Gte(GteExpr {
+            left: Box::new(self),
+            right: Box::new(right),
+        })
+    }
+
+    pub fn lt(self, right: SqlExpr) -> SqlExpr {
+        SqlExpr::Lt(LtExpr {
+            left: Box::new(self),
+            right: Box::new(right),
+        })
+    }
+
+    pub fn lte(self, right: SqlExpr) -> SqlExpr {
+        SqlExpr::Lte(LteExpr {
+            left: Box::new(self),
+            right: Box::new(right),
+        })
+    }
+
+    pub fn and(self, right: SqlExpr) -> SqlExpr {
+        SqlExpr::And(AndExpr {
+            left: Box::new(self),
+            right: Box::new(right),
+        })
+    }
+
+    pub fn or(self, right: SqlExpr) -> SqlExpr {
+        SqlExpr::Or(OrExpr {
+            left: Box::new(self),
+            right: Box::new(right),
+        })
+    }
+
+    pub fn like(self, pattern: impl Into<String>) -> SqlExpr {
+        SqlExpr::Like(LikeExpr {
+            left: Box::new(self),
+            right: pattern.into(),
+        })
+    }
+
+    /// A case-insensitive `like`.
+    pub fn ilike(self, pattern: impl Into<String>) -> SqlExpr {
+        SqlExpr::ILike(LikeExpr {
+            left: Box::new(self),
+            right: pattern.into(),
+        })
+    }
+
+    pub fn between(self, low: SqlExpr, high: SqlExpr) -> SqlExpr {
+        SqlExpr::Between(BetweenExpr {
+            expr: Box::new(self),
+            low: Box::new(low),
+            high: Box::new(high),
+        })
+    }
+
+    pub fn not_in(self, values: Vec<SqlExpr>) -> SqlExpr {
+        SqlExpr::NotIn(NotInExpr {
+            left: Box::new(self),
+            right: values,
+        })
+    }
+
+    pub fn is_in(self, values: Vec<SqlExpr>) -> SqlExpr {
+        SqlExpr::In(InExpr {
+            left: Box::new(self),
+            right: values,
+        })
+    }
+
+    pub fn is_null(self) -> SqlExpr {
+        SqlExpr::IsNull(IsNullExpr {
+            expr: Box::new(self),
+        })
+    }
+
+    pub fn is_not_null(self) -> SqlExpr {
+        SqlExpr::IsNotNull(IsNotNullExpr {
+            expr: Box::new(self),
+        })
+    }
+}
+
+impl std::ops::Not for SqlExpr {
+    type Output = SqlExpr;
+
+    fn not(self) -> SqlExpr {
+        SqlExpr::Not(NotExpr {
+            expr: Box::new(self),
+        })
+    }
+}
+
+impl std::ops::Add for SqlExpr {
+    type Output = SqlExpr;
+
+    fn add(self, right: SqlExpr) -> SqlExpr {
+        SqlExpr::Add(AddExpr {
+            left: Box::new(self),
+            right: Box::new(right),
+        })
+    }
+}
+
+impl std::ops::Sub for SqlExpr {
+    type Output = SqlExpr;
+
+    fn sub(self, right: SqlExpr) -> SqlExpr {
+        SqlExpr::Sub(SubExpr {
+            left: Box::new(self),
+            right: Box::new(right),
+        })
+    }
+}
+
+impl std::ops::Mul for SqlExpr {
+    type Output = SqlExpr;
+
+    fn mul(self, right: SqlExpr) -> SqlExpr {
+        SqlExpr::Mul(MulExpr {
+            left: Box::new(self),
+            right: Box::new(right),
+        })
+    }
+}
+
+impl std::ops::Div for SqlExpr {
+    type Output = SqlExpr;
+
+    fn div(self, right: SqlExpr) -> SqlExpr {
+        SqlExpr::Div(DivExpr {
+            left: Box::new(self),
+            right: Box::new(right),
+        })
+    }
+}
+
+impl CaseExpr {
+    pub fn when(mut self, when: SqlExpr, then: SqlExpr) -> CaseExpr {
+        self.branches.push(CaseWhen { when, then });
+        self
+    }
+
+    pub fn otherwise(mut self, otherwise: SqlExpr) -> SqlExpr {
+        self.otherwise = Some(Box::new(otherwise));
+        SqlExpr::Case(self)
+    }
+
+    /// Finishes the `CASE`, which is null when no branch matches.
+    pub fn end(self) -> SqlExpr {
+        SqlExpr::Case(self)
+    }
+}
+
+impl Node {
+    /// Starts a node for `table`. It is aliased as the table and only reachable through joins
+    /// until `alias` and `field_name` say otherwise.
+    pub fn table(table: impl Into<String>) -> Node {
+        let table = table.into();
+        Node {
+            alias: table.clone(),
+            field_name: String::new(),
+            table,
+            graphql_type: None,
+            many: false,
+            unique_key: None,
+            filterable: false,
+            sortable: false,
+            mutable: false,
+            upsert_columns: None,
+            polymorphic: None,
+            fields: Default::default(),
+        }
+    }
+
+    pub fn alias(mut self, alias: impl Into<String>) -> Node {
+        self.alias = alias.into();
+        self
+    }
+
+    /// Makes this node selectable from the root query field `field_name`.
+    pub fn field_name(mut self, field_name: impl Into<String>) -> Node {
+        self.field_name = field_name.into();
+        self
+    }
+
+    /// Makes the root query field return a list.
+    pub fn many(mut self) -> Node {
+        self.many = true;
+        self
+    }
+
+    pub fn unique_key(mut self, column: impl Into<String>) -> Node {
+        self.unique_key = Some(column.into());
+        self
+    }
+
+    /// Lets query fields selecting this node filter it with a `where` argument.
+    pub fn filterable(mut self) -> Node {
+        self.filterable = true;
+        self
+    }
+
+    /// Lets query fields selecting this node sort it with an `orderBy` argument.
+    pub fn sortable(mut self) -> Node {
+        self.sortable = true;
+        self
+    }
+
+    /// Lets `create<Type>`, `update<Type>`, `upsert<Type>` and `delete<Type>` mutations write
+    /// this node.
+    pub fn mutable(mut self) -> Node {
+        self.mutable = true;
+        self
+    }
+
+    /// Limits the columns `upsert<Type>` updates when the row already exists.
+    pub fn upsert_columns(mut self, columns: impl IntoIterator<Item = impl Into<String>>) -> Node {
+        self.upsert_columns = Some(columns.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Makes this node an interface whose rows are typed by their `discriminator` column, each
+    /// value naming a GraphQL type.
+    pub fn single_table<V: Into<String>, T: Into<String>>(
+        mut self,
+        discriminator: impl Into<String>,
+        types: impl IntoIterator<Item = (V, T)>,
+    ) -> Node {
+        self.polymorphic = Some(Polymorphic::SingleTable(SingleTable {
+            discriminator: discriminator.into(),
+            types: types
+                .into_iter()
+                .map(|(value, type_name)| (value.into(), type_name.into()))
+                .collect(),
+        }));
+        self
+    }
+
+    /// Makes this node a union of the node aliased `extends`, LEFT JOINed on `on`, and any
+    /// other members.
+    pub fn member(mut self, extends: impl Into<String>, on: SqlExpr) -> Node {
+        let member = UnionMember {
+            extends: extends.into(),
+            on,
+        };
+        match &mut self.polymorphic {
+            Some(Polymorphic::Union(union)) => union.members.push(member),
+            _ => {
+                self.polymorphic = Some(Polymorphic::Union(Union {
+                    members: vec![member],
+                }))
+            }
+        }
+        self
+    }
+
+    pub fn graphql_type(mut self, graphql_type: impl Into<String>) -> Node {
+        self.graphql_type = Some(graphql_type.into());
+        self
+    }
+
+    /// A field selecting the column of the same name.
+    pub fn column(self, name: impl Into<String>) -> Node {
+        let name = name.into();
+        self.column_as(name.clone(), name)
+    }
+
+    /// A field selecting a differently named column.
+    pub fn column_as(mut self, field: impl Into<String>, column: impl Into<String>) -> Node {
+        let column = ColumnInfo::from(column.into());
+        self.fields.insert(field.into(), Field::Column(column));
+        self
+    }
+
+    /// A field joining at most one `extends` node.
+    pub fn join_one(
+        self,
+        field: impl Into<String>,
+        extends: impl Into<String>,
+        join: Join,
+    ) -> Node {
+        self.join(field, JoinInfo::to(extends, join))
+    }
+
+    /// A field joining a list of `extends` nodes.
+    pub fn join_many(
+        self,
+        field: impl Into<String>,
+        extends: impl Into<String>,
+        join: Join,
+    ) -> Node {
+        self.join(field, JoinInfo::to(extends, join).many())
+    }
+
+    /// Filters every row of this node, ANDed with its other filters.
+    pub fn filter(mut self, expr: SqlExpr) -> Node {
+        let expr = match self.fields.remove("where") {
+            Some(Field::Where(where_info)) => where_info.expr.and(expr),
+            _ => expr,
+        };
+        self.fields
+            .insert("where".into(), Field::Where(WhereInfo { expr }));
+        self
+    }
+
+    /// A field following `recursive.on` from each row down through the node's own table.
+    pub fn recursive(mut self, field: impl Into<String>, recursive: RecursiveInfo) -> Node {
+        self.fields
+            .insert(field.into(), Field::Recursive(recursive));
+        self
+    }
+
+    pub fn join(mut self, field: impl Into<String>, mut join_info: JoinInfo) -> Node {
+        let field = field.into();
+        join_info.extends.field_name = field.clone();
+        self.fields.insert(field, Field::Join(join_info));
+        self
+    }
+}
+
+impl JoinInfo {
+    /// Joins the node aliased `extends`, under that same alias until `alias` is called.
+    pub fn to(extends: impl Into<String>, join: Join) -> JoinInfo {
+        let extends = extends.into();
+        JoinInfo {
+            extends: ExtendsNode {
+                alias: extends.clone(),
+                field_name: String::new(),
+                extends,
+            },
+            join,
+            many: false,
+            cost: None,
+        }
+    }
+
+    /// The SQL alias of the joined node, needed when it is joined more than once.
+    pub fn alias(mut self, alias: impl Into<String>) -> JoinInfo {
+        self.extends.alias = alias.into();
+        self
+    }
+
+    pub fn many(mut self) -> JoinInfo {
+        self.many = true;
+        self
+    }
+
+    pub fn cost(mut self, cost: u32) -> JoinInfo {
+        self.cost = Some(cost);
+        self
+    }
+}
+
+impl RecursiveInfo {
+    /// Follows `on`, written against `$parent` and `$child`, as deep as the rows go.
+    pub fn on(on: SqlExpr) -> RecursiveInfo {
+        RecursiveInfo {
+            on,
+            max_depth: None,
+            tree: false,
+            depth_field: None,
+        }
+    }
+
+    pub fn max_depth(mut self, max_depth: u32) -> RecursiveInfo {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Nests each level under the one above it instead of returning a flat list.
+    pub fn tree(mut self) -> RecursiveInfo {
+        self.tree = true;
+        self
+    }
+
+    /// Selects each object's depth as `field` instead of `depth`.
+    pub fn depth_as(mut self, field: impl Into<String>) -> RecursiveInfo {
+        self.depth_field = Some(field.into());
+        self
+    }
+}
+
+/// Collects nodes into a `Root` that has passed `validate_nodes`.
+#[derive(Clone, Debug, Default)]
+pub struct RootBuilder {
+    nodes: Vec<Node>,
+}
+
+impl RootBuilder {
+    pub fn node(mut self, node: Node) -> RootBuilder {
+        self.nodes.push(node);
+        self
+    }
+
+    pub fn build(self) -> Result<Root, Error> {
+        let (root, issues) = validate_nodes(self.nodes);
+        if issues.is_empty() {
+            Ok(root)
+        } else {
+            Err(Error::InvalidSchema { issues })
+        }
+    }
+}
+
+impl Root {
+    pub fn builder() -> RootBuilder {
+        RootBuilder::default()
+    }
+}
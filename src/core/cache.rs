@@ -0,0 +1,277 @@
+use std::{collections::HashMap, sync::Arc};
+
+use graphql_parser::query::{
+    Definition, Document, OperationDefinition, Selection, SelectionSet, TypeCondition, Value,
+};
+
+use crate::core::{
+    cost::CostPlan, error::Error, hydrate::ResponseShape, shared_schema::SqlValue,
+    sql_schema::SqlQuery,
+};
+
+/// Names the variables literals are hoisted into after the kind of literal, `$__int0`,
+/// `$__string1`.., so that literals of different kinds never share a plan.
+const HOISTED: [&str; 3] = ["__int", "__float", "__string"];
+
+/// A query reduced to the shape that decides its SQL.
+///
+/// Two queries that differ only in whitespace, operation name or the literal values of their
+/// arguments normalize to the same `text`. Selections and arguments keep their order, which
+/// decides the order of the SQL's columns and filters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NormalizedQuery {
+    /// A valid GraphQL document whose literal arguments are the variables `$__int0`,
+    /// `$__string1`..
+    pub text: String,
+    /// The hoisted literals and variable defaults, keyed by variable name
+    pub values: HashMap<String, SqlValue>,
+}
+
+/// Normalizes the first operation of `doc`.
+///
+/// Int, Float and String literals are hoisted into variables. Booleans, enums, nulls and the
+/// length of lists stay in the text because they can change the shape of the SQL, so plans
+/// must only ever use argument values as bound params.
+pub fn normalize_query<'a>(doc: &Document<'a, &'a str>) -> Result<NormalizedQuery, Error> {
+    let mut values = HashMap::new();
+    let mut text = String::new();
+    let mut fragments = vec![];
+
+    for definition in &doc.definitions {
+        match definition {
+            Definition::Operation(operation) if text.is_empty() => {
+                let (keyword, variable_definitions, selection_set) = match operation {
+                    OperationDefinition::SelectionSet(selection_set) => {
+                        ("query", &[][..], selection_set)
+                    }
+                    OperationDefinition::Query(query) => (
+                        "query",
+                        &query.variable_definitions[..],
+                        &query.selection_set,
+                    ),
+                    OperationDefinition::Mutation(mutation) => (
+                        "mutation",
+                        &mutation.variable_definitions[..],
+                        &mutation.selection_set,
+                    ),
+                    OperationDefinition::Subscription(_) => {
+                        return Err(Error::from("Only queries and mutations can be normalized"));
+                    }
+                };
+                let mut variables = variable_definitions
+                    .iter()
+                    .map(|definition| format!("${}: {}", definition.name, definition.var_type))
+                    .collect::<Vec<_>>();
+                variables.sort();
+                for definition in variable_definitions {
+                    if let Some(default_value) = &definition.default_value {
+                        values.insert(
+                            definition.name.to_string(),
+                            SqlValue::try_from(default_value)?,
+                        );
+                    }
+                }
+                let mut hoisted = vec![];
+                let selections = normalize_selection_set(selection_set, &mut hoisted);
+                text = if variables.is_empty() {
+                    format!("{} {}", keyword, selections)
+                } else {
+                    format!("{}({}) {}", keyword, variables.join(", "), selections)
+                };
+                for (n, value) in hoisted.iter().enumerate() {
+                    values.insert(hoisted_name(value, n), SqlValue::try_from(value)?);
+                }
+            }
+            Definition::Operation(_) => {}
+            Definition::Fragment(fragment) => fragments.push(fragment.to_string()),
+        }
+    }
+    if text.is_empty() {
+        return Err(Error::from(
+            "Invalid query structure must have a query definition in query",
+        ));
+    }
+    fragments.sort();
+    for fragment in fragments {
+        text.push('\n');
+        text.push_str(fragment.trim_end());
+    }
+    Ok(NormalizedQuery { text, values })
+}
+
+/// Whether `name` is a variable `normalize_query` hoisted a literal into.
+pub fn is_hoisted(name: &str) -> bool {
+    HOISTED.iter().any(|prefix| {
+        name.strip_prefix(prefix)
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+    })
+}
+
+/// The variable the `n`th hoisted literal, `value`, is hoisted into.
+fn hoisted_name<'a>(value: &Value<'a, &'a str>, n: usize) -> String {
+    let prefix = match value {
+        Value::Int(_) => HOISTED[0],
+        Value::Float(_) => HOISTED[1],
+        _ => HOISTED[2],
+    };
+    format!("{}{}", prefix, n)
+}
+
+/// Prints a selection set, pushing the literals it hoists onto `hoisted`.
+fn normalize_selection_set<'a>(
+    selection_set: &SelectionSet<'a, &'a str>,
+    hoisted: &mut Vec<Value<'a, &'a str>>,
+) -> String {
+    if selection_set.items.is_empty() {
+        return String::new();
+    }
+    let selections = selection_set
+        .items
+        .iter()
+        .map(|selection| match selection {
+            Selection::Field(field) => {
+                let mut text = match field.alias {
+                    Some(alias) => format!("{}: {}", alias, field.name),
+                    None => field.name.to_string(),
+                };
+                if !field.arguments.is_empty() {
+                    let arguments = field
+                        .arguments
+                        .iter()
+                        .map(|(name, value)| {
+                            format!("{}: {}", name, normalize_value(value, hoisted))
+                        })
+                        .collect::<Vec<_>>();
+                    text.push_str(&format!("({})", arguments.join(", ")));
+                }
+                for directive in &field.directives {
+                    text.push_str(&format!(" {}", directive));
+                }
+                let selections = normalize_selection_set(&field.selection_set, hoisted);
+                if !selections.is_empty() {
+                    text.push(' ');
+                    text.push_str(&selections);
+                }
+                text
+            }
+            Selection::FragmentSpread(spread) => format!("...{}", spread.fragment_name),
+            Selection::InlineFragment(fragment) => {
+                let selections = normalize_selection_set(&fragment.selection_set, hoisted);
+                match &fragment.type_condition {
+                    Some(TypeCondition::On(type_name)) => {
+                        format!("... on {} {}", type_name, selections)
+                    }
+                    None => format!("... {}", selections),
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    format!("{{ {} }}", selections.join(" "))
+}
+
+fn normalize_value<'a>(
+    value: &Value<'a, &'a str>,
+    hoisted: &mut Vec<Value<'a, &'a str>>,
+) -> String {
+    match value {
+        Value::Int(_) | Value::Float(_) | Value::String(_) => {
+            hoisted.push(value.clone());
+            format!("${}", hoisted_name(value, hoisted.len() - 1))
+        }
+        Value::List(items) => format!(
+            "[{}]",
+            items
+                .iter()
+                .map(|item| normalize_value(item, hoisted))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        // `BTreeMap` keeps the keys sorted
+        Value::Object(fields) => format!(
+            "{{{}}}",
+            fields
+                .iter()
+                .map(|(name, value)| format!("{}: {}", name, normalize_value(value, hoisted)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Value::Variable(_) | Value::Boolean(_) | Value::Null | Value::Enum(_) => value.to_string(),
+    }
+}
+
+/// A planned and rendered query shape.
+#[derive(Debug)]
+pub struct CachedPlan {
+    /// The SQL, with the positions a call binds its values at
+    pub query: SqlQuery,
+    /// The param of `query` each hoisted variable binds, which is named as it is when the
+    /// query is planned uncached
+    pub hoisted: HashMap<String, String>,
+}
+
+/// A bounded map that evicts its least recently used entry when full.
+#[derive(Debug)]
+pub struct Lru<V> {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<String, (V, u64)>,
+}
+
+impl<V: Clone> Lru<V> {
+    pub fn new(capacity: usize) -> Self {
+        Lru {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, key: &str) -> Option<V> {
+        self.tick += 1;
+        let entry = self.entries.get_mut(key)?;
+        entry.1 = self.tick;
+        Some(entry.0.clone())
+    }
+
+    pub fn insert(&mut self, key: String, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.tick += 1;
+        if self.entries.len() >= self.capacity
+            && !self.entries.contains_key(&key)
+            && let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, tick))| *tick)
+                .map(|(key, _)| key.clone())
+        {
+            self.entries.remove(&oldest);
+        }
+        self.entries.insert(key, (value, self.tick));
+    }
+}
+
+/// Memoized plans, looked up first by exact query text and then by normalized query.
+#[derive(Debug)]
+pub struct PlanCache {
+    /// Query text to its normalized form, so repeated queries skip parsing
+    pub texts: Lru<Arc<NormalizedQuery>>,
+    /// Normalized query text and the shape of its variables to its plan
+    pub plans: Lru<Arc<CachedPlan>>,
+    /// Normalized query text to the shape its rows hydrate into
+    pub shapes: Lru<Arc<ResponseShape>>,
+    /// Query text to what it costs, checked against every call's variables
+    pub costs: Lru<Arc<CostPlan>>,
+}
+
+impl PlanCache {
+    pub fn new(capacity: usize) -> Self {
+        PlanCache {
+            texts: Lru::new(capacity),
+            plans: Lru::new(capacity),
+            shapes: Lru::new(capacity),
+            costs: Lru::new(capacity),
+        }
+    }
+}
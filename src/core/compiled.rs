@@ -0,0 +1,270 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use crate::core::{
+    cache::{CachedPlan, NormalizedQuery, PlanCache, is_hoisted, normalize_query},
+    cost::{CostPlan, plan_cost},
+    error::Error,
+    fns::{
+        QueryPlan, parse_gql, plan_query_document, plan_query_inputs, plan_response_shape,
+        render_sql_query,
+    },
+    hydrate::{ResponseShape, Row, hydrate},
+    schema::{Node, Options, Root, Variables},
+    sql_schema::{SqlQuery, SqlSelect},
+    variables::InputVariables,
+};
+
+/// A `Root` indexed once so it can be queried many times.
+pub struct CompiledSchema {
+    pub root: Root,
+    /// The alias of the node each root query field selects
+    root_fields: HashMap<String, String>,
+    plan_cache: Option<Mutex<PlanCache>>,
+}
+
+impl CompiledSchema {
+    pub fn new(root: Root) -> Self {
+        let mut root_fields = HashMap::new();
+        for node in root.0.values() {
+            root_fields.insert(node.field_name.clone(), node.alias.clone());
+        }
+        CompiledSchema {
+            root,
+            root_fields,
+            plan_cache: None,
+        }
+    }
+
+    /// Like `new`, but `build` memoizes up to `capacity` query plans.
+    pub fn with_plan_cache(root: Root, capacity: usize) -> Self {
+        CompiledSchema {
+            plan_cache: Some(Mutex::new(PlanCache::new(capacity))),
+            ..CompiledSchema::new(root)
+        }
+    }
+
+    /// The node selected by the root query field `field_name`.
+    pub fn root_node(&self, field_name: &str) -> Option<&Node> {
+        self.root_fields
+            .get(field_name)
+            .and_then(|alias| self.root.0.get(alias))
+    }
+
+    pub fn build(
+        &self,
+        query: &str,
+        variables: Option<&Variables>,
+        options: Option<&Options>,
+    ) -> Result<SqlQuery, Error> {
+        let Some(cache) = &self.plan_cache else {
+            return render_sql_query(&self.plan(query, variables, options)?, options);
+        };
+        let inputs = InputVariables::new(variables)?;
+        if let Some(max_cost) = options.and_then(|options| options.max_cost) {
+            self.cost_plan(cache, query)?.check(&inputs, max_cost)?;
+        }
+        let (normalized, plan) = self.cached_plan(cache, query, &inputs, options)?;
+        let mut values = HashMap::new();
+        for (name, value) in &normalized.values {
+            let name = plan.hoisted.get(name).unwrap_or(name);
+            values.insert(name.clone(), value.clone());
+        }
+        values.extend(inputs.values);
+        let mut query = plan.query.clone();
+        query.bind_params(&values);
+        Ok(query)
+    }
+
+    /// Nests the rows returned by `query`'s SQL into the shape of the query.
+    pub fn hydrate(
+        &self,
+        query: &str,
+        rows: &[Row],
+        options: Option<&Options>,
+    ) -> Result<serde_json::Value, Error> {
+        let shape = match &self.plan_cache {
+            Some(cache) => self.cached_shape(cache, query, options)?,
+            None => Arc::new(self.response_shape(query, options)?),
+        };
+        Ok(hydrate(&shape, rows))
+    }
+
+    /// The SQL AST `build` renders.
+    pub fn plan(
+        &self,
+        query: &str,
+        variables: Option<&Variables>,
+        options: Option<&Options>,
+    ) -> Result<SqlSelect, Error> {
+        self.plan_query(query, variables, options)
+            .map(|plan| plan.sql_ast)
+    }
+
+    fn plan_query(
+        &self,
+        query: &str,
+        variables: Option<&Variables>,
+        options: Option<&Options>,
+    ) -> Result<QueryPlan, Error> {
+        let doc = parse_gql(query)?;
+        plan_query_document(
+            &doc,
+            &self.root,
+            &|field_name| self.root_node(field_name),
+            variables,
+            options,
+        )
+    }
+
+    fn response_shape(
+        &self,
+        query: &str,
+        options: Option<&Options>,
+    ) -> Result<ResponseShape, Error> {
+        plan_response_shape(
+            &parse_gql(query)?,
+            &self.root,
+            &|field_name| self.root_node(field_name),
+            options,
+        )
+    }
+
+    fn normalized(
+        &self,
+        cache: &Mutex<PlanCache>,
+        query: &str,
+        options: Option<&Options>,
+    ) -> Result<Arc<NormalizedQuery>, Error> {
+        let text_key = format!("{:?}\n{}", options, query);
+        let cached = lock(cache).texts.get(&text_key);
+        Ok(match cached {
+            Some(normalized) => normalized,
+            None => {
+                let normalized = Arc::new(normalize_query(&parse_gql(query)?)?);
+                lock(cache).texts.insert(text_key, normalized.clone());
+                normalized
+            }
+        })
+    }
+
+    /// What `query` costs, planned once and estimated again for each call's variables.
+    fn cost_plan(&self, cache: &Mutex<PlanCache>, query: &str) -> Result<Arc<CostPlan>, Error> {
+        let cached = lock(cache).costs.get(query);
+        Ok(match cached {
+            Some(cost_plan) => cost_plan,
+            None => {
+                let cost_plan = Arc::new(plan_cost(&parse_gql(query)?, &self.root)?);
+                lock(cache)
+                    .costs
+                    .insert(query.to_string(), cost_plan.clone());
+                cost_plan
+            }
+        })
+    }
+
+    fn cached_shape(
+        &self,
+        cache: &Mutex<PlanCache>,
+        query: &str,
+        options: Option<&Options>,
+    ) -> Result<Arc<ResponseShape>, Error> {
+        let normalized = self.normalized(cache, query, options)?;
+        let shape_key = format!("{:?}\n{}", options, normalized.text);
+        let cached = lock(cache).shapes.get(&shape_key);
+        match cached {
+            Some(shape) => Ok(shape),
+            None => {
+                let shape = Arc::new(self.response_shape(query, options)?);
+                lock(cache).shapes.insert(shape_key, shape.clone());
+                Ok(shape)
+            }
+        }
+    }
+
+    /// The plan of `query`, shared by every query that normalizes the same and is given
+    /// variables of the same shape.
+    fn cached_plan(
+        &self,
+        cache: &Mutex<PlanCache>,
+        query: &str,
+        inputs: &InputVariables,
+        options: Option<&Options>,
+    ) -> Result<(Arc<NormalizedQuery>, Arc<CachedPlan>), Error> {
+        let normalized = self.normalized(cache, query, options)?;
+        let plan_key = format!("{:?}\n{}\n{}", options, inputs.shape(), normalized.text);
+        let cached = lock(cache).plans.get(&plan_key);
+        let plan = match cached {
+            Some(plan) => plan,
+            None => {
+                // The cost was checked against the query as written
+                let options = options.map(|options| Options {
+                    max_cost: None,
+                    ..options.clone()
+                });
+                let plan = |query: &str, inputs: InputVariables| {
+                    plan_query_inputs(
+                        &parse_gql(query)?,
+                        &self.root,
+                        &|field_name| self.root_node(field_name),
+                        inputs,
+                        options.as_ref(),
+                    )
+                };
+                // The query as written names its params as it does uncached, and its
+                // normalized form tells which of them are bound to hoisted literals, which
+                // like the defaults it drops are given
+                let QueryPlan { sql_ast, .. } = plan(query, inputs.clone())?;
+                let mut normalized_inputs = inputs.clone();
+                for name in normalized.values.keys() {
+                    normalized_inputs.give(name);
+                }
+                let hoisted = hoisted_params(
+                    &plan(&normalized.text, normalized_inputs)?.sql_ast,
+                    &sql_ast,
+                )?;
+                let plan = Arc::new(CachedPlan {
+                    query: render_sql_query(&sql_ast, options.as_ref())?,
+                    hoisted,
+                });
+                lock(cache).plans.insert(plan_key, plan.clone());
+                plan
+            }
+        };
+
+        Ok((normalized, plan))
+    }
+}
+
+/// The param of `planned` each hoisted variable of `normalized` binds, pairing the params of
+/// the two plans of the same query in order.
+fn hoisted_params(
+    normalized: &SqlSelect,
+    planned: &SqlSelect,
+) -> Result<HashMap<String, String>, Error> {
+    let (normalized, planned) = (normalized.params(), planned.params());
+    if normalized.len() != planned.len() {
+        return Err(Error::from(
+            "The query planned differently once its literals were hoisted",
+        ));
+    }
+    Ok(normalized
+        .into_iter()
+        .zip(planned)
+        .filter(|(normalized, _)| is_hoisted(&normalized.name))
+        .map(|(normalized, planned)| (normalized.name.clone(), planned.name.clone()))
+        .collect())
+}
+
+/// The cache only holds finished entries, so one left behind by a panic is still usable.
+fn lock(cache: &Mutex<PlanCache>) -> MutexGuard<'_, PlanCache> {
+    cache.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl From<Root> for CompiledSchema {
+    fn from(root: Root) -> Self {
+        CompiledSchema::new(root)
+    }
+}
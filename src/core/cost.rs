@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+
+use graphql_parser::query::{
+    Definition, Document, Number, OperationDefinition, Selection, SelectionSet, TypeCondition,
+    Value,
+};
+use serde::Serialize;
+#[cfg(feature = "wasm")]
+use tsify::Tsify;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::core::{
+    error::Error,
+    mutation::find_mutation,
+    schema::{Field, Node, Polymorphic, Root},
+    shared_schema::SqlValue,
+    variables::InputVariables,
+};
+
+/// What a join costs when its metadata has no `cost`.
+pub const DEFAULT_JOIN_COST: u32 = 1;
+/// How many rows a list is assumed to hold when neither metadata nor arguments limit it.
+pub const DEFAULT_LIST_SIZE: u32 = 10;
+/// The arguments that limit the rows of a list, each applied per parent row.
+pub const LIMIT_ARGUMENTS: [&str; 2] = ["limit", "first"];
+/// How many of the most expensive paths a cost error reports.
+pub const REPORTED_PATHS: usize = 5;
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(into_wasm_abi))]
+pub struct CostPath {
+    /// The GraphQL field path, e.g. `user.posts.author`
+    pub path: String,
+    /// What this field adds to the total, multiplied by the size of the lists it is nested in
+    pub cost: u32,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(into_wasm_abi))]
+pub struct QueryCost {
+    pub total: u32,
+    /// Every costed field, most expensive first
+    pub paths: Vec<CostPath>,
+}
+
+/// What each field of a query costs, with the size of any list limited by a variable left
+/// to the values it is given, so it is planned once however many times it runs.
+#[derive(Clone, Debug, Default)]
+pub struct CostPlan {
+    paths: Vec<PlannedCost>,
+}
+
+#[derive(Clone, Debug)]
+struct PlannedCost {
+    path: String,
+    cost: u32,
+    /// The sizes of the lists the field is nested in, summed over each level of a recursive
+    /// field and multiplied within one
+    levels: Vec<Vec<ListSize>>,
+}
+
+#[derive(Clone, Debug)]
+enum ListSize {
+    Fixed(u32),
+    /// A `limit`/`first` variable, or `otherwise` when it isn't given an Int
+    Variable {
+        name: String,
+        otherwise: u32,
+    },
+}
+
+impl ListSize {
+    fn size(&self, variables: &InputVariables) -> u32 {
+        match self {
+            ListSize::Fixed(size) => *size,
+            ListSize::Variable { name, otherwise } => match variables.values.get(name) {
+                Some(SqlValue::Int(size)) => {
+                    size.value.map_or(*otherwise, |size| size.max(0) as u32)
+                }
+                _ => *otherwise,
+            },
+        }
+    }
+}
+
+/// Plans what every operation of `doc` would cost before any of its SQL is generated.
+///
+/// Each join costs `DEFAULT_JOIN_COST` and each column nothing, unless their metadata sets a
+/// `cost`. Everything below a list (`many`) relation is multiplied by the list's size, taken
+/// from a `limit`/`first` argument, the node's `limit` metadata, or `DEFAULT_LIST_SIZE`. A
+/// mutation costs what its selection of the written row does.
+pub fn plan_cost<'a>(doc: &Document<'a, &'a str>, root: &Root) -> Result<CostPlan, Error> {
+    let mut plan = CostPlan::default();
+    for definition in &doc.definitions {
+        let (selection_set, variable_definitions, is_mutation) = match definition {
+            Definition::Operation(OperationDefinition::SelectionSet(selection_set)) => {
+                (selection_set, &[][..], false)
+            }
+            Definition::Operation(OperationDefinition::Query(query)) => {
+                (&query.selection_set, &query.variable_definitions[..], false)
+            }
+            Definition::Operation(OperationDefinition::Mutation(mutation)) => (
+                &mutation.selection_set,
+                &mutation.variable_definitions[..],
+                true,
+            ),
+            Definition::Operation(OperationDefinition::Subscription(subscription)) => (
+                &subscription.selection_set,
+                &subscription.variable_definitions[..],
+                false,
+            ),
+            Definition::Fragment(_) => continue,
+        };
+        let defaults = variable_definitions
+            .iter()
+            .filter_map(|definition| match &definition.default_value {
+                Some(Value::Int(size)) => Some((definition.name, int_size(size))),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+        let planner = CostPlanner { root, defaults };
+        for selection in &selection_set.items {
+            let Selection::Field(root_field) = selection else {
+                continue;
+            };
+            let (node, many) = if is_mutation {
+                match find_mutation(root, root_field.name) {
+                    Some((_, node)) => (node, false),
+                    None => continue,
+                }
+            } else {
+                match root
+                    .0
+                    .values()
+                    .find(|node| node.field_name == root_field.name)
+                {
+                    Some(node) => (node, node.many),
+                    None => continue,
+                }
+            };
+            let lists = if many {
+                vec![planner.list_size(node, &root_field.arguments)]
+            } else {
+                vec![]
+            };
+            let path = response_key(root_field).to_string();
+            planner.plan_selection(
+                node,
+                &root_field.selection_set,
+                &path,
+                &lists,
+                &mut plan.paths,
+            )?;
+        }
+    }
+    Ok(plan)
+}
+
+impl CostPlan {
+    /// The cost of the query given `variables`.
+    pub fn estimate(&self, variables: &InputVariables) -> QueryCost {
+        let mut paths = self
+            .paths
+            .iter()
+            .map(|planned| CostPath {
+                path: planned.path.clone(),
+                cost: planned
+                    .levels
+                    .iter()
+                    .map(|lists| {
+                        lists
+                            .iter()
+                            .map(|list| list.size(variables))
+                            .fold(planned.cost, u32::saturating_mul)
+                    })
+                    .fold(0, u32::saturating_add),
+            })
+            .collect::<Vec<_>>();
+        let total = paths
+            .iter()
+            .map(|path| path.cost)
+            .fold(0, u32::saturating_add);
+        paths.sort_by(|a, b| b.cost.cmp(&a.cost).then_with(|| a.path.cmp(&b.path)));
+        QueryCost { total, paths }
+    }
+
+    /// Fails with the most expensive paths when the query costs more than `max_cost` given
+    /// `variables`.
+    pub fn check(&self, variables: &InputVariables, max_cost: u32) -> Result<(), Error> {
+        let cost = self.estimate(variables);
+        if cost.total > max_cost {
+            return Err(Error::MaxCostExceeded {
+                cost: cost.total,
+                max_cost,
+                paths: cost.paths.into_iter().take(REPORTED_PATHS).collect(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Estimates what the SQL for `doc` would cost given `variables`; see `plan_cost`.
+pub fn estimate_cost<'a>(
+    doc: &Document<'a, &'a str>,
+    root: &Root,
+    variables: &InputVariables,
+) -> Result<QueryCost, Error> {
+    Ok(plan_cost(doc, root)?.estimate(variables))
+}
+
+/// Fails with the most expensive paths when `doc` costs more than `max_cost`.
+pub fn check_cost<'a>(
+    doc: &Document<'a, &'a str>,
+    root: &Root,
+    variables: &InputVariables,
+    max_cost: u32,
+) -> Result<(), Error> {
+    plan_cost(doc, root)?.check(variables, max_cost)
+}
+
+struct CostPlanner<'r, 'a> {
+    root: &'r Root,
+    /// The Int defaults of the operation's variables
+    defaults: HashMap<&'a str, u32>,
+}
+
+impl<'a> CostPlanner<'_, 'a> {
+    fn plan_selection(
+        &self,
+        node: &Node,
+        selection_set: &SelectionSet<'a, &'a str>,
+        path: &str,
+        lists: &[ListSize],
+        paths: &mut Vec<PlannedCost>,
+    ) -> Result<(), Error> {
+        let root = self.root;
+        for selection in &selection_set.items {
+            let subfield = match selection {
+                Selection::Field(subfield) => subfield,
+                // Fragments on a union member are costed against the member
+                Selection::InlineFragment(fragment) => {
+                    let member = match (&node.polymorphic, &fragment.type_condition) {
+                        (Some(Polymorphic::Union(union)), Some(TypeCondition::On(type_name))) => {
+                            union
+                                .members
+                                .iter()
+                                .filter_map(|member| root.0.get(&member.extends))
+                                .find(|member| member.type_name() == *type_name)
+                        }
+                        _ => None,
+                    };
+                    self.plan_selection(
+                        member.unwrap_or(node),
+                        &fragment.selection_set,
+                        path,
+                        lists,
+                        paths,
+                    )?;
+                    continue;
+                }
+                Selection::FragmentSpread(_) => continue,
+            };
+            let subpath = format!("{}.{}", path, response_key(subfield));
+            match node.fields.get(subfield.name) {
+                Some(Field::Column(column)) => {
+                    if let Some(cost) = column.cost {
+                        paths.push(PlannedCost {
+                            path: subpath,
+                            cost,
+                            levels: vec![lists.to_vec()],
+                        });
+                    }
+                }
+                Some(Field::Join(join_info)) => {
+                    let child = root.0.get(&join_info.extends.extends).ok_or(format!(
+                        "Unable to resolve node \"{}\": it extends \"{}\" but no node has that alias",
+                        join_info.extends.alias, join_info.extends.extends
+                    ))?;
+                    paths.push(PlannedCost {
+                        path: subpath.clone(),
+                        cost: join_info.cost.unwrap_or(DEFAULT_JOIN_COST),
+                        levels: vec![lists.to_vec()],
+                    });
+                    let mut lists = lists.to_vec();
+                    if join_info.many {
+                        lists.push(self.list_size(child, &subfield.arguments));
+                    }
+                    self.plan_selection(child, &subfield.selection_set, &subpath, &lists, paths)?;
+                }
+                // Each level of a recursive field is costed like a list of its own node
+                Some(Field::Recursive(recursive)) => {
+                    let size = self.list_size(node, &subfield.arguments);
+                    let mut levels = vec![];
+                    let mut lists = lists.to_vec();
+                    for _ in 0..recursive.max_depth.unwrap_or(1).max(1) {
+                        levels.push(lists.clone());
+                        lists.push(size.clone());
+                    }
+                    paths.push(PlannedCost {
+                        path: subpath.clone(),
+                        cost: DEFAULT_JOIN_COST,
+                        levels,
+                    });
+                    self.plan_selection(node, &subfield.selection_set, &subpath, &lists, paths)?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// The size of a list of `node` selected with `arguments`, which like the SQL's limit falls
+    /// back to the node's `limit` when a variable limit isn't given.
+    fn list_size(&self, node: &Node, arguments: &[(&'a str, Value<'a, &'a str>)]) -> ListSize {
+        let metadata = node
+            .fields
+            .values()
+            .find_map(|field| match field {
+                Field::Limit(limit) => Some(limit.value),
+                _ => None,
+            })
+            .unwrap_or(DEFAULT_LIST_SIZE);
+        let argument = arguments
+            .iter()
+            .find(|(name, _)| LIMIT_ARGUMENTS.contains(name));
+        match argument {
+            Some((_, Value::Int(size))) => ListSize::Fixed(int_size(size)),
+            Some((_, Value::Variable(name))) => ListSize::Variable {
+                name: name.to_string(),
+                otherwise: self.defaults.get(name).copied().unwrap_or(metadata),
+            },
+            _ => ListSize::Fixed(metadata),
+        }
+    }
+}
+
+fn int_size(size: &Number) -> u32 {
+    size.as_i64()
+        .map_or(0, |size| size.clamp(0, u32::MAX as i64) as u32)
+}
+
+fn response_key<'a>(field: &graphql_parser::query::Field<'a, &'a str>) -> &'a str {
+    field.alias.unwrap_or(field.name)
+}
@@ -0,0 +1,90 @@
+use std::fmt;
+
+use serde::Serialize;
+#[cfg(feature = "wasm")]
+use tsify::Tsify;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::core::{cost::CostPath, validate::SchemaIssue};
+
+/// What went wrong, thrown to JavaScript as an object tagged by `kind`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(into_wasm_abi))]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Error {
+    /// The query or metadata could not be turned into SQL
+    Message { message: String },
+    /// The query nests joins deeper than `Options.max_depth`
+    MaxDepthExceeded {
+        max_depth: u32,
+        /// The GraphQL field path that went too deep
+        path: Vec<String>,
+    },
+    /// The query's estimated cost is above `Options.max_cost`
+    MaxCostExceeded {
+        cost: u32,
+        max_cost: u32,
+        /// The most expensive fields, most expensive first
+        paths: Vec<CostPath>,
+    },
+    /// An allow-listed registry was asked for a query it does not have
+    UnregisteredQuery { hash: String },
+    /// Metadata failed `validate_schema`
+    InvalidSchema { issues: Vec<SchemaIssue> },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Message { message } => write!(f, "{}", message),
+            Error::MaxDepthExceeded { max_depth, path } => write!(
+                f,
+                "the query exceeds the maximum join depth of {} at {}",
+                max_depth,
+                path.join(".")
+            ),
+            Error::MaxCostExceeded {
+                cost,
+                max_cost,
+                paths,
+            } => write!(
+                f,
+                "the query costs {} which is more than the maximum of {} (most expensive: {})",
+                cost,
+                max_cost,
+                paths
+                    .iter()
+                    .map(|path| format!("{} = {}", path.path, path.cost))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Error::UnregisteredQuery { hash } => {
+                write!(f, "no persisted query is registered under {}", hash)
+            }
+            Error::InvalidSchema { issues } => write!(
+                f,
+                "the schema is invalid: {}",
+                issues
+                    .iter()
+                    .map(|issue| issue.to_string())
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::Message { message }
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
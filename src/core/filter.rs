@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+
+use graphql_parser::query::Value;
+
+use crate::core::{
+    builder::{col, exists, raw},
+    error::Error,
+    schema::{Field, JoinInfo, Node, Root},
+    shared_schema::{CHILD, ContainsExpr, PARENT, SqlExpr, SqlParam, SqlValue},
+    variables::InputVariables,
+};
+
+/// The argument that takes the filter of a `filterable` node.
+pub const WHERE_ARGUMENT: &str = "where";
+
+/// Compiles the `where` argument of a field selecting `node` under `alias`.
+///
+/// The argument is an input object whose keys are the node's columns, each taking the
+/// operators `eq`, `neq`, `gt`, `lt`, `in`, `contains` and `isNull`, its joins, each taking the
+/// joined node's own filter and compiled to an `EXISTS` subquery, and `AND`, `OR` and `NOT`.
+/// Values are bound as params like any other argument, so only the structure of the filter
+/// shapes the SQL. A variable filter that isn't given matches every row.
+pub fn where_filter<'a>(
+    root: &Root,
+    variables: &InputVariables,
+    node: &Node,
+    alias: &str,
+    value: &Value<'a, &'a str>,
+) -> Result<Option<SqlExpr>, Error> {
+    let path = format!("{}_{}", alias, WHERE_ARGUMENT);
+    let filtering = Filtering { root, variables };
+    Ok(variables
+        .expand(value, |value| {
+            node_where(filtering, node, alias, value, &path)
+        })?
+        .flatten())
+}
+
+/// A param bound to an argument value: a variable binds the param of the same name, and a
+/// literal binds a param named `name`.
+pub fn argument_param<'a>(value: &Value<'a, &'a str>, name: &str) -> Result<SqlExpr, Error> {
+    Ok(SqlExpr::Param(match value {
+        Value::Variable(variable) => SqlParam {
+            name: variable.to_string(),
+            value: SqlValue::Text(None.into()),
+        },
+        value => SqlParam {
+            name: name.to_string(),
+            value: SqlValue::try_from(value)?,
+        },
+    }))
+}
+
+/// What compiling a filter reads besides the filter itself.
+#[derive(Clone, Copy)]
+struct Filtering<'r> {
+    root: &'r Root,
+    variables: &'r InputVariables,
+}
+
+/// The filter of one input object, or `None` when it matches every row.
+fn node_where<'a>(
+    filtering: Filtering,
+    node: &Node,
+    alias: &str,
+    value: &Value<'a, &'a str>,
+    path: &str,
+) -> Result<Option<SqlExpr>, Error> {
+    let Value::Object(fields) = value else {
+        return Err(Error::from(format!(
+            "{} must be an input object, got {}",
+            path, value
+        )));
+    };
+    let mut filters = vec![];
+    for (name, value) in fields {
+        let path = format!("{}_{}", path, name);
+        let filter = match (*name, node.fields.get(*name)) {
+            ("AND", _) => all(list(value, &path)?
+                .map(|(path, item)| node_where(filtering, node, alias, item, &path))
+                .collect::<Result<Vec<_>, _>>()?),
+            ("OR", _) => any(list(value, &path)?
+                .map(|(path, item)| node_where(filtering, node, alias, item, &path))
+                .collect::<Result<Vec<_>, _>>()?),
+            ("NOT", _) => Some(match node_where(filtering, node, alias, value, &path)? {
+                Some(filter) => !filter,
+                None => never(),
+            }),
+            (_, Some(Field::Column(column))) => column_where(
+                filtering.variables,
+                col(alias, column.column.clone()),
+                value,
+                &path,
+            )?,
+            (_, Some(Field::Join(join_info))) => Some(join_where(
+                filtering, node, alias, name, join_info, value, &path,
+            )?),
+            _ => {
+                return Err(Error::from(format!(
+                    "{} has no column or join {} to filter on",
+                    node.type_name(),
+                    name
+                )));
+            }
+        };
+        filters.extend(filter);
+    }
+    Ok(all(filters.into_iter().map(Some).collect()))
+}
+
+/// The filter of one column's operators. An operator given a variable that isn't given is
+/// left out, and so is an item of `in`.
+fn column_where<'a>(
+    variables: &InputVariables,
+    column: SqlExpr,
+    value: &Value<'a, &'a str>,
+    path: &str,
+) -> Result<Option<SqlExpr>, Error> {
+    let Value::Object(operators) = value else {
+        return Err(Error::from(format!(
+            "{} must be an input object of operators, got {}",
+            path, value
+        )));
+    };
+    let mut filters = vec![];
+    for (operator, value) in operators {
+        if variables.omits(value) {
+            continue;
+        }
+        let path = format!("{}_{}", path, operator);
+        let param = || argument_param(value, &path);
+        let column = column.clone();
+        filters.push(match (*operator, value) {
+            ("eq", Value::Null) => column.is_null(),
+            ("neq", Value::Null) => column.is_not_null(),
+            ("eq", _) => column.eq(param()?),
+            ("neq", _) => column.neq(param()?),
+            ("gt", _) => column.gt(param()?),
+            ("lt", _) => column.lt(param()?),
+            ("in", _) => {
+                let values = list(value, &path)?
+                    .filter(|(_, item)| !variables.omits(item))
+                    .map(|(path, item)| argument_param(item, &path))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if values.is_empty() {
+                    never()
+                } else {
+                    column.is_in(values)
+                }
+            }
+            ("contains", _) => SqlExpr::Contains(ContainsExpr {
+                left: Box::new(column),
+                right: Box::new(param()?),
+            }),
+            ("isNull", Value::Boolean(true)) => column.is_null(),
+            ("isNull", Value::Boolean(false)) => column.is_not_null(),
+            ("isNull", _) => {
+                return Err(Error::from(format!(
+                    "{} must be true or false, got {}",
+                    path, value
+                )));
+            }
+            _ => {
+                return Err(Error::from(format!(
+                    "Unknown filter operator {}, expected eq, neq, gt, lt, in, contains or isNull",
+                    operator
+                )));
+            }
+        });
+    }
+    Ok(all(filters.into_iter().map(Some).collect()))
+}
+
+/// Whether any row joined through `join_info` matches the joined node's filter.
+fn join_where<'a>(
+    filtering: Filtering,
+    node: &Node,
+    alias: &str,
+    name: &str,
+    join_info: &JoinInfo,
+    value: &Value<'a, &'a str>,
+    path: &str,
+) -> Result<SqlExpr, Error> {
+    let target = filtering
+        .root
+        .0
+        .get(&join_info.extends.extends)
+        .ok_or(format!(
+            "Unable to filter on {}: it joins \"{}\" but no node has that alias",
+            name, join_info.extends.extends
+        ))?;
+    if !target.filterable {
+        return Err(Error::from(format!(
+            "Unable to filter on {}: {} is not filterable",
+            name,
+            target.type_name()
+        )));
+    }
+    let subquery_alias = format!("{}_{}", alias, name);
+
+    let mut condition = join_info.join.on.clone();
+    let renames = [
+        (node.alias.as_str(), alias),
+        (PARENT, alias),
+        (join_info.extends.alias.as_str(), &subquery_alias),
+        (CHILD, &subquery_alias),
+    ]
+    .into_iter()
+    .filter(|(declared, claimed)| declared != claimed)
+    .map(|(declared, claimed)| (declared.to_string(), claimed.to_string()))
+    .collect::<HashMap<_, _>>();
+    condition.rename_tables(&renames);
+
+    // The joined node's own filters are written against its alias or the join's
+    let renames = [
+        (target.alias.as_str(), &subquery_alias),
+        (join_info.extends.alias.as_str(), &subquery_alias),
+    ]
+    .into_iter()
+    .filter(|(declared, claimed)| declared != claimed)
+    .map(|(declared, claimed)| (declared.to_string(), claimed.to_string()))
+    .collect::<HashMap<_, _>>();
+    for mut filter in target.metadata_filters() {
+        filter.resolve_columns(&subquery_alias);
+        filter.rename_tables(&renames);
+        condition = condition.and(filter);
+    }
+    if let Some(filter) = node_where(filtering, target, &subquery_alias, value, path)? {
+        condition = condition.and(filter);
+    }
+    Ok(exists(&target.table, subquery_alias, condition))
+}
+
+/// The items of a list argument, with the path of each.
+fn list<'v, 'a>(
+    value: &'v Value<'a, &'a str>,
+    path: &str,
+) -> Result<impl Iterator<Item = (String, &'v Value<'a, &'a str>)>, Error> {
+    let Value::List(items) = value else {
+        return Err(Error::from(format!(
+            "{} must be a list, got {}",
+            path, value
+        )));
+    };
+    let path = path.to_string();
+    Ok(items
+        .iter()
+        .enumerate()
+        .map(move |(i, item)| (format!("{}_{}", path, i), item)))
+}
+
+/// ANDs filters, where `None` matches every row.
+fn all(filters: Vec<Option<SqlExpr>>) -> Option<SqlExpr> {
+    filters
+        .into_iter()
+        .flatten()
+        .reduce(|left, right| left.and(right))
+}
+
+/// ORs filters, where `None` matches every row.
+fn any(filters: Vec<Option<SqlExpr>>) -> Option<SqlExpr> {
+    filters
+        .into_iter()
+        .try_fold(None, |any: Option<SqlExpr>, filter| {
+            let filter = filter?;
+            Some(Some(match any {
+                Some(any) => any.or(filter),
+                None => filter,
+            }))
+        })
+        .map(|any| any.unwrap_or_else(never))
+}
+
+fn never() -> SqlExpr {
+    raw("1 = 0")
+}
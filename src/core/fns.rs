@@ -1,175 +1,1140 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
 use graphql_parser::{
     parse_query,
-    query::{Definition, Document, OperationDefinition, Selection},
+    query::{
+        Definition, Document, OperationDefinition, Selection, SelectionSet, TypeCondition, Value,
+    },
 };
-use sea_query::{GenericBuilder, SelectStatement};
+use sea_query::{Alias, Expr, GenericBuilder, Query, ReturningClause, Values};
 
 use crate::core::{
-    schema::{AnyNode, BuilderType, ExtendsNode, Field, Node, Options, OrderDirection, Root},
-    shared_schema::SqlExpr,
-    sql_schema::{SqlColumn, SqlJoin, SqlOrderDirection, SqlSelect},
+    builder::{col, param, raw},
+    cost::{LIMIT_ARGUMENTS, check_cost},
+    error::Error,
+    filter::{WHERE_ARGUMENT, argument_param, where_filter},
+    hydrate::{ResponseShape, TreeShape, TypeShape, TypeSource},
+    mutation::{MutationKind, find_mutation, mutation_write},
+    schema::{
+        BuilderType, ExtendsNode, Field, Node, Options, OrderDirection, Polymorphic, Root,
+        Variables,
+    },
+    shared_schema::{CHILD, ColumnRef, Join, JoinType, PARENT, SqlExpr, SqlValue},
+    sort::{ORDER_BY_ARGUMENT, order_by_argument},
+    sql_schema::{
+        SqlColumn, SqlJoin, SqlLimit, SqlLimitParent, SqlMutation, SqlMutationKind, SqlOrderBy,
+        SqlOrderDirection, SqlQuery, SqlRecursiveCte, SqlSelect, aliased_columns, param_positions,
+    },
+    variables::InputVariables,
 };
 
-fn resolve_node<'a>(any_node: &'a AnyNode, root: &'a Root) -> Result<&'a Node, String> {
-    match any_node {
-        AnyNode::AliasNode(node) => {
-            let node = root
-                .0
-                .get(&node.extends)
-                .ok_or(format!("Unable to resolve node"))?;
-            Ok(node)
+/// The meta-field naming the GraphQL type of an object.
+pub const TYPENAME: &str = "__typename";
+
+fn resolve_node<'a>(extends: &ExtendsNode, root: &'a Root) -> Result<&'a Node, String> {
+    root.0.get(&extends.extends).ok_or(format!(
+        "Unable to resolve node \"{}\": it extends \"{}\" but no node has that alias",
+        extends.alias, extends.extends
+    ))
+}
+
+/// State threaded through one `build_sql_ast` traversal.
+struct BuildContext<'a> {
+    root: &'a Root,
+    max_depth: Option<u32>,
+    /// Whether every node is sorted by its default sorts and unique key
+    ordered: bool,
+    /// The GraphQL field path of the node being built
+    path: Vec<String>,
+    /// Every SQL alias handed out so far in this query
+    aliases: HashSet<String>,
+    variables: &'a InputVariables,
+    /// Whether a mutation's write is compiled, which its response shape doesn't need
+    writes: bool,
+    /// The to-one joins selected so far, keyed by the alias and field they join from, which
+    /// an `orderBy` argument sorts through rather than joining them again
+    to_one_joins: HashMap<(String, String), SqlJoin>,
+}
+
+impl<'a> BuildContext<'a> {
+    fn new(root: &'a Root, options: Option<&Options>, variables: &'a InputVariables) -> Self {
+        BuildContext {
+            root,
+            max_depth: options.and_then(|options| options.max_depth),
+            ordered: !options.is_some_and(|options| options.unordered),
+            path: vec![],
+            aliases: HashSet::new(),
+            variables,
+            writes: true,
+            to_one_joins: HashMap::new(),
         }
-        AnyNode::Node(node) => Ok(node),
     }
-}
 
-fn resolve_extends_node(any_node: &AnyNode) -> Option<&ExtendsNode> {
-    match any_node {
-        AnyNode::AliasNode(node) => Some(&node),
-        AnyNode::Node(_) => None,
+    /// Steps into the relation `field`, failing when that goes deeper than `max_depth`.
+    fn enter(&mut self, field: &str) -> Result<(), Error> {
+        self.path.push(field.to_string());
+        if let Some(max_depth) = self.max_depth
+            && self.path.len() > max_depth as usize + 1
+        {
+            return Err(Error::MaxDepthExceeded {
+                max_depth,
+                path: self.path.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Reserves `alias` for a node, or a fresh `alias_N` when a recursive relation already
+    /// joined it at a shallower level.
+    fn claim_alias(&mut self, alias: &str) -> String {
+        let mut claimed = alias.to_string();
+        let mut n = 1;
+        while self.aliases.contains(&claimed) {
+            n += 1;
+            claimed = format!("{}_{}", alias, n);
+        }
+        self.aliases.insert(claimed.clone());
+        claimed
     }
 }
 
-pub fn parse_gql(resolve_info: &str) -> Result<Document<&str>, String> {
+pub fn parse_gql(resolve_info: &str) -> Result<Document<'_, &str>, String> {
     parse_query(resolve_info).map_err(|e| e.to_string())
 }
 
+/// Builds the SQL for a query or mutation, separating the statements of a mutation that
+/// takes more than one with `;`.
 pub fn build_sql_query(
     query: &str,
     metadata: Root,
     options: Option<Options>,
-) -> Result<String, String> {
+) -> Result<String, Error> {
+    build_sql_query_with_params(query, &metadata, options.as_ref()).map(|query| {
+        std::iter::once(query.sql)
+            .chain(query.then.into_iter().map(|then| then.sql))
+            .collect::<Vec<_>>()
+            .join(";\n")
+    })
+}
+
+/// Like `build_sql_query`, but keeps the values bound to the statement's placeholders.
+pub fn build_sql_query_with_params(
+    query: &str,
+    metadata: &Root,
+    options: Option<&Options>,
+) -> Result<SqlQuery, Error> {
     let doc = parse_gql(query)?;
+    let find_root = |field_name: &str| {
+        metadata
+            .0
+            .values()
+            .find(|node| node.field_name == field_name)
+    };
+    build_sql_document(&doc, metadata, &find_root, None, options)
+}
 
-    if let Some(selection) = doc.definitions.first() {
-        if let Definition::Operation(op) = selection {
-            if let OperationDefinition::SelectionSet(selection_set) = op {
-                if let Some(Selection::Field(root_field)) = selection_set.items.first() {
-                    let node = metadata
-                        .0
-                        .values()
-                        .find(|node| node.field_name == root_field.name)
-                        .ok_or(format!(
-                            "no such field with field_name = {} in nodes",
-                            root_field.name
-                        ))?;
-                    let sql_ast = build_sql_ast(
-                        // TODO: can I avoid this clone?
-                        &AnyNode::Node(node.clone()),
-                        root_field,
-                        &metadata,
-                    )?;
-                    let sql = match options.map(|x| x.builder) {
-                        Some(BuilderType::Postgres) => {
-                            render_sql(&sql_ast, sea_query::PostgresQueryBuilder)
-                        }
-                        Some(BuilderType::MySql) => {
-                            render_sql(&sql_ast, sea_query::MysqlQueryBuilder)
-                        }
-                        Some(BuilderType::Sqlite) => {
-                            render_sql(&sql_ast, sea_query::SqliteQueryBuilder)
-                        }
-                        None => render_sql(&sql_ast, sea_query::PostgresQueryBuilder),
-                    };
-                    return Ok(sql);
-                }
+/// Builds the SQL for the first root field of an already parsed query.
+pub fn build_sql_document<'a, 'r>(
+    doc: &Document<'a, &'a str>,
+    metadata: &'r Root,
+    find_root: &dyn Fn(&str) -> Option<&'r Node>,
+    variables: Option<&Variables>,
+    options: Option<&Options>,
+) -> Result<SqlQuery, Error> {
+    let sql_ast = plan_sql_document(doc, metadata, find_root, variables, options)?;
+    render_sql_query(&sql_ast, options)
+}
+
+/// A query's SQL AST and the shape its rows hydrate into.
+#[derive(Clone, Debug)]
+pub struct QueryPlan {
+    pub sql_ast: SqlSelect,
+    pub shape: ResponseShape,
+}
+
+/// Builds the SQL AST for the first root field of an already parsed query.
+pub fn plan_sql_document<'a, 'r>(
+    doc: &Document<'a, &'a str>,
+    metadata: &'r Root,
+    find_root: &dyn Fn(&str) -> Option<&'r Node>,
+    variables: Option<&Variables>,
+    options: Option<&Options>,
+) -> Result<SqlSelect, Error> {
+    plan_query_document(doc, metadata, find_root, variables, options).map(|plan| plan.sql_ast)
+}
+
+/// Plans the first root field of an already parsed query.
+///
+/// `find_root` picks the node a root query field selects, and `variables` override the values
+/// of the query's variable defaults and of metadata `SqlExpr::Param`s with the same name.
+/// Object and list variables are expanded into the arguments they are given for, and what a
+/// variable that isn't given, and has no default, is the value of is left out; see
+/// `InputVariables`.
+pub fn plan_query_document<'a, 'r>(
+    doc: &Document<'a, &'a str>,
+    metadata: &'r Root,
+    find_root: &dyn Fn(&str) -> Option<&'r Node>,
+    variables: Option<&Variables>,
+    options: Option<&Options>,
+) -> Result<QueryPlan, Error> {
+    let variables = InputVariables::new(variables)?;
+    plan_query_inputs(doc, metadata, find_root, variables, options)
+}
+
+/// Like `plan_query_document`, with the variables already read.
+pub fn plan_query_inputs<'a, 'r>(
+    doc: &Document<'a, &'a str>,
+    metadata: &'r Root,
+    find_root: &dyn Fn(&str) -> Option<&'r Node>,
+    variables: InputVariables,
+    options: Option<&Options>,
+) -> Result<QueryPlan, Error> {
+    if let Some(max_cost) = options.and_then(|options| options.max_cost) {
+        check_cost(doc, metadata, &variables, max_cost)?;
+    }
+    plan_operation(doc, metadata, find_root, variables, options, true)
+}
+
+/// The shape the rows of the first root field of an already parsed query hydrate into,
+/// which no variable changes.
+pub fn plan_response_shape<'a, 'r>(
+    doc: &Document<'a, &'a str>,
+    metadata: &'r Root,
+    find_root: &dyn Fn(&str) -> Option<&'r Node>,
+    options: Option<&Options>,
+) -> Result<ResponseShape, Error> {
+    let variables = InputVariables::default();
+    plan_operation(doc, metadata, find_root, variables, options, false).map(|plan| plan.shape)
+}
+
+fn plan_operation<'a, 'r>(
+    doc: &Document<'a, &'a str>,
+    metadata: &'r Root,
+    find_root: &dyn Fn(&str) -> Option<&'r Node>,
+    mut variables: InputVariables,
+    options: Option<&Options>,
+    writes: bool,
+) -> Result<QueryPlan, Error> {
+    let operation = match doc.definitions.first() {
+        Some(Definition::Operation(OperationDefinition::SelectionSet(selection_set))) => {
+            Some((selection_set, &[][..], false))
+        }
+        Some(Definition::Operation(OperationDefinition::Query(query))) => {
+            Some((&query.selection_set, &query.variable_definitions[..], false))
+        }
+        Some(Definition::Operation(OperationDefinition::Mutation(mutation))) => Some((
+            &mutation.selection_set,
+            &mutation.variable_definitions[..],
+            true,
+        )),
+        _ => None,
+    };
+
+    if let Some((selection_set, variable_definitions, is_mutation)) = operation
+        && let Some(Selection::Field(root_field)) = selection_set.items.first()
+    {
+        if is_mutation && selection_set.items.len() > 1 {
+            return Err(Error::from(format!(
+                "A mutation can only have one root field, but it has {}",
+                selection_set.items.len()
+            )));
+        }
+        for definition in variable_definitions {
+            if definition.default_value.is_some() {
+                variables.give(definition.name);
+            }
+        }
+        let mut ctx = BuildContext::new(metadata, options, &variables);
+        ctx.writes = writes;
+        ctx.path.push(root_field.name.to_string());
+        let (mut sql_ast, shape) = if is_mutation {
+            plan_mutation(root_field, &mut ctx, options)?
+        } else {
+            let node = find_root(root_field.name).ok_or(format!(
+                "no such field with field_name = {} in nodes",
+                root_field.name
+            ))?;
+            let (mut sql_ast, mut shape) = build_sql_ast(node, &node.alias, root_field, &mut ctx)?;
+            shape.many = node.many;
+            match &mut sql_ast.limit {
+                // The rows are filtered before they are numbered
+                Some(limit) if node.many => limit.where_clause = sql_ast.where_clause.take(),
+                _ => sql_ast.limit = None,
+            }
+            (sql_ast, shape)
+        };
+
+        let mut values = HashMap::new();
+        for definition in variable_definitions {
+            if let Some(default_value) = &definition.default_value {
+                values.insert(
+                    definition.name.to_string(),
+                    SqlValue::try_from(default_value)?,
+                );
             }
         }
+        values.extend(variables.values.clone());
+        if !values.is_empty() {
+            sql_ast.bind_params(&values);
+        }
+        return Ok(QueryPlan { sql_ast, shape });
     }
 
-    Err(String::from(
+    Err(Error::from(
         "Invalid query structure must have a query definition in query",
     ))
 }
 
-fn render_sql<T>(select: &SqlSelect, builder: T) -> String
+/// Plans a root mutation field: the write it makes and the select that reads back the row
+/// it wrote.
+///
+/// Postgres selects the rows from the write as a CTE returning them. MySQL and SQLite read the
+/// row back with a `SELECT` after the write, or before a delete, finding an inserted row by
+/// its key in the input or else by `LAST_INSERT_ID()` on MySQL, and on SQLite by the key the
+/// insert returns, which needn't be the rowid.
+fn plan_mutation<'a>(
+    field: &graphql_parser::query::Field<'a, &'a str>,
+    ctx: &mut BuildContext,
+    options: Option<&Options>,
+) -> Result<(SqlSelect, ResponseShape), Error> {
+    let (kind, node) = find_mutation(ctx.root, field.name).ok_or(format!(
+        "no mutable node has a mutation named {}",
+        field.name
+    ))?;
+    let (mut sql_ast, shape) = build_sql_ast(node, &node.alias, field, ctx)?;
+    sql_ast.limit = None;
+    if !ctx.writes {
+        return Ok((sql_ast, shape));
+    }
+    let mut mutation = mutation_write(ctx.root, ctx.variables, node, kind, &sql_ast.alias, field)?;
+
+    let builder_type = options.map(|x| x.builder.clone()).unwrap_or_default();
+    if !matches!(builder_type, BuilderType::Postgres)
+        && matches!(kind, MutationKind::Create | MutationKind::Upsert)
+        && let Some(key) = &node.unique_key
+    {
+        let input = mutation.values.iter().find(|(column, _)| column == key);
+        let inserted = match (input, &builder_type) {
+            (Some((_, value)), _) => value.clone(),
+            (None, BuilderType::Sqlite) => {
+                mutation.returning = Some(key.clone());
+                param(
+                    format!("{}_{}", sql_ast.alias, key),
+                    SqlValue::Text(None.into()),
+                )
+            }
+            (None, _) => raw("LAST_INSERT_ID()"),
+        };
+        let filter = col(&sql_ast.alias, key.clone()).eq(inserted);
+        sql_ast.where_clause = Some(match sql_ast.where_clause.take() {
+            Some(where_clause) => where_clause.and(filter),
+            None => filter,
+        });
+    }
+    sql_ast.mutation = Some(mutation);
+    Ok((sql_ast, shape))
+}
+
+/// Renders a SQL AST with the query builder chosen in `options`.
+pub fn render_sql_query(sql_ast: &SqlSelect, options: Option<&Options>) -> Result<SqlQuery, Error> {
+    let builder_type = options.map(|x| x.builder.clone()).unwrap_or_default();
+    match builder_type {
+        BuilderType::Postgres => {
+            render_sql::<sea_query::PostgresQueryBuilder>(sql_ast, &builder_type)
+        }
+        BuilderType::MySql => render_sql::<sea_query::MysqlQueryBuilder>(sql_ast, &builder_type),
+        BuilderType::Sqlite => render_sql::<sea_query::SqliteQueryBuilder>(sql_ast, &builder_type),
+    }
+}
+
+fn render_sql<T>(select: &SqlSelect, builder_type: &BuilderType) -> Result<SqlQuery, Error>
 where
-    T: GenericBuilder,
+    T: GenericBuilder + Default,
 {
-    let select: SelectStatement = select.into();
+    if let Some(mutation) = &select.mutation {
+        let read = SqlSelect {
+            mutation: None,
+            ..select.clone()
+        };
+        let mut statements = match builder_type {
+            // The write is a CTE the select reads its rows from
+            BuilderType::Postgres => {
+                return rendered(select, SqlSelect::mark_params, |select| {
+                    let mutation = select.mutation.as_ref().expect("a mutation");
+                    let read = SqlSelect {
+                        mutation: None,
+                        table: mutation.alias.clone(),
+                        ..select.clone()
+                    };
+                    let mut with_clause = mutation.with_clause(builder_type);
+                    for cte in &read.ctes {
+                        with_clause.recursive(true).cte(cte.to_cte(builder_type));
+                    }
+                    read.to_statement(builder_type)
+                        .with(with_clause)
+                        .build(T::default())
+                });
+            }
+            BuilderType::MySql | BuilderType::Sqlite => {
+                // The read binds the key the insert returns
+                let returned = mutation
+                    .returning
+                    .as_ref()
+                    .map(|key| (key, format!("{}_{}", mutation.alias, key)));
+                let returning = returned.as_ref().map(|(key, name)| {
+                    Query::returning().exprs(aliased_columns(
+                        [(key.as_str(), name.as_str())],
+                        T::default().quote(),
+                    ))
+                });
+                let write = render_write::<T>(mutation, builder_type, returning)?;
+                let mut read = render_sql::<T>(&read, builder_type)?;
+                if let Some((_, name)) = returned {
+                    read.keys.insert(name.clone(), name);
+                }
+                // A deleted row can only be read before it is deleted
+                match mutation.kind {
+                    SqlMutationKind::Delete => vec![read, write],
+                    SqlMutationKind::Insert | SqlMutationKind::Update => vec![write, read],
+                }
+            }
+        };
+        render_nested::<T>(mutation, builder_type, &mut statements)?;
+        let first = statements.remove(0);
+        return Ok(SqlQuery {
+            then: statements,
+            ..first
+        });
+    }
+
     // Final SQL output
-    let (sql, _params) = select.build(builder); // or MySqlQueryBuilder, etc.
-    sql
+    rendered(select, SqlSelect::mark_params, |select| {
+        let statement = select.to_statement(builder_type);
+        match select.with_clause(builder_type) {
+            Some(with_clause) => statement.with(with_clause).build(T::default()),
+            None => statement.build(T::default()),
+        }
+    })
+}
+
+/// The statement `render` builds from `ast`, with the positions each named param is bound at
+/// read off the params of the same statement built from a copy marked by `mark`.
+fn rendered<A: Clone>(
+    ast: &A,
+    mark: fn(&mut A),
+    render: impl Fn(&A) -> (String, Values),
+) -> Result<SqlQuery, Error> {
+    let (sql, params) = render(ast);
+    let params = params
+        .iter()
+        .map(SqlValue::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+    let positions = if params.is_empty() {
+        BTreeMap::new()
+    } else {
+        let mut marked = ast.clone();
+        mark(&mut marked);
+        param_positions(&render(&marked).1)
+    };
+    Ok(SqlQuery {
+        sql,
+        params,
+        positions,
+        then: vec![],
+        keys: BTreeMap::new(),
+    })
+}
+
+fn render_write<T>(
+    mutation: &SqlMutation,
+    builder_type: &BuilderType,
+    returning: Option<ReturningClause>,
+) -> Result<SqlQuery, Error>
+where
+    T: GenericBuilder + Default,
+{
+    // Nested inserts are statements of their own
+    let mutation = SqlMutation {
+        nested: vec![],
+        ..mutation.clone()
+    };
+    rendered(&mutation, SqlMutation::mark_params, |mutation| {
+        mutation.build(builder_type, T::default(), returning.clone())
+    })
+}
+
+/// Appends the statements inserting `parent`'s nested rows, each followed by its own.
+///
+/// A row's foreign key is the parent's input when that sets the key, and otherwise a key
+/// placeholder filled from the key an earlier statement returned: the read of the mutation's
+/// row, or for a nested row `RETURNING` on SQLite and `SELECT LAST_INSERT_ID()` on MySQL.
+fn render_nested<T>(
+    parent: &SqlMutation,
+    builder_type: &BuilderType,
+    statements: &mut Vec<SqlQuery>,
+) -> Result<(), Error>
+where
+    T: GenericBuilder + Default,
+{
+    let input_key = |mutation: &SqlMutation, key: &str| {
+        mutation
+            .values
+            .iter()
+            .find(|(column, _)| column == key)
+            .map(|(_, value)| value.clone())
+    };
+    for nested in &parent.nested {
+        let mut insert = nested.insert.clone();
+        let mut keys = BTreeMap::new();
+        let foreign_key = input_key(parent, &nested.parent_key).unwrap_or_else(|| {
+            let name = format!("{}_{}", insert.alias, nested.foreign_key);
+            keys.insert(
+                name.clone(),
+                format!("{}_{}", parent.alias, nested.parent_key),
+            );
+            param(name, SqlValue::Text(None.into()))
+        });
+        insert
+            .values
+            .push((nested.foreign_key.clone(), foreign_key));
+
+        // The key this row's own nested rows need returned
+        let generated_key = insert
+            .nested
+            .iter()
+            .find(|child| input_key(&insert, &child.parent_key).is_none())
+            .map(|child| child.parent_key.clone());
+        let key_alias = |key: &str| format!("{}_{}", insert.alias, key);
+        let returning = match (builder_type, &generated_key) {
+            (BuilderType::Sqlite, Some(key)) => Some(Query::returning().exprs(aliased_columns(
+                [(key.as_str(), key_alias(key).as_str())],
+                T::default().quote(),
+            ))),
+            _ => None,
+        };
+        let mut statement = render_write::<T>(&insert, builder_type, returning)?;
+        statement.keys = keys;
+        statements.push(statement);
+        if let (BuilderType::MySql, Some(key)) = (builder_type, &generated_key) {
+            let (sql, _) = Query::select()
+                .expr_as(Expr::cust("LAST_INSERT_ID()"), Alias::new(key_alias(key)))
+                .build(T::default());
+            statements.push(SqlQuery {
+                sql,
+                params: vec![],
+                positions: BTreeMap::new(),
+                then: vec![],
+                keys: BTreeMap::new(),
+            });
+        }
+        render_nested::<T>(&insert, builder_type, statements)?;
+    }
+    Ok(())
 }
 
 fn build_sql_ast<'a>(
-    any_node: &AnyNode,
+    parent_node: &Node,
+    // The alias the metadata uses for this node
+    declared_alias: &str,
     field: &graphql_parser::query::Field<'a, &'a str>,
-    root: &Root,
-) -> Result<SqlSelect, String> {
-    let mut columns = vec![];
+    ctx: &mut BuildContext,
+) -> Result<(SqlSelect, ResponseShape), Error> {
+    let mut columns: Vec<SqlColumn> = vec![];
+    let mut shape = ResponseShape {
+        key: field.alias.unwrap_or(field.name).to_string(),
+        many: false,
+        identity: vec![],
+        columns: vec![],
+        children: vec![],
+        type_name: parent_node.type_name(),
+        typename_keys: vec![],
+        type_source: None,
+        type_shapes: vec![],
+        tree: None,
+    };
     let mut joins = vec![];
-    let mut where_clause = None;
-    let mut limit = None;
+    let mut ctes = vec![];
     let mut order_by = vec![];
+    // Children sort within the order of their parents
+    let mut child_order_by = vec![];
 
-    let aliased_node = resolve_extends_node(&any_node);
-    let parent_node = resolve_node(&any_node, &root)?;
-    let alias = match aliased_node {
-        Some(node) => node.alias.clone(),
-        None => parent_node.alias.clone(),
-    };
+    let alias = ctx.claim_alias(declared_alias);
 
-    for sel in &field.selection_set.items {
-        if let Selection::Field(subfield) = sel {
-            if let Some(field_meta) = parent_node.fields.get(subfield.name) {
-                match &field_meta {
-                    Field::Column(column) => {
-                        let table = alias.clone();
-                        columns.push(SqlColumn {
-                            name: column.column.clone(),
-                            table: table.clone(),
-                            alias: format!("{}_{}", table, column.column),
-                        });
+    let possible_types = parent_node.possible_types(ctx.root);
+    let mut selected = vec![];
+    selected_fields(
+        &field.selection_set.items,
+        None,
+        &possible_types,
+        &mut selected,
+    );
+    let mut type_shapes = vec![];
+    // The fields each union member selects, by member type
+    let mut member_selections: HashMap<String, Vec<Selection<'a, &'a str>>> = HashMap::new();
+
+    for (type_condition, subfield) in selected {
+        if subfield.name == TYPENAME {
+            let key = subfield.alias.unwrap_or(subfield.name).to_string();
+            match type_condition {
+                None => shape.typename_keys.push(key),
+                Some(type_name) => type_shape(&mut type_shapes, type_name)
+                    .typename_keys
+                    .push(key),
+            }
+            continue;
+        }
+        let Some(field_meta) = parent_node.fields.get(subfield.name) else {
+            // Union members select the fields the union itself lacks
+            if let Some(Polymorphic::Union(_)) = &parent_node.polymorphic {
+                for type_name in &possible_types {
+                    if type_condition.is_none_or(|condition| condition == type_name) {
+                        member_selections
+                            .entry(type_name.clone())
+                            .or_default()
+                            .push(Selection::Field(subfield.clone()));
                     }
-                    Field::Join(join_info) => {
-                        let join_sql_ast = build_sql_ast(
-                            &AnyNode::AliasNode(join_info.extends.clone()),
-                            subfield,
-                            root,
-                        )?;
-                        joins.push(SqlJoin {
-                            table: join_sql_ast.table,
-                            alias: join_sql_ast.alias,
-                            join: join_info.join.clone(),
+                }
+            }
+            continue;
+        };
+        let (shape_columns, shape_children) = match type_condition {
+            None => (&mut shape.columns, &mut shape.children),
+            Some(type_name) => {
+                let type_shape = type_shape(&mut type_shapes, type_name);
+                (&mut type_shape.columns, &mut type_shape.children)
+            }
+        };
+        match &field_meta {
+            Field::Column(column) => {
+                let column_alias = format!("{}_{}", alias, column.column);
+                shape_columns.push((
+                    subfield.alias.unwrap_or(subfield.name).to_string(),
+                    column_alias.clone(),
+                ));
+                if !columns.iter().any(|column| column.alias == column_alias) {
+                    columns.push(SqlColumn {
+                        name: column.column.clone(),
+                        table: alias.clone(),
+                        alias: column_alias,
+                    });
+                }
+            }
+            Field::Join(join_info) => {
+                ctx.enter(subfield.name)?;
+                let child_node = resolve_node(&join_info.extends, ctx.root)?;
+                let (join_sql_ast, mut child_shape) =
+                    build_sql_ast(child_node, &join_info.extends.alias, subfield, ctx)?;
+                ctx.path.pop();
+                child_shape.many = join_info.many;
+                shape_children.push(child_shape);
+
+                let mut join = join_info.join.clone();
+                // Join conditions are written against the aliases in the metadata
+                let renames = [
+                    (parent_node.alias.as_str(), alias.as_str()),
+                    (declared_alias, alias.as_str()),
+                    (&join_info.extends.alias, &join_sql_ast.alias),
+                    (PARENT, alias.as_str()),
+                    (CHILD, &join_sql_ast.alias),
+                ]
+                .into_iter()
+                .filter(|(declared, claimed)| declared != claimed)
+                .map(|(declared, claimed)| (declared.to_string(), claimed.to_string()))
+                .collect::<HashMap<_, _>>();
+                if !renames.is_empty() {
+                    join.on.rename_tables(&renames);
+                }
+                let limit = match join_sql_ast.limit {
+                    // The joined rows are numbered for each parent row, found by its key
+                    Some(mut limit) if join_info.many => {
+                        let key = parent_node.unique_key.as_ref().ok_or(format!(
+                            "{}.{} is limited, so {} needs a unique_key",
+                            parent_node.type_name(),
+                            subfield.name,
+                            parent_node.type_name()
+                        ))?;
+                        limit.where_clause = join_sql_ast.where_clause;
+                        let on = col(&join_sql_ast.alias, SqlLimit::PARENT_KEY)
+                            .eq(col(&alias, key))
+                            .and(limit.condition(&join_sql_ast.alias));
+                        limit.parent = Some(SqlLimitParent {
+                            table: parent_node.table.clone(),
+                            alias: alias.clone(),
+                            key: key.clone(),
+                            on: std::mem::replace(&mut join.on, on),
                         });
-                        for join in join_sql_ast.joins {
-                            joins.push(join);
-                        }
-                        for column in join_sql_ast.columns {
-                            columns.push(column);
-                        }
+                        Some(limit)
                     }
-                    Field::Where(_where) => {
-                        where_clause = Some(SqlExpr::Raw(_where.clone().into()));
+                    _ => {
+                        // Filtering the joined rows in the WHERE would drop their parents too
+                        if let Some(filter) = join_sql_ast.where_clause {
+                            join.on = join.on.and(filter);
+                        }
+                        None
                     }
-                    Field::Limit(_limit) => limit = Some(_limit.clone()),
-                    Field::OrderBy(_order_by) => {
-                        order_by.push(crate::core::sql_schema::SqlOrderBy {
-                            expr: SqlExpr::Raw(_order_by.expr.column.clone().into()),
-                            direction: match _order_by.direction {
-                                OrderDirection::Asc => SqlOrderDirection::Asc,
-                                OrderDirection::Desc => SqlOrderDirection::Desc,
-                            },
+                };
+                let sql_join = SqlJoin {
+                    table: join_sql_ast.table,
+                    alias: join_sql_ast.alias,
+                    join,
+                    limit,
+                };
+                if !join_info.many {
+                    ctx.to_one_joins
+                        .entry((alias.clone(), subfield.name.to_string()))
+                        .or_insert_with(|| sql_join.clone());
+                }
+                joins.push(sql_join);
+                for join in join_sql_ast.joins {
+                    joins.push(join);
+                }
+                for column in join_sql_ast.columns {
+                    columns.push(column);
+                }
+                child_order_by.extend(join_sql_ast.order_by);
+                ctes.extend(join_sql_ast.ctes);
+            }
+            Field::Recursive(recursive) => {
+                ctx.enter(subfield.name)?;
+                let key = parent_node.unique_key.as_ref().ok_or(format!(
+                    "{}.{} is recursive, so {} needs a unique_key",
+                    parent_node.type_name(),
+                    subfield.name,
+                    parent_node.type_name()
+                ))?;
+                let cte_name = ctx.claim_alias(&format!("{}_{}", alias, subfield.name));
+                // A tree nests the field's own selection at every level, so selecting the
+                // field again inside it adds nothing
+                let tree_field;
+                let child_field = if recursive.tree {
+                    tree_field = graphql_parser::query::Field {
+                        selection_set: SelectionSet {
+                            span: subfield.selection_set.span,
+                            items: subfield
+                                .selection_set
+                                .items
+                                .iter()
+                                .filter(|item| {
+                                    !matches!(item, Selection::Field(nested) if nested.name == subfield.name)
+                                })
+                                .cloned()
+                                .collect(),
+                        },
+                        ..subfield.clone()
+                    };
+                    &tree_field
+                } else {
+                    subfield
+                };
+                let (child_sql_ast, mut child_shape) =
+                    build_sql_ast(parent_node, &parent_node.alias, child_field, ctx)?;
+                ctx.path.pop();
+
+                let mut on = recursive.on.clone();
+                let renames = [parent_node.alias.as_str(), declared_alias]
+                    .into_iter()
+                    .map(|declared| (declared.to_string(), PARENT.to_string()))
+                    .collect::<HashMap<_, _>>();
+                on.rename_tables(&renames);
+                ctes.extend(child_sql_ast.ctes);
+                ctes.push(SqlRecursiveCte {
+                    name: cte_name.clone(),
+                    table: parent_node.table.clone(),
+                    key: key.clone(),
+                    on,
+                    max_depth: recursive.max_depth,
+                });
+
+                joins.push(SqlJoin {
+                    table: cte_name.clone(),
+                    alias: cte_name.clone(),
+                    join: Join {
+                        on: col(&cte_name, SqlRecursiveCte::ROOT_KEY).eq(col(&alias, key)),
+                        kind: JoinType::LeftJoin,
+                    },
+                    limit: None,
+                });
+                let mut child_on =
+                    col(&child_sql_ast.alias, key).eq(col(&cte_name, SqlRecursiveCte::NODE_KEY));
+                if let Some(filter) = child_sql_ast.where_clause {
+                    child_on = child_on.and(filter);
+                }
+                joins.push(SqlJoin {
+                    table: child_sql_ast.table,
+                    alias: child_sql_ast.alias,
+                    join: Join {
+                        on: child_on,
+                        kind: JoinType::LeftJoin,
+                    },
+                    limit: None,
+                });
+                joins.extend(child_sql_ast.joins);
+                columns.extend(child_sql_ast.columns);
+
+                let [depth, parent_key] = [SqlRecursiveCte::DEPTH, SqlRecursiveCte::PARENT_KEY]
+                    .map(|column| {
+                        let column_alias = format!("{}_{}", cte_name, column);
+                        columns.push(SqlColumn {
+                            name: column.to_string(),
+                            table: cte_name.clone(),
+                            alias: column_alias.clone(),
                         });
+                        column_alias
+                    });
+                for item in &child_field.selection_set.items {
+                    if let Selection::Field(nested) = item
+                        && nested.name == recursive.depth_field()
+                    {
+                        child_shape.columns.push((
+                            nested.alias.unwrap_or(nested.name).to_string(),
+                            depth.clone(),
+                        ));
                     }
+                }
+                // Shallower levels come first, as they would walking the tree
+                if ctx.ordered {
+                    child_order_by.push(SqlOrderBy {
+                        expr: col(&cte_name, SqlRecursiveCte::DEPTH),
+                        direction: SqlOrderDirection::Asc,
+                        nulls: None,
+                    });
+                }
+                child_order_by.extend(child_sql_ast.order_by);
+
+                child_shape.many = true;
+                if recursive.tree {
+                    child_shape.tree = Some(TreeShape {
+                        key: child_shape.identity.first().cloned().unwrap_or_default(),
+                        parent_key,
+                        depth,
+                    });
+                }
+                shape_children.push(child_shape);
+            }
+            // Filters, default sorts and limits apply whether or not they are selected
+            Field::Where(_) | Field::OrderBy(_) | Field::Limit(_) => {}
+        };
+    }
+
+    match &parent_node.polymorphic {
+        Some(Polymorphic::SingleTable(single_table)) => {
+            let column_alias = format!("{}_{}", alias, single_table.discriminator);
+            if !columns.iter().any(|column| column.alias == column_alias) {
+                columns.push(SqlColumn {
+                    name: single_table.discriminator.clone(),
+                    table: alias.clone(),
+                    alias: column_alias.clone(),
+                });
+            }
+            shape.type_source = Some(TypeSource::Discriminator {
+                column: column_alias,
+                types: single_table.types.clone(),
+            });
+        }
+        Some(Polymorphic::Union(union)) => {
+            let mut members = vec![];
+            for member in &union.members {
+                let member_node = ctx.root.0.get(&member.extends).ok_or(format!(
+                    "Unable to resolve a member of {}: no node has the alias \"{}\"",
+                    parent_node.type_name(),
+                    member.extends
+                ))?;
+                let type_name = member_node.type_name();
+                // Every member is joined, selected or not, to tell which type each row is
+                let member_field = graphql_parser::query::Field {
+                    position: field.position,
+                    alias: None,
+                    name: field.name,
+                    arguments: vec![],
+                    directives: vec![],
+                    selection_set: SelectionSet {
+                        span: field.selection_set.span,
+                        items: member_selections.remove(&type_name).unwrap_or_default(),
+                    },
                 };
+                let (member_sql_ast, member_shape) =
+                    build_sql_ast(member_node, &member_node.alias, &member_field, ctx)?;
+                let Some(identity) = member_shape.identity.first() else {
+                    return Err(Error::from(format!(
+                        "{}, a member of {}, needs a unique_key to tell which rows are one",
+                        type_name,
+                        parent_node.type_name()
+                    )));
+                };
+                members.push((type_name.clone(), identity.clone()));
+                let type_shape = type_shape(&mut type_shapes, &type_name);
+                type_shape.columns.extend(member_shape.columns);
+                type_shape.children.extend(member_shape.children);
+
+                let mut on = member.on.clone();
+                let renames = [
+                    (parent_node.alias.as_str(), alias.as_str()),
+                    (declared_alias, alias.as_str()),
+                    (&member_node.alias, &member_sql_ast.alias),
+                    (PARENT, alias.as_str()),
+                    (CHILD, &member_sql_ast.alias),
+                ]
+                .into_iter()
+                .filter(|(declared, claimed)| declared != claimed)
+                .map(|(declared, claimed)| (declared.to_string(), claimed.to_string()))
+                .collect::<HashMap<_, _>>();
+                on.rename_tables(&renames);
+                if let Some(filter) = member_sql_ast.where_clause {
+                    on = on.and(filter);
+                }
+                joins.push(SqlJoin {
+                    table: member_sql_ast.table,
+                    alias: member_sql_ast.alias,
+                    join: Join {
+                        on,
+                        kind: JoinType::LeftJoin,
+                    },
+                    limit: None,
+                });
+                joins.extend(member_sql_ast.joins);
+                columns.extend(member_sql_ast.columns);
+                child_order_by.extend(member_sql_ast.order_by);
+                ctes.extend(member_sql_ast.ctes);
             }
+            shape.type_source = Some(TypeSource::Members(members));
         }
+        None => {}
     }
+    shape.type_shapes = type_shapes;
 
-    Ok(SqlSelect {
-        table: parent_node.table.clone(),
-        columns,
-        joins,
-        alias,
-        limit,
-        order_by,
-        where_clause,
-    })
+    let where_clause = node_filter(
+        ctx.root,
+        ctx.variables,
+        parent_node,
+        declared_alias,
+        &alias,
+        field,
+    )?;
+    let mut sort_joins = vec![];
+
+    if parent_node.sortable
+        && let Some((_, value)) = field
+            .arguments
+            .iter()
+            .find(|(name, _)| *name == ORDER_BY_ARGUMENT)
+    {
+        let root = ctx.root;
+        let selected = ctx.to_one_joins.clone();
+        let sort = order_by_argument(root, parent_node, &alias, value, &selected, &mut |alias| {
+            ctx.claim_alias(alias)
+        })?;
+        order_by.extend(sort.order_by);
+        sort_joins = sort.joins;
+        for join in &sort_joins {
+            if !joins.iter().any(|selected| selected.alias == join.alias) {
+                joins.push(join.clone());
+            }
+        }
+    }
+    if ctx.ordered {
+        for default in parent_node.default_order_by() {
+            order_by.push(SqlOrderBy {
+                expr: col(&alias, default.expr.column.clone()),
+                direction: match default.direction {
+                    OrderDirection::Asc => SqlOrderDirection::Asc,
+                    OrderDirection::Desc => SqlOrderDirection::Desc,
+                },
+                nulls: None,
+            });
+        }
+    }
+
+    if let Some(unique_key) = &parent_node.unique_key {
+        let column_alias = format!("{}_{}", alias, unique_key);
+        if !columns.iter().any(|column| column.alias == column_alias) {
+            columns.push(SqlColumn {
+                name: unique_key.clone(),
+                table: alias.clone(),
+                alias: column_alias.clone(),
+            });
+        }
+        shape.identity.push(column_alias);
+        // Ties are broken by the unique key so rows, and so hydrated lists, come back in the
+        // same order every time
+        if ctx.ordered {
+            order_by.push(SqlOrderBy {
+                expr: col(&alias, unique_key.clone()),
+                direction: SqlOrderDirection::Asc,
+                nulls: None,
+            });
+        }
+    }
+    let limit = list_limit(ctx.variables, parent_node, &alias, field)?.map(|count| {
+        let mut limit_order_by = order_by.clone();
+        // Which rows make the limit must not change between runs, even unordered
+        if limit_order_by.is_empty()
+            && let Some(unique_key) = &parent_node.unique_key
+        {
+            limit_order_by.push(SqlOrderBy {
+                expr: col(&alias, unique_key.clone()),
+                direction: SqlOrderDirection::Asc,
+                nulls: None,
+            });
+        }
+        SqlLimit {
+            count,
+            where_clause: None,
+            order_by: limit_order_by,
+            joins: sort_joins,
+            parent: None,
+        }
+    });
+    order_by.extend(child_order_by);
+
+    Ok((
+        SqlSelect {
+            mutation: None,
+            ctes,
+            table: parent_node.table.clone(),
+            columns,
+            joins,
+            alias,
+            limit,
+            order_by,
+            where_clause,
+        },
+        shape,
+    ))
+}
+
+/// How many rows of `node` a list selecting it with `field` keeps for each parent row: the
+/// field's `limit` or `first` argument, bound as a param like any other, or else, also when
+/// the argument is a variable that isn't given, the node's `Field::Limit`. Unlimited when
+/// `None`.
+fn list_limit<'a>(
+    variables: &InputVariables,
+    node: &Node,
+    alias: &str,
+    field: &graphql_parser::query::Field<'a, &'a str>,
+) -> Result<Option<SqlExpr>, Error> {
+    let argument = field.arguments.iter().find(|(name, value)| {
+        LIMIT_ARGUMENTS.contains(name)
+            && !matches!(node.fields.get(*name), Some(Field::Column(_)))
+            && !variables.omits(value)
+    });
+    match argument {
+        Some((name, value @ (Value::Int(_) | Value::Variable(_)))) => {
+            argument_param(value, &format!("{}_{}", alias, name)).map(Some)
+        }
+        Some((name, value)) => Err(Error::from(format!(
+            "The {} argument of {} must be an Int, got {}",
+            name, field.name, value
+        ))),
+        None => Ok(node.fields.values().find_map(|field| match field {
+            Field::Limit(limit) => Some(param(
+                format!("{}_limit", alias),
+                SqlValue::Int(Some(i32::try_from(limit.value).unwrap_or(i32::MAX)).into()),
+            )),
+            _ => None,
+        })),
+    }
 }
 
-// pub fn hydrate_results(rows: String, _resolve_info: &str) -> Result<String, String> {
-//     // This is a stub for now; real hydration would map flat rows to nested JSON.
-//     Ok(rows)
-// }
+/// Collects the fields of a selection set with the type they are conditional on: the
+/// innermost inline fragment naming one of `possible_types`, or `None` for every type.
+fn selected_fields<'s, 'a>(
+    items: &'s [Selection<'a, &'a str>],
+    type_condition: Option<&'a str>,
+    possible_types: &[String],
+    fields: &mut Vec<(
+        Option<&'a str>,
+        &'s graphql_parser::query::Field<'a, &'a str>,
+    )>,
+) {
+    for item in items {
+        match item {
+            Selection::Field(field) => fields.push((type_condition, field)),
+            Selection::InlineFragment(fragment) => {
+                let type_condition = match fragment.type_condition {
+                    Some(TypeCondition::On(type_name))
+                        if possible_types.iter().any(|possible| possible == type_name) =>
+                    {
+                        Some(type_name)
+                    }
+                    _ => type_condition,
+                };
+                selected_fields(
+                    &fragment.selection_set.items,
+                    type_condition,
+                    possible_types,
+                    fields,
+                );
+            }
+            // Named fragments are not supported yet
+            Selection::FragmentSpread(_) => {}
+        }
+    }
+}
+
+/// The shape of the fields selected under `... on <type_name>`.
+fn type_shape<'t>(type_shapes: &'t mut Vec<TypeShape>, type_name: &str) -> &'t mut TypeShape {
+    let index = match type_shapes
+        .iter()
+        .position(|type_shape| type_shape.type_name == type_name)
+    {
+        Some(index) => index,
+        None => {
+            type_shapes.push(TypeShape {
+                type_name: type_name.to_string(),
+                ..Default::default()
+            });
+            type_shapes.len() - 1
+        }
+    };
+    &mut type_shapes[index]
+}
+
+/// The metadata filters of `node` and the filters implied by `field`'s arguments, ANDed
+/// together and resolved against the node's claimed alias.
+///
+/// An argument named after one of the node's columns filters on that column being equal to
+/// the argument, which is always bound as a param: a variable binds the param of the same
+/// name, and a literal binds a param named after the alias and argument. A variable that
+/// isn't given filters on nothing.
+fn node_filter<'a>(
+    root: &Root,
+    variables: &InputVariables,
+    node: &Node,
+    declared_alias: &str,
+    alias: &str,
+    field: &graphql_parser::query::Field<'a, &'a str>,
+) -> Result<Option<SqlExpr>, Error> {
+    let mut filters = node.metadata_filters();
+
+    for (name, value) in &field.arguments {
+        match node.fields.get(*name) {
+            // Filters from the metadata are kept as a field named `where` too
+            _ if node.filterable && *name == WHERE_ARGUMENT => {
+                filters.extend(where_filter(root, variables, node, alias, value)?)
+            }
+            Some(Field::Column(_)) if variables.omits(value) => {}
+            Some(Field::Column(column)) => filters.push(
+                SqlExpr::Column(ColumnRef {
+                    column: column.column.clone(),
+                    table: None,
+                })
+                .eq(argument_param(value, &format!("{}_{}", alias, name))?),
+            ),
+            _ => {}
+        }
+    }
+
+    let Some(mut filter) = filters.into_iter().reduce(|left, right| left.and(right)) else {
+        return Ok(None);
+    };
+    filter.resolve_columns(alias);
+    let renames = [(node.alias.as_str(), alias), (declared_alias, alias)]
+        .into_iter()
+        .filter(|(declared, claimed)| declared != claimed)
+        .map(|(declared, claimed)| (declared.to_string(), claimed.to_string()))
+        .collect::<HashMap<_, _>>();
+    if !renames.is_empty() {
+        filter.rename_tables(&renames);
+    }
+    Ok(Some(filter))
+}
@@ -0,0 +1,282 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+#[cfg(feature = "wasm")]
+use tsify::Tsify;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::core::{
+    error::Error,
+    fns::{parse_gql, plan_response_shape},
+    schema::{Options, Root},
+};
+
+/// One result row, keyed by column alias.
+pub type Row = Map<String, Value>;
+
+#[derive(Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
+pub struct Rows(
+    #[cfg_attr(feature = "wasm", tsify(type = "Record<string, unknown>[]"))] pub Vec<Row>,
+);
+
+/// The `data` of a GraphQL response.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(into_wasm_abi))]
+pub struct Hydrated(
+    #[cfg_attr(feature = "wasm", tsify(type = "Record<string, unknown>"))] pub Value,
+);
+
+/// How the flat rows of a query nest back into the objects the GraphQL query selected.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResponseShape {
+    /// The response key (alias or field name) the object is selected under
+    pub key: String,
+    /// Whether the field is a list of objects rather than a single one
+    pub many: bool,
+    /// The column aliases that tell objects apart, empty when the node has no `unique_key`
+    pub identity: Vec<String>,
+    /// Response key and column alias of each scalar field
+    pub columns: Vec<(String, String)>,
+    pub children: Vec<ResponseShape>,
+    /// The GraphQL type of the objects, unless `type_source` tells them apart
+    pub type_name: String,
+    /// The response keys `__typename` is selected under
+    pub typename_keys: Vec<String>,
+    /// How the GraphQL type of each object is told apart, for interface and union nodes
+    pub type_source: Option<TypeSource>,
+    /// The fields selected under `... on Type`, only hydrated on objects of that type
+    pub type_shapes: Vec<TypeShape>,
+    /// How the objects of a recursive field nest into a tree, `None` for a flat list
+    pub tree: Option<TreeShape>,
+}
+
+/// The column aliases that place each object of a recursive field in its tree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TreeShape {
+    /// The object's own key
+    pub key: String,
+    /// The key of the object one level above it
+    pub parent_key: String,
+    /// How many levels below the root the object is, starting at 1
+    pub depth: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TypeSource {
+    /// The type is named by the value of a discriminator column
+    Discriminator {
+        column: String,
+        types: BTreeMap<String, String>,
+    },
+    /// The type is the first member type whose identity column is not null
+    Members(Vec<(String, String)>),
+}
+
+impl TypeSource {
+    /// The GraphQL type of the object `row` belongs to, `None` when it matches no type.
+    pub fn type_name<'s>(&'s self, row: &Row) -> Option<&'s str> {
+        match self {
+            TypeSource::Discriminator { column, types } => {
+                let value = match row.get(column)? {
+                    Value::String(value) => value.clone(),
+                    Value::Null => return None,
+                    value => value.to_string(),
+                };
+                types.get(&value).map(String::as_str)
+            }
+            TypeSource::Members(members) => members
+                .iter()
+                .find(|(_, column)| row.get(column).is_some_and(|value| !value.is_null()))
+                .map(|(type_name, _)| type_name.as_str()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct TypeShape {
+    pub type_name: String,
+    /// Response key and column alias of each scalar field
+    pub columns: Vec<(String, String)>,
+    pub children: Vec<ResponseShape>,
+    /// The response keys `__typename` is selected under
+    pub typename_keys: Vec<String>,
+}
+
+/// Nests the rows of `query`'s SQL into the shape of the query.
+pub fn hydrate_results(
+    query: &str,
+    rows: &[Row],
+    root: &Root,
+    options: Option<&Options>,
+) -> Result<Value, Error> {
+    let doc = parse_gql(query)?;
+    let find_root = |field_name: &str| root.0.values().find(|node| node.field_name == field_name);
+    let shape = plan_response_shape(&doc, root, &find_root, options)?;
+    Ok(hydrate(&shape, rows))
+}
+
+/// Nests `rows` into `{ [shape.key]: ... }`.
+///
+/// Rows are grouped into objects by their `identity` columns in the order they first appear.
+/// A node without a `unique_key` has no identity: each of its rows is an object of its own in
+/// a list, and all of them are the one object otherwise. A list that joins many rows below it
+/// has each of its objects repeated over their rows, so its rows are grouped by every selected
+/// column instead, and objects with the same values are one. An object whose identity columns, or
+/// every selected column, are all null was not matched by its `LEFT JOIN`, so it is left out;
+/// give nodes a `unique_key` when all of their selected columns can be null.
+/// `__typename` is the node's GraphQL type, or for interface and union nodes the type each
+/// object turns out to be. Those objects always get a `__typename`, and only the fields of the
+/// fragments on that type.
+pub fn hydrate(shape: &ResponseShape, rows: &[Row]) -> Value {
+    let rows = rows.iter().collect::<Vec<_>>();
+    let mut data = Map::new();
+    data.insert(shape.key.clone(), hydrate_field(shape, &rows));
+    Value::Object(data)
+}
+
+fn hydrate_field(shape: &ResponseShape, rows: &[&Row]) -> Value {
+    let objects = match &shape.tree {
+        Some(tree) => hydrate_tree(shape, tree, rows, None, 1),
+        None => group_rows(shape, rows)
+            .iter()
+            .map(|rows| Value::Object(hydrate_object(shape, rows)))
+            .collect(),
+    };
+    if shape.many {
+        Value::Array(objects)
+    } else {
+        objects.into_iter().next().unwrap_or(Value::Null)
+    }
+}
+
+/// The objects one level below `parent` in a recursive field's tree, each holding the objects
+/// below it under the same key.
+fn hydrate_tree(
+    shape: &ResponseShape,
+    tree: &TreeShape,
+    rows: &[&Row],
+    parent: Option<&Value>,
+    depth: i64,
+) -> Vec<Value> {
+    // Matching the depth too keeps cyclic data from nesting forever
+    let level = rows
+        .iter()
+        .copied()
+        .filter(|row| {
+            row.get(&tree.depth).and_then(Value::as_i64) == Some(depth)
+                && parent.is_none_or(|parent| row.get(&tree.parent_key) == Some(parent))
+        })
+        .collect::<Vec<_>>();
+    group_rows(shape, &level)
+        .iter()
+        .map(|level_rows| {
+            let mut object = hydrate_object(shape, level_rows);
+            let key = level_rows[0].get(&tree.key).unwrap_or(&Value::Null);
+            object.insert(
+                shape.key.clone(),
+                Value::Array(hydrate_tree(shape, tree, rows, Some(key), depth + 1)),
+            );
+            Value::Object(object)
+        })
+        .collect()
+}
+
+/// Groups `rows` into the rows of each object, in the order the objects first appear.
+fn group_rows<'r>(shape: &ResponseShape, rows: &[&'r Row]) -> Vec<Vec<&'r Row>> {
+    let identity = if shape.identity.is_empty() {
+        shape.columns.iter().map(|(_, column)| column).collect()
+    } else {
+        shape.identity.iter().collect::<Vec<_>>()
+    };
+    // Without an identity, equal rows of a list may still be different objects, unless a
+    // to-many join repeats them
+    let separate = shape.identity.is_empty() && shape.many && !joins_many(shape);
+
+    let mut groups: Vec<Vec<&Row>> = vec![];
+    let mut indexes = HashMap::new();
+    for row in rows {
+        let values = identity
+            .iter()
+            .map(|column| row.get(*column).unwrap_or(&Value::Null))
+            .collect::<Vec<_>>();
+        if !values.is_empty() && values.iter().all(|value| value.is_null()) {
+            continue;
+        }
+        if separate {
+            groups.push(vec![*row]);
+            continue;
+        }
+        // Everything a single object without an identity joins is that object
+        let key = if shape.identity.is_empty() && !shape.many {
+            String::new()
+        } else {
+            Value::Array(values.into_iter().cloned().collect()).to_string()
+        };
+        let index = *indexes.entry(key).or_insert_with(|| {
+            groups.push(vec![]);
+            groups.len() - 1
+        });
+        groups[index].push(*row);
+    }
+    groups
+}
+
+/// Whether a to-many join below `shape`, directly or through to-one joins, repeats its rows.
+fn joins_many(shape: &ResponseShape) -> bool {
+    shape
+        .children
+        .iter()
+        .chain(
+            shape
+                .type_shapes
+                .iter()
+                .flat_map(|type_shape| &type_shape.children),
+        )
+        .any(|child| child.many || joins_many(child))
+}
+
+/// The object whose rows are `rows`.
+fn hydrate_object(shape: &ResponseShape, rows: &[&Row]) -> Map<String, Value> {
+    let mut object = Map::new();
+    for (key, column) in &shape.columns {
+        object.insert(
+            key.clone(),
+            rows[0].get(column).cloned().unwrap_or(Value::Null),
+        );
+    }
+    for child in &shape.children {
+        object.insert(child.key.clone(), hydrate_field(child, rows));
+    }
+    let type_name = match &shape.type_source {
+        Some(type_source) => type_source.type_name(rows[0]),
+        None => Some(shape.type_name.as_str()),
+    };
+    let type_name_value = type_name.map_or(Value::Null, |type_name| type_name.into());
+    for key in &shape.typename_keys {
+        object.insert(key.clone(), type_name_value.clone());
+    }
+    if shape.type_source.is_some() {
+        for type_shape in &shape.type_shapes {
+            if type_name != Some(type_shape.type_name.as_str()) {
+                continue;
+            }
+            for (key, column) in &type_shape.columns {
+                object.insert(
+                    key.clone(),
+                    rows[0].get(column).cloned().unwrap_or(Value::Null),
+                );
+            }
+            for child in &type_shape.children {
+                object.insert(child.key.clone(), hydrate_field(child, rows));
+            }
+            for key in &type_shape.typename_keys {
+                object.insert(key.clone(), type_name_value.clone());
+            }
+        }
+        object.insert("__typename".into(), type_name_value);
+    }
+    object
+}
@@ -1,4 +1,17 @@
+pub mod builder;
+pub mod cache;
+pub mod compiled;
+pub mod cost;
+pub mod error;
+pub mod filter;
 pub mod fns;
+pub mod hydrate;
+pub mod mutation;
+pub mod persisted;
 pub mod schema;
+pub mod sdl;
 pub mod shared_schema;
+pub mod sort;
 pub mod sql_schema;
+pub mod validate;
+pub mod variables;
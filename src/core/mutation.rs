@@ -0,0 +1,447 @@
+use std::collections::{BTreeMap, HashMap};
+
+use graphql_parser::query::Value;
+
+use crate::core::{
+    builder::col,
+    error::Error,
+    filter::argument_param,
+    schema::{Field, JoinInfo, Node, Root},
+    shared_schema::{CHILD, PARENT, SqlExpr},
+    sql_schema::{SqlMutation, SqlMutationKind, SqlNestedInsert, SqlOnConflict},
+    variables::InputVariables,
+};
+
+/// The argument `create<Type>` takes the columns of the new row in.
+pub const INPUT_ARGUMENT: &str = "input";
+/// The argument `update<Type>` takes the columns it changes in.
+pub const PATCH_ARGUMENT: &str = "patch";
+
+/// The write a root mutation field makes, named by the prefix of the field.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MutationKind {
+    Create,
+    Update,
+    Upsert,
+    Delete,
+}
+
+impl MutationKind {
+    /// The prefix of the mutation fields of this kind, e.g. `create` in `createPost`.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            MutationKind::Create => "create",
+            MutationKind::Update => "update",
+            MutationKind::Upsert => "upsert",
+            MutationKind::Delete => "delete",
+        }
+    }
+}
+
+/// The mutable node a root mutation field named `field_name` writes, and how.
+///
+/// When several mutable nodes share a type, the one with a root query field wins, then the
+/// first by alias.
+pub fn find_mutation<'r>(root: &'r Root, field_name: &str) -> Option<(MutationKind, &'r Node)> {
+    [
+        MutationKind::Create,
+        MutationKind::Update,
+        MutationKind::Upsert,
+        MutationKind::Delete,
+    ]
+    .into_iter()
+    .find_map(|kind| {
+        let type_name = field_name.strip_prefix(kind.prefix())?;
+        root.0
+            .values()
+            .filter(|node| node.mutable && node.type_name() == type_name)
+            .min_by_key(|node| (node.field_name.is_empty(), &node.alias))
+            .map(|node| (kind, node))
+    })
+}
+
+/// The column field holding `node`'s unique key, which `update<Type>` and `delete<Type>` take
+/// the key of the row they write as.
+pub fn key_field(node: &Node) -> Option<&str> {
+    let key = node.unique_key.as_ref()?;
+    node.fields
+        .iter()
+        .filter_map(|(name, field)| match field {
+            Field::Column(column) if &column.column == key => Some(name.as_str()),
+            _ => None,
+        })
+        .min()
+}
+
+/// Compiles the write of a `kind` mutation field selecting `node` under `alias`.
+///
+/// Column values, like every other argument, are bound as params: a variable binds the param
+/// of the same name, and a literal binds a param named after the alias, argument and field.
+/// An input given whole as a variable is expanded into its fields, see `InputVariables`, and a
+/// column whose variable isn't given is left unset.
+/// Updates and deletes write the one row whose key is the key argument, and only when it
+/// passes the node's metadata filters. Upserts insert their input, or update the row that
+/// already has its key. Creates also insert the rows their input gives for relations, see
+/// `nested_inserts`.
+pub fn mutation_write<'a>(
+    root: &Root,
+    variables: &InputVariables,
+    node: &Node,
+    kind: MutationKind,
+    alias: &str,
+    field: &graphql_parser::query::Field<'a, &'a str>,
+) -> Result<SqlMutation, Error> {
+    let argument = |name: &str| {
+        field
+            .arguments
+            .iter()
+            .find(|(argument, _)| *argument == name)
+            .map(|(_, value)| value)
+    };
+    let mut nested = vec![];
+    let values = match kind {
+        MutationKind::Create => {
+            let input = argument(INPUT_ARGUMENT).ok_or(format!(
+                "{} is missing its {} argument",
+                field.name, INPUT_ARGUMENT
+            ))?;
+            let insert = expand_input(variables, field, INPUT_ARGUMENT, input, |input| {
+                insert_row(root, variables, node, alias, input)
+            })?;
+            nested = insert.nested;
+            insert.values
+        }
+        MutationKind::Upsert => {
+            let input = argument(INPUT_ARGUMENT).ok_or(format!(
+                "{} is missing its {} argument",
+                field.name, INPUT_ARGUMENT
+            ))?;
+            expand_input(variables, field, INPUT_ARGUMENT, input, |input| {
+                column_values(variables, node, alias, INPUT_ARGUMENT, input)
+            })?
+        }
+        MutationKind::Update => {
+            let patch = argument(PATCH_ARGUMENT).ok_or(format!(
+                "{} is missing its {} argument",
+                field.name, PATCH_ARGUMENT
+            ))?;
+            let values = expand_input(variables, field, PATCH_ARGUMENT, patch, |patch| {
+                column_values(variables, node, alias, PATCH_ARGUMENT, patch)
+            })?;
+            if values.is_empty() {
+                return Err(Error::from(format!(
+                    "The {} of {} must set at least one column",
+                    PATCH_ARGUMENT, field.name
+                )));
+            }
+            values
+        }
+        MutationKind::Delete => vec![],
+    };
+
+    let where_clause = match kind {
+        MutationKind::Create | MutationKind::Upsert => None,
+        MutationKind::Update | MutationKind::Delete => {
+            let (key, name) = node
+                .unique_key
+                .as_ref()
+                .zip(key_field(node))
+                .ok_or(format!(
+                    "{} needs a unique_key selected by one of its columns to be {}d",
+                    node.type_name(),
+                    kind.prefix()
+                ))?;
+            let value = argument(name)
+                .filter(|value| !variables.omits(value))
+                .ok_or(format!("{} is missing its {} argument", field.name, name))?;
+            let mut filter =
+                col(alias, key.clone()).eq(argument_param(value, &format!("{}_{}", alias, name))?);
+            for mut metadata_filter in node.metadata_filters() {
+                metadata_filter.resolve_columns(alias);
+                if node.alias != alias {
+                    metadata_filter
+                        .rename_tables(&HashMap::from([(node.alias.clone(), alias.to_string())]));
+                }
+                filter = filter.and(metadata_filter);
+            }
+            Some(filter)
+        }
+    };
+
+    let on_conflict = match kind {
+        MutationKind::Upsert => Some(upsert_conflict(node, field, &values)?),
+        _ => None,
+    };
+
+    Ok(SqlMutation {
+        kind: match kind {
+            MutationKind::Create | MutationKind::Upsert => SqlMutationKind::Insert,
+            MutationKind::Update => SqlMutationKind::Update,
+            MutationKind::Delete => SqlMutationKind::Delete,
+        },
+        table: node.table.clone(),
+        alias: alias.to_string(),
+        values,
+        where_clause,
+        on_conflict,
+        nested,
+        returning: None,
+    })
+}
+
+/// The insert of the row a `create<Type>` input gives, along with its nested rows.
+fn insert_row<'a>(
+    root: &Root,
+    variables: &InputVariables,
+    node: &Node,
+    alias: &str,
+    input: &Value<'a, &'a str>,
+) -> Result<SqlMutation, Error> {
+    let mut values = vec![];
+    let mut nested = vec![];
+    for (name, value) in input_fields(INPUT_ARGUMENT, input)? {
+        match node.fields.get(*name) {
+            Some(Field::Column(_)) if variables.omits(value) => {}
+            Some(Field::Column(column)) => values.push((
+                column.column.clone(),
+                argument_param(value, &format!("{}_{}_{}", alias, INPUT_ARGUMENT, name))?,
+            )),
+            Some(Field::Join(join_info)) => nested.extend(nested_inserts(
+                root, variables, node, alias, name, join_info, value,
+            )?),
+            _ => {
+                return Err(Error::from(format!(
+                    "{} has no column {} to set",
+                    node.type_name(),
+                    name
+                )));
+            }
+        }
+    }
+    Ok(SqlMutation {
+        kind: SqlMutationKind::Insert,
+        table: node.table.clone(),
+        alias: alias.to_string(),
+        values,
+        where_clause: None,
+        on_conflict: None,
+        nested,
+        returning: None,
+    })
+}
+
+/// The rows an input inserts for the relation `name`, a list of inputs for a list relation
+/// and one input otherwise.
+///
+/// The relation must join on `$parent.<unique key> = $child.<column>`, so each row is
+/// inserted after its parent with the column set to the parent's key. Each row's alias, which
+/// names its params, is the parent's followed by the relation and the row's index.
+fn nested_inserts<'a>(
+    root: &Root,
+    variables: &InputVariables,
+    node: &Node,
+    alias: &str,
+    name: &str,
+    join_info: &JoinInfo,
+    value: &Value<'a, &'a str>,
+) -> Result<Vec<SqlNestedInsert>, Error> {
+    let child = root
+        .0
+        .get(&join_info.extends.extends)
+        .filter(|child| child.mutable)
+        .ok_or(format!(
+            "{}.{} can only be created along with {} when it returns a mutable node",
+            node.type_name(),
+            name,
+            node.type_name()
+        ))?;
+    let (parent_key, foreign_key) = foreign_key(node, join_info).ok_or(format!(
+        "{}.{} can only be created along with {} when it joins on $parent.<unique key> = \
+         $child.<column>",
+        node.type_name(),
+        name,
+        node.type_name()
+    ))?;
+    let rows = match value {
+        Value::List(rows) if join_info.many => rows.iter().collect(),
+        Value::Object(_) => vec![value],
+        other => {
+            return Err(Error::from(format!(
+                "The {} of {} must be {}, got {}",
+                name,
+                node.type_name(),
+                if join_info.many {
+                    "a list of input objects"
+                } else {
+                    "an input object"
+                },
+                other
+            )));
+        }
+    };
+    rows.into_iter()
+        .enumerate()
+        .map(|(index, row)| {
+            let alias = format!("{}_{}_{}", alias, name, index);
+            let insert = insert_row(root, variables, child, &alias, row)?;
+            if insert
+                .values
+                .iter()
+                .any(|(column, _)| *column == foreign_key)
+            {
+                return Err(Error::from(format!(
+                    "The {} of {} can't set {}, which is the key of the {} they belong to",
+                    name,
+                    node.type_name(),
+                    foreign_key,
+                    node.type_name()
+                )));
+            }
+            Ok(SqlNestedInsert {
+                insert,
+                foreign_key: foreign_key.clone(),
+                parent_key: parent_key.clone(),
+            })
+        })
+        .collect()
+}
+
+/// The parent's unique key and the child column a join matches it with, when that is all the
+/// join matches, which lets `create<Type>` insert the join's rows along with the parent's.
+pub fn foreign_key(node: &Node, join_info: &JoinInfo) -> Option<(String, String)> {
+    let key = node.unique_key.as_ref()?;
+    let (left, right) = match &join_info.join.on {
+        SqlExpr::Eq(eq) => match (&*eq.left, &*eq.right) {
+            (SqlExpr::Column(left), SqlExpr::Column(right)) => (
+                (left.table.as_deref()?, left.column.as_str()),
+                (right.table.as_deref()?, right.column.as_str()),
+            ),
+            _ => return None,
+        },
+        // e.g. `$parent.id = $child.author_id` from `@sqlJoin`
+        SqlExpr::Raw(raw) => {
+            let (left, right) = raw.value.split_once('=')?;
+            (raw_column(left)?, raw_column(right)?)
+        }
+        _ => return None,
+    };
+    let (parent, child) = match (left, right) {
+        ((PARENT, parent), (CHILD, child)) | ((CHILD, child), (PARENT, parent)) => (parent, child),
+        _ => return None,
+    };
+    (parent == key).then(|| (key.clone(), child.to_string()))
+}
+
+/// The table and column of one side of a raw join condition, unquoted.
+fn raw_column(sql: &str) -> Option<(&str, &str)> {
+    fn ident(ident: &str) -> Option<&str> {
+        let ident = ident.trim_matches('"');
+        ident
+            .chars()
+            .all(|c| c == '$' || c == '_' || c.is_alphanumeric())
+            .then_some(ident)
+    }
+    let (table, column) = sql.trim().split_once('.')?;
+    Some((ident(table)?, ident(column)?))
+}
+
+/// How `upsert<Type>` updates the row that has the key in its input.
+///
+/// The row is updated with the input's values for the node's `upsert_columns`, or for every
+/// column but the key. Metadata filters do not apply, since the existing row is never read.
+fn upsert_conflict<'a>(
+    node: &Node,
+    field: &graphql_parser::query::Field<'a, &'a str>,
+    values: &[(String, SqlExpr)],
+) -> Result<SqlOnConflict, Error> {
+    let (key, name) = node
+        .unique_key
+        .as_ref()
+        .zip(key_field(node))
+        .ok_or(format!(
+            "{} needs a unique_key selected by one of its columns to be upserted",
+            node.type_name()
+        ))?;
+    if !values.iter().any(|(column, _)| column == key) {
+        return Err(Error::from(format!(
+            "The {} of {} must set the key, {}",
+            INPUT_ARGUMENT, field.name, name
+        )));
+    }
+    let mut update = values
+        .iter()
+        .map(|(column, _)| column)
+        .filter(|column| match &node.upsert_columns {
+            Some(upsert_columns) => upsert_columns.contains(column),
+            None => *column != key,
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    // Setting the key to itself still hands back the existing row
+    if update.is_empty() {
+        update.push(key.clone());
+    }
+    Ok(SqlOnConflict {
+        target: vec![key.clone()],
+        update,
+    })
+}
+
+/// The columns an `input` or `patch` argument sets, ordered by field name.
+fn column_values<'a>(
+    variables: &InputVariables,
+    node: &Node,
+    alias: &str,
+    argument: &str,
+    value: &Value<'a, &'a str>,
+) -> Result<Vec<(String, SqlExpr)>, Error> {
+    let mut values = vec![];
+    for (name, value) in input_fields(argument, value)? {
+        match node.fields.get(*name) {
+            Some(Field::Column(_)) if variables.omits(value) => {}
+            Some(Field::Column(column)) => values.push((
+                column.column.clone(),
+                argument_param(value, &format!("{}_{}_{}", alias, argument, name))?,
+            )),
+            _ => {
+                return Err(Error::from(format!(
+                    "{} has no column {} to set",
+                    node.type_name(),
+                    name
+                )));
+            }
+        }
+    }
+    Ok(values)
+}
+
+/// Compiles the `input` or `patch` argument `value` of `field` with `f`, expanding it when it
+/// is a variable.
+fn expand_input<'a, R>(
+    variables: &InputVariables,
+    field: &graphql_parser::query::Field<'a, &'a str>,
+    argument: &str,
+    value: &Value<'a, &'a str>,
+    f: impl for<'v> FnOnce(&Value<'v, &'v str>) -> Result<R, Error>,
+) -> Result<R, Error> {
+    variables.expand(value, f)?.ok_or_else(|| {
+        Error::from(format!(
+            "The {} argument of {} is {}, which isn't given",
+            argument, field.name, value
+        ))
+    })
+}
+
+/// The fields of an `input` or `patch` argument, which must be an input object.
+fn input_fields<'v, 'a>(
+    argument: &str,
+    value: &'v Value<'a, &'a str>,
+) -> Result<&'v BTreeMap<&'a str, Value<'a, &'a str>>, Error> {
+    match value {
+        Value::Object(fields) => Ok(fields),
+        other => Err(Error::from(format!(
+            "The {} argument must be an input object, got {}",
+            argument, other
+        ))),
+    }
+}
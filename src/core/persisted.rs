@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+
+use graphql_parser::query::{Definition, OperationDefinition};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+#[cfg(feature = "wasm")]
+use tsify::Tsify;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::core::{
+    error::Error,
+    fns::{build_sql_document, parse_gql, plan_query_inputs, render_sql_query},
+    schema::{Options, Root, Variables},
+    shared_schema::SqlValue,
+    sql_schema::SqlQuery,
+    variables::InputVariables,
+};
+
+#[derive(Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
+pub struct NamedQuery {
+    pub name: String,
+    pub query: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
+pub struct NamedQueries(pub Vec<NamedQuery>);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(into_wasm_abi, from_wasm_abi))]
+pub struct PersistedQuery {
+    pub name: String,
+    pub query: String,
+    pub sql: String,
+    pub params: Vec<SqlValue>,
+    #[serde(default)]
+    #[cfg_attr(feature = "wasm", tsify(type = "Record<string, number[]>"))]
+    pub positions: BTreeMap<String, Vec<usize>>,
+    /// Statements to run after `sql`, as in `SqlQuery`
+    #[serde(default)]
+    pub then: Vec<SqlQuery>,
+}
+
+/// Precompiled queries keyed by the SHA-256 hash of their GraphQL text.
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(into_wasm_abi, from_wasm_abi))]
+pub struct Manifest {
+    #[cfg_attr(feature = "wasm", tsify(type = "Record<string, PersistedQuery>"))]
+    pub queries: BTreeMap<String, PersistedQuery>,
+}
+
+/// The hex encoded SHA-256 hash a persisted query is registered under.
+pub fn query_hash(query: &str) -> String {
+    Sha256::digest(query.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+impl Manifest {
+    /// Compiles every query to SQL up front, with a param for each variable the query
+    /// declares whether or not a call gives it.
+    pub fn compile(
+        queries: &[NamedQuery],
+        root: &Root,
+        options: Option<&Options>,
+    ) -> Result<Self, Error> {
+        let mut manifest = Manifest::default();
+        for named in queries {
+            let SqlQuery {
+                sql,
+                params,
+                positions,
+                then,
+                // Only statements after the first fill params from returned rows
+                ..
+            } = compile_query(&named.query, root, options)
+                .map_err(|err| format!("Unable to compile query \"{}\": {}", named.name, err))?;
+            manifest.queries.insert(
+                query_hash(&named.query),
+                PersistedQuery {
+                    name: named.name.clone(),
+                    query: named.query.clone(),
+                    sql,
+                    params,
+                    positions,
+                    then,
+                },
+            );
+        }
+        Ok(manifest)
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string(self).map_err(|err| Error::from(err.to_string()))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        serde_json::from_str(json).map_err(|err| Error::from(err.to_string()))
+    }
+
+    pub fn get(&self, hash: &str) -> Option<&PersistedQuery> {
+        self.queries.get(hash)
+    }
+}
+
+/// Plans `query` as if every variable it declares were given, so leaving one out of a call
+/// binds its default or a null instead of dropping what it is the value of.
+fn compile_query(query: &str, root: &Root, options: Option<&Options>) -> Result<SqlQuery, Error> {
+    let doc = parse_gql(query)?;
+    let mut variables = InputVariables::default();
+    if let Some(Definition::Operation(operation)) = doc.definitions.first() {
+        let definitions = match operation {
+            OperationDefinition::Query(query) => &query.variable_definitions[..],
+            OperationDefinition::Mutation(mutation) => &mutation.variable_definitions[..],
+            _ => &[][..],
+        };
+        for definition in definitions {
+            variables.give(definition.name);
+        }
+    }
+    let find_root = |field_name: &str| root.0.values().find(|node| node.field_name == field_name);
+    let plan = plan_query_inputs(&doc, root, &find_root, variables, options)?;
+    render_sql_query(&plan.sql_ast, options)
+}
+
+/// Serves queries from a manifest, optionally refusing anything that isn't in it.
+pub struct QueryRegistry {
+    pub manifest: Manifest,
+    /// Reject queries that are not in the manifest instead of compiling them
+    pub allow_list: bool,
+}
+
+impl QueryRegistry {
+    pub fn new(manifest: Manifest, allow_list: bool) -> Self {
+        QueryRegistry {
+            manifest,
+            allow_list,
+        }
+    }
+
+    /// The precompiled SQL registered under `hash`, with `variables` bound to its params.
+    pub fn build_by_hash(
+        &self,
+        hash: &str,
+        variables: Option<&Variables>,
+    ) -> Result<SqlQuery, Error> {
+        let persisted = self.manifest.get(hash).ok_or(Error::UnregisteredQuery {
+            hash: hash.to_string(),
+        })?;
+        let mut query = SqlQuery {
+            sql: persisted.sql.clone(),
+            params: persisted.params.clone(),
+            positions: persisted.positions.clone(),
+            then: persisted.then.clone(),
+            keys: BTreeMap::new(),
+        };
+        query.bind_params(&InputVariables::new(variables)?.values);
+        Ok(query)
+    }
+
+    /// The precompiled SQL for `query`, compiling it when it isn't registered and the registry
+    /// is not an allow-list.
+    pub fn build(
+        &self,
+        query: &str,
+        variables: Option<&Variables>,
+        root: &Root,
+        options: Option<&Options>,
+    ) -> Result<SqlQuery, Error> {
+        match self.build_by_hash(&query_hash(query), variables) {
+            Err(Error::UnregisteredQuery { .. }) if !self.allow_list => {
+                let doc = parse_gql(query)?;
+                let find_root =
+                    |field_name: &str| root.0.values().find(|node| node.field_name == field_name);
+                build_sql_document(&doc, root, &find_root, variables, options)
+            }
+            result => result,
+        }
+    }
+}
@@ -1,19 +1,35 @@
-use serde::Deserialize;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+#[cfg(feature = "wasm")]
 use tsify::Tsify;
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
-use crate::core::shared_schema::Join;
+use crate::core::{
+    error::Error,
+    shared_schema::{Join, SqlExpr, Value},
+};
 
-#[derive(Tsify, Deserialize, Debug)]
-#[tsify(from_wasm_abi)]
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct Options {
     pub builder: BuilderType,
+    /// The deepest chain of joins a query may select, unlimited when omitted
+    #[serde(default)]
+    pub max_depth: Option<u32>,
+    /// The highest estimated cost a query may have, unlimited when omitted
+    #[serde(default)]
+    pub max_cost: Option<u32>,
+    /// Leaves out the default sorts and unique key tie-breakers of every node, for callers
+    /// that order the rows elsewhere
+    #[serde(default)]
+    pub unordered: bool,
 }
 
-#[derive(Tsify, Deserialize, Clone, Debug)]
-#[tsify(from_wasm_abi)]
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub enum BuilderType {
+    #[default]
     #[serde(rename = "postgres")]
     Postgres,
     #[serde(rename = "mysql")]
@@ -22,13 +38,25 @@ pub enum BuilderType {
     Sqlite,
 }
 
-#[derive(Tsify, Deserialize, Debug)]
-#[tsify(from_wasm_abi)]
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct RootInput(pub Vec<Node>);
 
-#[derive(Tsify, Deserialize, Debug)]
-#[tsify(from_wasm_abi)]
-pub struct Root(#[tsify(type = "Record<string, Node>")] pub HashMap<String, Node>);
+/// GraphQL variables, keyed by name without the `$`
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
+pub struct Variables(
+    #[cfg_attr(feature = "wasm", tsify(type = "Record<string, unknown>"))]
+    pub  HashMap<String, serde_json::Value>,
+);
+
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
+pub struct Root(
+    #[cfg_attr(feature = "wasm", tsify(type = "Record<string, Node>"))]
+    #[serde(serialize_with = "sorted")]
+    pub HashMap<String, Node>,
+);
 
 impl From<Vec<Node>> for Root {
     fn from(values: Vec<Node>) -> Self {
@@ -40,8 +68,8 @@ impl From<Vec<Node>> for Root {
     }
 }
 
-#[derive(Tsify, Deserialize, Clone, Debug)]
-#[tsify(from_wasm_abi)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub enum AnyNode {
     #[serde(rename = "alias")]
     AliasNode(ExtendsNode),
@@ -49,8 +77,8 @@ pub enum AnyNode {
     Node(Node),
 }
 
-#[derive(Tsify, Deserialize, Clone, Debug)]
-#[tsify(from_wasm_abi)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct ExtendsNode {
     pub alias: String,
     pub field_name: String,
@@ -58,8 +86,8 @@ pub struct ExtendsNode {
     pub extends: String,
 }
 
-#[derive(Tsify, Deserialize, Clone, Debug)]
-#[tsify(from_wasm_abi)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct Node {
     /// The SuperJoin Identifier and SQL alias.
     pub alias: String,
@@ -67,48 +95,132 @@ pub struct Node {
     pub field_name: String,
     /// The SQL table name
     pub table: String,
+    /// The GraphQL type name, derived from `table` when omitted
+    #[serde(default)]
+    pub graphql_type: Option<String>,
+    /// Whether the root query field returns a list of this node
+    #[serde(default)]
+    pub many: bool,
+    /// The column that tells rows of this node apart when hydrating, always selected when given
+    #[serde(default)]
+    pub unique_key: Option<String>,
+    /// Whether query fields selecting this node accept a `where` argument filtering on its
+    /// columns and joins
+    #[serde(default)]
+    pub filterable: bool,
+    /// Whether query fields selecting this node accept an `orderBy` argument sorting on its
+    /// columns and to-one joins
+    #[serde(default)]
+    pub sortable: bool,
+    /// Whether mutations named `create<Type>`, `update<Type>`, `upsert<Type>` and
+    /// `delete<Type>` write rows of this node
+    #[serde(default)]
+    pub mutable: bool,
+    /// The columns `upsert<Type>` updates when a row with the same key exists, every column
+    /// the input sets other than the key when omitted
+    #[serde(default)]
+    pub upsert_columns: Option<Vec<String>>,
+    /// How rows map to the GraphQL types an interface or union node can be
+    #[serde(default)]
+    pub polymorphic: Option<Polymorphic>,
     /// Metadata about how to fetch the fields from SQL
-    #[tsify(type = "Record<string, Field>")]
+    #[cfg_attr(feature = "wasm", tsify(type = "Record<string, Field>"))]
+    #[serde(serialize_with = "sorted")]
     pub fields: HashMap<String, Field>,
 }
 
-#[derive(Tsify, Deserialize, Clone, Debug)]
-#[tsify(from_wasm_abi)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 #[serde(tag = "kind")]
 pub enum Field {
     #[serde(rename = "column")]
     Column(ColumnInfo),
     #[serde(rename = "join")]
     Join(JoinInfo),
+    #[serde(rename = "recursive")]
+    Recursive(RecursiveInfo),
     #[serde(rename = "where")]
-    Where(String),
+    Where(WhereInfo),
     #[serde(rename = "order_by")]
     OrderBy(OrderBy),
     #[serde(rename = "limit")]
-    Limit(u32),
+    Limit(Value<u32>),
 }
 
-#[derive(Tsify, Deserialize, Clone, Debug)]
-#[tsify(from_wasm_abi)]
+/// How the rows of an interface or union node tell which GraphQL type they are.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 #[serde(tag = "kind")]
+pub enum Polymorphic {
+    /// Every type is stored in the node's own table, named by a discriminator column
+    #[serde(rename = "single_table")]
+    SingleTable(SingleTable),
+    /// Each type is a member node LEFT JOINed to the node's table
+    #[serde(rename = "union")]
+    Union(Union),
+}
+
+/// Single-table inheritance: the node's fields are the fields of all of its types.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
+pub struct SingleTable {
+    pub discriminator: String,
+    /// The GraphQL type of each discriminator value
+    #[cfg_attr(feature = "wasm", tsify(type = "Record<string, string>"))]
+    pub types: BTreeMap<String, String>,
+}
+
+/// A row is of the type of the first member it joins, so every member needs a `unique_key`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
+pub struct Union {
+    pub members: Vec<UnionMember>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
+pub struct UnionMember {
+    /// The alias of the member node
+    pub extends: String,
+    /// The join condition, written like a join's against `$parent` and `$child`
+    pub on: SqlExpr,
+}
+
+/// A filter on every row of a node, ANDed with its other filters.
+///
+/// Columns without a `table` refer to the node itself, wherever it is joined.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
+pub struct WhereInfo {
+    pub expr: SqlExpr,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct OrderBy {
     pub expr: ColumnInfo,
     pub direction: OrderDirection,
 }
 
-#[derive(Tsify, Deserialize, Clone, Debug)]
-#[tsify(from_wasm_abi)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 #[serde(tag = "kind")]
 pub enum OrderDirection {
     Asc,
     Desc,
 }
 
-#[derive(Tsify, Deserialize, Clone, Debug)]
-#[tsify(from_wasm_abi)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct ColumnInfo {
     pub column: String,
     pub table: Option<String>,
+    /// The GraphQL scalar type, `String` (or `ID` for an `id` column) when omitted
+    #[serde(default)]
+    pub graphql_type: Option<String>,
+    /// What selecting this column adds to a query's estimated cost, nothing when omitted
+    #[serde(default)]
+    pub cost: Option<u32>,
 }
 
 impl From<String> for ColumnInfo {
@@ -116,6 +228,8 @@ impl From<String> for ColumnInfo {
         ColumnInfo {
             column,
             table: None,
+            graphql_type: None,
+            cost: None,
         }
     }
 }
@@ -126,10 +240,205 @@ impl From<&str> for ColumnInfo {
     }
 }
 
-#[derive(Tsify, Deserialize, Clone, Debug)]
-#[tsify(from_wasm_abi)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct JoinInfo {
     /// The id of a root type
     pub extends: ExtendsNode,
     pub join: Join,
+    /// Whether the join yields a list of nodes rather than a single one
+    #[serde(default)]
+    pub many: bool,
+    /// What selecting this join adds to a query's estimated cost, 1 when omitted
+    #[serde(default)]
+    pub cost: Option<u32>,
+}
+
+/// A self-referencing relation followed to any depth, e.g. every reply below a comment.
+///
+/// It compiles to a `WITH RECURSIVE` CTE over the node's table, so the node needs a
+/// `unique_key`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
+pub struct RecursiveInfo {
+    /// How a row joins the rows one level below it, written against `$parent` and `$child`
+    pub on: SqlExpr,
+    /// The deepest level followed, unlimited when omitted
+    #[serde(default)]
+    pub max_depth: Option<u32>,
+    /// Whether each object holds the objects one level below it under the same field, rather
+    /// than every level coming back as one flat list
+    #[serde(default)]
+    pub tree: bool,
+    /// The field each object's depth is selected as, `depth` when omitted
+    #[serde(default)]
+    pub depth_field: Option<String>,
+}
+
+impl RecursiveInfo {
+    /// The field each object's depth is selected as.
+    pub fn depth_field(&self) -> &str {
+        self.depth_field.as_deref().unwrap_or("depth")
+    }
+}
+
+impl Node {
+    /// The GraphQL types an interface or union node can be, empty for any other node.
+    pub fn possible_types(&self, root: &Root) -> Vec<String> {
+        match &self.polymorphic {
+            None => vec![],
+            Some(Polymorphic::SingleTable(single_table)) => {
+                single_table.types.values().cloned().collect()
+            }
+            Some(Polymorphic::Union(union)) => union
+                .members
+                .iter()
+                .filter_map(|member| root.0.get(&member.extends))
+                .map(|member| member.type_name())
+                .collect(),
+        }
+    }
+
+    /// The node's `Field::OrderBy`s, ordered by field name.
+    pub fn default_order_by(&self) -> Vec<&OrderBy> {
+        let mut order_by = self
+            .fields
+            .iter()
+            .filter_map(|(name, field)| match field {
+                Field::OrderBy(order_by) => Some((name, order_by)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        order_by.sort_by_key(|(name, _)| *name);
+        order_by.into_iter().map(|(_, order_by)| order_by).collect()
+    }
+
+    /// The expressions of the node's `Field::Where`s, ordered by field name.
+    pub fn metadata_filters(&self) -> Vec<SqlExpr> {
+        let mut wheres = self
+            .fields
+            .iter()
+            .filter_map(|(name, field)| match field {
+                Field::Where(where_info) => Some((name, &where_info.expr)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        wheres.sort_by_key(|(name, _)| *name);
+        wheres.into_iter().map(|(_, expr)| expr.clone()).collect()
+    }
+
+    /// The GraphQL type name of this node, derived from its table when not given.
+    ///
+    /// `users` becomes `User`, `post_likes` becomes `PostLike` and `public.categories`
+    /// becomes `Category`.
+    pub fn type_name(&self) -> String {
+        if let Some(graphql_type) = &self.graphql_type {
+            return graphql_type.clone();
+        }
+        let table = self.table.rsplit('.').next().unwrap_or(&self.table);
+        let table = if let Some(stem) = table.strip_suffix("ies") {
+            format!("{}y", stem)
+        } else if table.ends_with("ss") {
+            table.to_string()
+        } else {
+            table.strip_suffix('s').unwrap_or(table).to_string()
+        };
+        table
+            .split(['_', '-'])
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                let mut chars = part.chars();
+                match chars.next() {
+                    Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                    None => String::new(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// The metadata format `Root::to_json` writes.
+pub const METADATA_VERSION: u32 = 2;
+
+/// Metadata as it is stored on disk, tagged with the format it was written in.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(into_wasm_abi, from_wasm_abi))]
+pub struct MetadataEnvelope {
+    pub version: u32,
+    pub nodes: Vec<Node>,
+}
+
+impl Root {
+    /// Writes a `MetadataEnvelope`, with nodes and fields sorted so the output is stable.
+    pub fn to_json(&self) -> Result<String, Error> {
+        let mut nodes = self.0.values().cloned().collect::<Vec<_>>();
+        nodes.sort_by(|a, b| a.alias.cmp(&b.alias));
+        let envelope = MetadataEnvelope {
+            version: METADATA_VERSION,
+            nodes,
+        };
+        serde_json::to_string_pretty(&envelope).map_err(|err| Error::from(err.to_string()))
+    }
+
+    /// Reads metadata written by `to_json`, migrating envelopes of older versions.
+    ///
+    /// A bare array of nodes is read as version 0, the format before envelopes.
+    pub fn from_json(json: &str) -> Result<Root, Error> {
+        let value = serde_json::from_str(json).map_err(|err| Error::from(err.to_string()))?;
+        let envelope = migrate_metadata(value)?;
+        Ok(Root::from(envelope.nodes))
+    }
+}
+
+/// Upgrades serialized metadata to `METADATA_VERSION`, one version at a time.
+///
+/// Version 0 is a bare array of nodes, version 1 wrapped them in a `MetadataEnvelope` and
+/// version 2 made `where` fields expressions instead of raw SQL.
+pub fn migrate_metadata(mut value: serde_json::Value) -> Result<MetadataEnvelope, Error> {
+    loop {
+        let version = match &value {
+            serde_json::Value::Array(_) => 0,
+            value => value
+                .get("version")
+                .and_then(|version| version.as_u64())
+                .ok_or(Error::from("metadata has no version"))?,
+        };
+        match version {
+            0 => value = serde_json::json!({ "version": 1, "nodes": value }),
+            // `where` fields held raw SQL before they held an expression
+            1 => {
+                for field in value["nodes"]
+                    .as_array_mut()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|node| node.get_mut("fields")?.as_object_mut())
+                    .flat_map(|fields| fields.values_mut())
+                    .filter(|field| field["kind"] == "where" && field["value"].is_string())
+                {
+                    let sql = field["value"].take();
+                    *field = serde_json::json!({
+                        "kind": "where",
+                        "expr": { "kind": "raw", "value": sql },
+                    });
+                }
+                value["version"] = 2.into();
+            }
+            2 => break,
+            version => {
+                return Err(Error::from(format!(
+                    "metadata version {} is newer than the supported version {}",
+                    version, METADATA_VERSION
+                )));
+            }
+        }
+    }
+    serde_json::from_value(value).map_err(|err| Error::from(err.to_string()))
+}
+
+/// Serializes a map with its keys in order.
+fn sorted<S: Serializer, V: Serialize>(
+    map: &HashMap<String, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
 }
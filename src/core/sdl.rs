@@ -0,0 +1,577 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use graphql_parser::schema::{
+    Definition, Directive, ObjectType, Type, TypeDefinition, Value, parse_schema,
+};
+
+use crate::core::{
+    error::Error,
+    filter::WHERE_ARGUMENT,
+    mutation::{INPUT_ARGUMENT, MutationKind, PATCH_ARGUMENT, foreign_key, key_field},
+    schema::{ColumnInfo, ExtendsNode, Field, JoinInfo, Node, Polymorphic, RecursiveInfo, Root},
+    shared_schema::{Join, JoinType, SqlExpr},
+    sort::{ORDER_BY_ARGUMENT, sort_field_name},
+};
+
+/// Prints the GraphQL type definitions implied by `root`.
+///
+/// Every node becomes an object type (nodes sharing a type name are merged), columns become
+/// scalar fields, joins become object or list fields, and each node's `field_name` becomes a
+/// field on the root `Query` type. Fields returning a `filterable` node take a `where`
+/// argument, typed by a generated `<Type>Where` input and one `<Scalar>Filter` input per
+/// scalar it filters on. Fields returning a `sortable` node take an `orderBy` argument, a list
+/// of generated `<Type>OrderBy` inputs naming a `<Type>SortField` or a to-one join. Union
+/// nodes become unions of their members, or interfaces when they have fields of their own,
+/// and single-table nodes become interfaces that each of their types implements. Each
+/// `mutable` node adds `create<Type>`, `update<Type>`, `upsert<Type>` and `delete<Type>` fields
+/// to the `Mutation` type, taking its columns in a generated `<Type>Input`, which also takes
+/// the inputs of the mutable nodes it joins by a foreign key, to create along with it.
+pub fn print_sdl(root: &Root) -> String {
+    let mut types: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+    let mut inputs: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+    let mut enums: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+    let mut query_fields = BTreeMap::new();
+    let mut mutation_fields = BTreeMap::new();
+
+    let mut nodes = root.0.values().collect::<Vec<_>>();
+    nodes.sort_by(|a, b| a.alias.cmp(&b.alias));
+    let filterable = nodes
+        .iter()
+        .filter(|node| node.filterable)
+        .map(|node| node.type_name())
+        .collect::<BTreeSet<_>>();
+    let sortable = nodes
+        .iter()
+        .filter(|node| node.sortable)
+        .map(|node| node.type_name())
+        .collect::<BTreeSet<_>>();
+    // The `where` and `orderBy` arguments of a field returning `type_name`
+    let arguments = |type_name: &str| {
+        let mut arguments = vec![];
+        if filterable.contains(type_name) {
+            arguments.push(format!("{}: {}Where", WHERE_ARGUMENT, type_name));
+        }
+        if sortable.contains(type_name) {
+            arguments.push(format!("{}: [{}OrderBy!]", ORDER_BY_ARGUMENT, type_name));
+        }
+        if arguments.is_empty() {
+            String::new()
+        } else {
+            format!("({})", arguments.join(", "))
+        }
+    };
+
+    for node in nodes {
+        let type_name = node.type_name();
+        let fields = types.entry(type_name.clone()).or_default();
+        let mut where_fields = BTreeMap::new();
+        let mut sort_fields = BTreeMap::new();
+        let mut order_by_fields = BTreeMap::new();
+        let mut input_fields = BTreeMap::new();
+        for (name, field) in &node.fields {
+            let field_type = match field {
+                Field::Column(column) => {
+                    let field_type = column_type(name, column);
+                    let scalar = field_type.trim_end_matches('!');
+                    if !scalar.starts_with('[') {
+                        input_fields.insert(name.clone(), format!(": {}", scalar));
+                        sort_fields.insert(sort_field_name(name), String::new());
+                        where_fields.insert(name.clone(), format!(": {}Filter", scalar));
+                        if node.filterable {
+                            inputs
+                                .entry(format!("{}Filter", scalar))
+                                .or_insert_with(|| scalar_filter(scalar));
+                        }
+                    }
+                    format!(": {}", field_type)
+                }
+                Field::Join(join_info) => match root.0.get(&join_info.extends.extends) {
+                    Some(target) => {
+                        let target_type = target.type_name();
+                        if filterable.contains(&target_type) {
+                            where_fields.insert(name.clone(), format!(": {}Where", target_type));
+                        }
+                        if !join_info.many && sortable.contains(&target_type) {
+                            order_by_fields
+                                .insert(name.clone(), format!(": {}OrderBy", target_type));
+                        }
+                        if target.mutable && foreign_key(node, join_info).is_some() {
+                            input_fields.insert(
+                                name.clone(),
+                                if join_info.many {
+                                    format!(": [{}Input!]", target_type)
+                                } else {
+                                    format!(": {}Input", target_type)
+                                },
+                            );
+                        }
+                        format!(
+                            "{}: {}",
+                            arguments(&target_type),
+                            output_type(target_type, join_info.many)
+                        )
+                    }
+                    // Dangling references are reported by schema validation
+                    None => continue,
+                },
+                Field::Recursive(recursive) => {
+                    fields
+                        .entry(recursive.depth_field().to_string())
+                        .or_insert_with(|| ": Int".into());
+                    format!(
+                        "{}: {}",
+                        arguments(&type_name),
+                        output_type(type_name.clone(), true)
+                    )
+                }
+                Field::Where(_) | Field::OrderBy(_) | Field::Limit(_) => continue,
+            };
+            fields.entry(name.clone()).or_insert(field_type);
+        }
+        if node.filterable {
+            where_fields.insert("AND".into(), format!(": [{}Where!]", type_name));
+            where_fields.insert("OR".into(), format!(": [{}Where!]", type_name));
+            where_fields.insert("NOT".into(), format!(": {}Where", type_name));
+            let input = inputs.entry(format!("{}Where", type_name)).or_default();
+            for (name, field_type) in where_fields {
+                input.entry(name).or_insert(field_type);
+            }
+        }
+        if node.sortable {
+            order_by_fields.insert("field".into(), format!(": {}SortField", type_name));
+            order_by_fields.insert("direction".into(), ": SortDirection".into());
+            order_by_fields.insert("nulls".into(), ": SortNulls".into());
+            let input = inputs.entry(format!("{}OrderBy", type_name)).or_default();
+            for (name, field_type) in order_by_fields {
+                input.entry(name).or_insert(field_type);
+            }
+            enums
+                .entry(format!("{}SortField", type_name))
+                .or_default()
+                .extend(sort_fields);
+            for (enum_name, values) in [
+                ("SortDirection", ["ASC", "DESC"]),
+                ("SortNulls", ["FIRST", "LAST"]),
+            ] {
+                enums.entry(enum_name.into()).or_insert_with(|| {
+                    values
+                        .into_iter()
+                        .map(|value| (value.to_string(), String::new()))
+                        .collect()
+                });
+            }
+        }
+        if node.mutable {
+            let input = inputs.entry(format!("{}Input", type_name)).or_default();
+            for (name, field_type) in input_fields {
+                input.entry(name).or_insert(field_type);
+            }
+            let mut fields = vec![(
+                MutationKind::Create,
+                format!("({}: {}Input!)", INPUT_ARGUMENT, type_name),
+            )];
+            if let Some(key) = key_field(node)
+                && let Some(Field::Column(column)) = node.fields.get(key)
+            {
+                let key = format!(
+                    "{}: {}!",
+                    key,
+                    column_type(key, column).trim_end_matches('!')
+                );
+                fields.push((
+                    MutationKind::Update,
+                    format!("({}, {}: {}Input!)", key, PATCH_ARGUMENT, type_name),
+                ));
+                fields.push((
+                    MutationKind::Upsert,
+                    format!("({}: {}Input!)", INPUT_ARGUMENT, type_name),
+                ));
+                fields.push((MutationKind::Delete, format!("({})", key)));
+            }
+            for (kind, arguments) in fields {
+                mutation_fields
+                    .entry(format!("{}{}", kind.prefix(), type_name))
+                    .or_insert_with(|| format!("{}: {}", arguments, type_name));
+            }
+        }
+        // Nodes that are only ever joined have no root field
+        if !node.field_name.is_empty() {
+            query_fields
+                .entry(node.field_name.clone())
+                .or_insert_with(|| {
+                    format!(
+                        "{}: {}",
+                        arguments(&type_name),
+                        output_type(type_name, node.many)
+                    )
+                });
+        }
+    }
+
+    // Interfaces and unions, and the interfaces each type implements
+    let mut interfaces = BTreeSet::new();
+    let mut unions = BTreeMap::new();
+    let mut implements: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for node in root.0.values() {
+        let type_name = node.type_name();
+        let fields = types.get(&type_name).cloned().unwrap_or_default();
+        let possible_types = node.possible_types(root);
+        match &node.polymorphic {
+            None => continue,
+            // A union with fields of its own is an interface its members implement
+            Some(Polymorphic::Union(_)) if fields.is_empty() => {
+                unions.insert(type_name, possible_types);
+                continue;
+            }
+            // Every type of a single table has all of its fields
+            Some(Polymorphic::SingleTable(_)) => {
+                for possible_type in &possible_types {
+                    let type_fields = types.entry(possible_type.clone()).or_default();
+                    for (name, definition) in &fields {
+                        type_fields
+                            .entry(name.clone())
+                            .or_insert_with(|| definition.clone());
+                    }
+                }
+            }
+            Some(Polymorphic::Union(_)) => {}
+        }
+        for possible_type in possible_types {
+            implements
+                .entry(possible_type)
+                .or_default()
+                .insert(type_name.clone());
+        }
+        interfaces.insert(type_name);
+    }
+
+    let mut definitions = types
+        .iter()
+        .filter(|(type_name, _)| !unions.contains_key(*type_name))
+        .map(|(type_name, fields)| {
+            if interfaces.contains(type_name) {
+                return print_type("interface", type_name, fields);
+            }
+            match implements.get(type_name) {
+                Some(interfaces) => print_type(
+                    "type",
+                    &format!(
+                        "{} implements {}",
+                        type_name,
+                        interfaces.iter().cloned().collect::<Vec<_>>().join(" & ")
+                    ),
+                    fields,
+                ),
+                None => print_type("type", type_name, fields),
+            }
+        })
+        .collect::<Vec<_>>();
+    definitions.extend(
+        unions
+            .iter()
+            .map(|(type_name, members)| format!("union {} = {}\n", type_name, members.join(" | "))),
+    );
+    definitions.push(print_type("type", "Query", &query_fields));
+    if !mutation_fields.is_empty() {
+        definitions.push(print_type("type", "Mutation", &mutation_fields));
+    }
+    definitions.extend(
+        inputs
+            .iter()
+            .map(|(input_name, fields)| print_type("input", input_name, fields)),
+    );
+    definitions.extend(
+        enums
+            .iter()
+            .map(|(enum_name, values)| print_type("enum", enum_name, values)),
+    );
+    definitions.join("\n")
+}
+
+/// Prints a type whose fields map each name to the rest of its definition, e.g. `: String`,
+/// which is empty for enum values.
+fn print_type(keyword: &str, type_name: &str, fields: &BTreeMap<String, String>) -> String {
+    let mut sdl = format!("{} {} {{\n", keyword, type_name);
+    for (name, definition) in fields {
+        sdl.push_str(&format!("  {}{}\n", name, definition));
+    }
+    sdl.push_str("}\n");
+    sdl
+}
+
+/// The operators a `where` argument accepts for a column of type `scalar`.
+fn scalar_filter(scalar: &str) -> BTreeMap<String, String> {
+    let mut operators = ["eq", "neq", "gt", "lt"]
+        .into_iter()
+        .map(|operator| (operator.to_string(), format!(": {}", scalar)))
+        .collect::<BTreeMap<_, _>>();
+    operators.insert("in".into(), format!(": [{}!]", scalar));
+    operators.insert("isNull".into(), ": Boolean".into());
+    if scalar == "String" {
+        operators.insert("contains".into(), ": String".into());
+    }
+    operators
+}
+
+fn column_type(name: &str, column: &ColumnInfo) -> String {
+    match &column.graphql_type {
+        Some(graphql_type) => graphql_type.clone(),
+        None if name == "id" => "ID".into(),
+        None => "String".into(),
+    }
+}
+
+fn output_type(type_name: String, many: bool) -> String {
+    if many {
+        format!("[{}!]!", type_name)
+    } else {
+        type_name
+    }
+}
+
+/// Reads metadata from GraphQL type definitions annotated with join-monster style directives.
+///
+/// `@sqlTable(name: "users", alias: "user_1", uniqueKey: "id")` makes an object type a node, and
+/// `@sqlFilterable` and `@sqlSortable` let the fields returning it take a `where` and an
+/// `orderBy` argument, and `@sqlMutable` adds its `create<Type>`, `update<Type>`,
+/// `upsert<Type>` and `delete<Type>` mutations, `@sqlMutable(upsertColumns: ["title"])` limiting
+/// the columns an upsert updates.
+/// `@sqlColumn(name: "full_name")` maps a scalar field to a differently named column and
+/// `@sqlJoin(on: "$parent.id = $child.author_id", alias: "user_2")` joins the node a field
+/// returns. `@sqlRecursive(on: "$parent.id = $child.parent_id", maxDepth: 5, tree: true)` makes
+/// a field returning the type itself a recursive relation, whose depth field (`depth` unless
+/// `depthField` says otherwise) is not a column. Other scalar fields are columns of the same
+/// name, and the `Query` field that returns a node is its `field_name`.
+pub fn parse_sdl(sdl: &str) -> Result<Root, Error> {
+    let doc = parse_schema::<String>(sdl).map_err(|err| err.to_string())?;
+    let objects = doc
+        .definitions
+        .iter()
+        .filter_map(|definition| match definition {
+            Definition::TypeDefinition(TypeDefinition::Object(object)) => Some(object),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let mut tables = HashMap::new();
+    for object in &objects {
+        if let Some(sql_table) = directive(&object.directives, "sqlTable") {
+            let table = string_argument(sql_table, "name")?.ok_or(format!(
+                "@sqlTable on type {} is missing the table name",
+                object.name
+            ))?;
+            let alias = string_argument(sql_table, "alias")?.unwrap_or(table.clone());
+            tables.insert(object.name.as_str(), (table, alias));
+        }
+    }
+
+    let mut root_fields = HashMap::new();
+    for object in objects.iter().filter(|object| object.name == "Query") {
+        for field in &object.fields {
+            let (type_name, many) = unwrap_type(&field.field_type);
+            if tables.contains_key(type_name) {
+                root_fields
+                    .entry(type_name)
+                    .or_insert((field.name.clone(), many));
+            }
+        }
+    }
+
+    let mut nodes = vec![];
+    for object in &objects {
+        let Some((table, alias)) = tables.get(object.name.as_str()) else {
+            continue;
+        };
+        let (field_name, many) = root_fields
+            .get(object.name.as_str())
+            .cloned()
+            .unwrap_or_default();
+        nodes.push(Node {
+            alias: alias.clone(),
+            field_name,
+            table: table.clone(),
+            graphql_type: Some(object.name.clone()),
+            many,
+            unique_key: directive(&object.directives, "sqlTable")
+                .map(|sql_table| string_argument(sql_table, "uniqueKey"))
+                .transpose()?
+                .flatten(),
+            filterable: directive(&object.directives, "sqlFilterable").is_some(),
+            sortable: directive(&object.directives, "sqlSortable").is_some(),
+            mutable: directive(&object.directives, "sqlMutable").is_some(),
+            upsert_columns: directive(&object.directives, "sqlMutable")
+                .map(|sql_mutable| string_list_argument(sql_mutable, "upsertColumns"))
+                .transpose()?
+                .flatten(),
+            polymorphic: None,
+            fields: node_fields(object, &tables)?,
+        });
+    }
+    Ok(Root::from(nodes))
+}
+
+fn node_fields(
+    object: &ObjectType<'_, String>,
+    tables: &HashMap<&str, (String, String)>,
+) -> Result<HashMap<String, Field>, Error> {
+    let mut fields = HashMap::new();
+    for field in &object.fields {
+        let (type_name, many) = unwrap_type(&field.field_type);
+        let metadata = if let Some(sql_recursive) = directive(&field.directives, "sqlRecursive") {
+            if type_name != object.name {
+                return Err(Error::from(format!(
+                    "{}.{} is recursive, so it must return {}",
+                    object.name, field.name, object.name
+                )));
+            }
+            let on = string_argument(sql_recursive, "on")?.ok_or(format!(
+                "@sqlRecursive on {}.{} is missing its on condition",
+                object.name, field.name
+            ))?;
+            let max_depth = match sql_recursive
+                .arguments
+                .iter()
+                .find(|(argument, _)| argument == "maxDepth")
+            {
+                Some((_, Value::Int(max_depth))) => Some(
+                    max_depth
+                        .as_i64()
+                        .and_then(|max_depth| u32::try_from(max_depth).ok())
+                        .ok_or(format!(
+                            "@sqlRecursive(maxDepth:) on {}.{} must be a positive integer",
+                            object.name, field.name
+                        ))?,
+                ),
+                Some((_, value)) => {
+                    return Err(Error::from(format!(
+                        "@sqlRecursive(maxDepth:) must be an integer, got {}",
+                        value
+                    )));
+                }
+                None => None,
+            };
+            let tree = sql_recursive
+                .arguments
+                .iter()
+                .any(|(argument, value)| argument == "tree" && *value == Value::Boolean(true));
+            Field::Recursive(RecursiveInfo {
+                on: SqlExpr::Raw(on.into()),
+                max_depth,
+                tree,
+                depth_field: string_argument(sql_recursive, "depthField")?,
+            })
+        } else if let Some(sql_join) = directive(&field.directives, "sqlJoin") {
+            let (_, extends) = tables.get(type_name).ok_or(format!(
+                "{}.{} joins {} which has no @sqlTable",
+                object.name, field.name, type_name
+            ))?;
+            let on = string_argument(sql_join, "on")?.ok_or(format!(
+                "@sqlJoin on {}.{} is missing its on condition",
+                object.name, field.name
+            ))?;
+            Field::Join(JoinInfo {
+                extends: ExtendsNode {
+                    alias: string_argument(sql_join, "alias")?.unwrap_or(extends.clone()),
+                    field_name: field.name.clone(),
+                    extends: extends.clone(),
+                },
+                join: Join {
+                    on: SqlExpr::Raw(on.into()),
+                    kind: JoinType::LeftJoin,
+                },
+                many,
+                cost: None,
+            })
+        } else if tables.contains_key(type_name) {
+            return Err(Error::from(format!(
+                "{}.{} returns {} but has no @sqlJoin",
+                object.name, field.name, type_name
+            )));
+        } else {
+            let column = directive(&field.directives, "sqlColumn")
+                .map(|sql_column| string_argument(sql_column, "name"))
+                .transpose()?
+                .flatten();
+            Field::Column(ColumnInfo {
+                column: column.unwrap_or(field.name.clone()),
+                table: None,
+                graphql_type: Some(type_name.to_string()),
+                cost: None,
+            })
+        };
+        fields.insert(field.name.clone(), metadata);
+    }
+    // Depth fields are filled in by their recursive relation
+    let depth_fields = fields
+        .values()
+        .filter_map(|field| match field {
+            Field::Recursive(recursive) => Some(recursive.depth_field().to_string()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    for depth_field in depth_fields {
+        if let Some(Field::Column(_)) = fields.get(&depth_field) {
+            fields.remove(&depth_field);
+        }
+    }
+    Ok(fields)
+}
+
+fn directive<'d, 'a>(
+    directives: &'d [Directive<'a, String>],
+    name: &str,
+) -> Option<&'d Directive<'a, String>> {
+    directives.iter().find(|directive| directive.name == name)
+}
+
+fn string_argument(directive: &Directive<'_, String>, name: &str) -> Result<Option<String>, Error> {
+    match directive
+        .arguments
+        .iter()
+        .find(|(argument, _)| argument == name)
+    {
+        Some((_, Value::String(value))) => Ok(Some(value.clone())),
+        Some((_, value)) => Err(Error::from(format!(
+            "@{}({}:) must be a string, got {}",
+            directive.name, name, value
+        ))),
+        None => Ok(None),
+    }
+}
+
+fn string_list_argument(
+    directive: &Directive<'_, String>,
+    name: &str,
+) -> Result<Option<Vec<String>>, Error> {
+    let error = |value: &Value<'_, String>| {
+        Error::from(format!(
+            "@{}({}:) must be a list of strings, got {}",
+            directive.name, name, value
+        ))
+    };
+    match directive
+        .arguments
+        .iter()
+        .find(|(argument, _)| argument == name)
+    {
+        Some((_, Value::List(values))) => values
+            .iter()
+            .map(|value| match value {
+                Value::String(value) => Ok(value.clone()),
+                other => Err(error(other)),
+            })
+            .collect::<Result<_, _>>()
+            .map(Some),
+        Some((_, value)) => Err(error(value)),
+        None => Ok(None),
+    }
+}
+
+/// The named type under any list and non-null wrappers, and whether it is a list.
+fn unwrap_type<'t>(field_type: &'t Type<'_, String>) -> (&'t str, bool) {
+    match field_type {
+        Type::NamedType(name) => (name, false),
+        Type::NonNullType(inner) => unwrap_type(inner),
+        Type::ListType(inner) => (unwrap_type(inner).0, true),
+    }
+}
@@ -1,9 +1,21 @@
-use sea_query::{Expr, ExprTrait, IntoLikeExpr};
+use std::{collections::HashMap, ops::Range};
+
+use sea_query::{
+    Alias, BinOper, Expr, ExprTrait, Func, IntoLikeExpr, Query, extension::postgres::PgExpr,
+};
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "wasm")]
 use tsify::Tsify;
 
-#[derive(Tsify, Deserialize, Serialize, Clone, Debug)]
-#[tsify(from_wasm_abi)]
+use crate::core::schema::BuilderType;
+
+/// Stands for the alias of the node a join condition is written on, wherever it is joined.
+pub const PARENT: &str = "$parent";
+/// Stands for the alias of the node a join condition joins, wherever it is joined.
+pub const CHILD: &str = "$child";
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct ColumnRef {
     pub column: String,
     pub table: Option<String>,
@@ -18,15 +30,15 @@ impl From<String> for ColumnRef {
     }
 }
 
-#[derive(Tsify, Deserialize, Serialize, Clone, Debug)]
-#[tsify(from_wasm_abi)]
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct SqlParam {
     pub name: String,
     pub value: SqlValue,
 }
 
-#[derive(Tsify, Deserialize, Serialize, Clone, Debug)]
-#[tsify(from_wasm_abi)]
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 #[serde(tag = "kind", rename_all = "snake_case")]
 pub enum SqlValue {
     Int(Value<Option<i32>>),
@@ -36,8 +48,8 @@ pub enum SqlValue {
     // etc.
 }
 
-#[derive(Tsify, Deserialize, Serialize, Clone, Debug)]
-#[tsify(from_wasm_abi)]
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct Value<T> {
     pub value: T,
 }
@@ -51,46 +63,168 @@ impl<T> From<T> for Value<T> {
 impl From<&SqlValue> for sea_query::Value {
     fn from(v: &SqlValue) -> sea_query::Value {
         match v {
-            SqlValue::Int(i) => sea_query::Value::Int(i.value.clone()), // or Int64 if needed
-            SqlValue::Float(f) => sea_query::Value::Double(f.value.clone()),
-            SqlValue::Text(s) => sea_query::Value::String(match s.value.clone() {
-                Some(s) => Some(Box::new(s)),
-                None => None,
-            }),
-            SqlValue::Bool(b) => sea_query::Value::Bool(b.value.clone()),
+            SqlValue::Int(i) => sea_query::Value::Int(i.value), // or Int64 if needed
+            SqlValue::Float(f) => sea_query::Value::Double(f.value),
+            SqlValue::Text(s) => sea_query::Value::String(s.value.clone().map(Box::new)),
+            SqlValue::Bool(b) => sea_query::Value::Bool(b.value),
         }
     }
 }
 
-#[derive(Tsify, Serialize, Deserialize, Debug, Clone)]
-#[tsify(from_wasm_abi)]
+impl TryFrom<&sea_query::Value> for SqlValue {
+    type Error = String;
+
+    fn try_from(v: &sea_query::Value) -> Result<Self, Self::Error> {
+        use sea_query::Value as V;
+        // An integer `SqlValue::Int` can't hold is an error rather than a rounded float
+        let int = |value: Option<i128>| match value.map(i32::try_from).transpose() {
+            Ok(value) => Ok(SqlValue::Int(value.into())),
+            Err(_) => Err(format!("SQL parameter value {:?} is out of range", v)),
+        };
+        Ok(match v {
+            V::Bool(b) => SqlValue::Bool((*b).into()),
+            V::TinyInt(i) => int(i.map(i128::from))?,
+            V::SmallInt(i) => int(i.map(i128::from))?,
+            V::Int(i) => int(i.map(i128::from))?,
+            V::BigInt(i) => int(i.map(i128::from))?,
+            V::TinyUnsigned(i) => int(i.map(i128::from))?,
+            V::SmallUnsigned(i) => int(i.map(i128::from))?,
+            V::Unsigned(i) => int(i.map(i128::from))?,
+            V::BigUnsigned(i) => int(i.map(i128::from))?,
+            V::Float(f) => SqlValue::Float(f.map(f64::from).into()),
+            V::Double(f) => SqlValue::Float((*f).into()),
+            V::String(s) => SqlValue::Text(s.as_deref().cloned().into()),
+            V::Char(c) => SqlValue::Text(c.map(String::from).into()),
+            other => return Err(format!("Unsupported SQL parameter value {:?}", other)),
+        })
+    }
+}
+
+impl TryFrom<&serde_json::Value> for SqlValue {
+    type Error = String;
+
+    fn try_from(v: &serde_json::Value) -> Result<Self, Self::Error> {
+        Ok(match v {
+            serde_json::Value::Null => SqlValue::Text(None.into()),
+            serde_json::Value::Bool(b) => SqlValue::Bool(Some(*b).into()),
+            serde_json::Value::Number(n) => match n.as_i64().map(i32::try_from) {
+                Some(Ok(i)) => SqlValue::Int(Some(i).into()),
+                _ => SqlValue::Float(n.as_f64().into()),
+            },
+            serde_json::Value::String(s) => SqlValue::Text(Some(s.clone()).into()),
+            other => return Err(format!("Unsupported SQL parameter value {}", other)),
+        })
+    }
+}
+
+impl<'a> TryFrom<&graphql_parser::query::Value<'a, &'a str>> for SqlValue {
+    type Error = String;
+
+    fn try_from(v: &graphql_parser::query::Value<'a, &'a str>) -> Result<Self, Self::Error> {
+        use graphql_parser::query::Value as V;
+        Ok(match v {
+            V::Null => SqlValue::Text(None.into()),
+            V::Boolean(b) => SqlValue::Bool(Some(*b).into()),
+            V::Int(n) => match n.as_i64().map(i32::try_from) {
+                Some(Ok(i)) => SqlValue::Int(Some(i).into()),
+                _ => SqlValue::Float(n.as_i64().map(|i| i as f64).into()),
+            },
+            V::Float(f) => SqlValue::Float(Some(*f).into()),
+            V::String(s) => SqlValue::Text(Some(s.clone()).into()),
+            V::Enum(e) => SqlValue::Text(Some(e.to_string()).into()),
+            other => return Err(format!("Unsupported SQL parameter value {}", other)),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct BinaryExpr {
     pub left: Box<SqlExpr>,
     pub right: Box<SqlExpr>,
 }
 
-#[derive(Tsify, Serialize, Deserialize, Debug, Clone)]
-#[tsify(from_wasm_abi)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct LikeExpr {
     pub left: Box<SqlExpr>,
     pub right: String,
 }
 
-#[derive(Tsify, Serialize, Deserialize, Debug, Clone)]
-#[tsify(from_wasm_abi)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct InExpr {
     pub left: Box<SqlExpr>,
     pub right: Vec<SqlExpr>,
 }
 
-#[derive(Tsify, Serialize, Deserialize, Debug, Clone)]
-#[tsify(from_wasm_abi)]
+/// Whether the text `left` contains `right`, which unlike a `like` pattern can be bound.
+/// `%` and `_` in `right` match only themselves.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
+pub struct ContainsExpr {
+    pub left: Box<SqlExpr>,
+    pub right: Box<SqlExpr>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
+pub struct NotInExpr {
+    pub left: Box<SqlExpr>,
+    pub right: Vec<SqlExpr>,
+}
+
+/// `expr BETWEEN low AND high`, bounds included.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
+pub struct BetweenExpr {
+    pub expr: Box<SqlExpr>,
+    pub low: Box<SqlExpr>,
+    pub high: Box<SqlExpr>,
+}
+
+/// A call of the SQL function `name`, e.g. `lower`, `coalesce` or `now`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
+pub struct FunctionExpr {
+    pub name: String,
+    #[serde(default)]
+    pub args: Vec<SqlExpr>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
+pub struct CaseWhen {
+    pub when: SqlExpr,
+    pub then: SqlExpr,
+}
+
+/// `CASE WHEN ... THEN ... ELSE otherwise END`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
+pub struct CaseExpr {
+    pub branches: Vec<CaseWhen>,
+    #[serde(default)]
+    pub otherwise: Option<Box<SqlExpr>>,
+}
+
+/// `EXISTS (SELECT 1 FROM table AS alias WHERE condition)`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
+pub struct ExistsExpr {
+    pub table: String,
+    pub alias: String,
+    pub condition: Box<SqlExpr>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct NotExpr {
     pub expr: Box<SqlExpr>,
 }
 
-#[derive(Tsify, Serialize, Deserialize, Debug, Clone)]
-#[tsify(from_wasm_abi)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 #[serde(tag = "kind", rename_all = "snake_case")]
 pub enum SqlExpr {
     /// e.g. "user.id"
@@ -114,83 +248,135 @@ pub enum SqlExpr {
     Not(NotExpr),
 
     Like(LikeExpr),
+    /// Case-insensitive `LIKE`, emulated with `LOWER` where the dialect has no `ILIKE`
+    #[serde(rename = "ilike")]
+    ILike(LikeExpr),
+    Contains(ContainsExpr),
     In(InExpr),
+    NotIn(NotInExpr),
+    Between(BetweenExpr),
     IsNull(IsNullExpr),
     IsNotNull(IsNotNullExpr),
+    Exists(ExistsExpr),
+
+    Add(AddExpr),
+    Sub(SubExpr),
+    Mul(MulExpr),
+    Div(DivExpr),
+
+    Function(FunctionExpr),
+    Case(CaseExpr),
 }
 
-#[derive(Tsify, Serialize, Deserialize, Debug, Clone)]
-#[tsify(from_wasm_abi)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct EqExpr {
     pub left: Box<SqlExpr>,
     pub right: Box<SqlExpr>,
 }
 
-#[derive(Tsify, Serialize, Deserialize, Debug, Clone)]
-#[tsify(from_wasm_abi)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct NeqExpr {
     pub left: Box<SqlExpr>,
     pub right: Box<SqlExpr>,
 }
 
-#[derive(Tsify, Serialize, Deserialize, Debug, Clone)]
-#[tsify(from_wasm_abi)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct GtExpr {
     pub left: Box<SqlExpr>,
     pub right: Box<SqlExpr>,
 }
 
-#[derive(Tsify, Serialize, Deserialize, Debug, Clone)]
-#[tsify(from_wasm_abi)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct GteExpr {
     pub left: Box<SqlExpr>,
     pub right: Box<SqlExpr>,
 }
 
-#[derive(Tsify, Serialize, Deserialize, Debug, Clone)]
-#[tsify(from_wasm_abi)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct LtExpr {
     pub left: Box<SqlExpr>,
     pub right: Box<SqlExpr>,
 }
 
-#[derive(Tsify, Serialize, Deserialize, Debug, Clone)]
-#[tsify(from_wasm_abi)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct LteExpr {
     pub left: Box<SqlExpr>,
     pub right: Box<SqlExpr>,
 }
 
+// Arithmetic
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
+pub struct AddExpr {
+    pub left: Box<SqlExpr>,
+    pub right: Box<SqlExpr>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
+pub struct SubExpr {
+    pub left: Box<SqlExpr>,
+    pub right: Box<SqlExpr>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
+pub struct MulExpr {
+    pub left: Box<SqlExpr>,
+    pub right: Box<SqlExpr>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
+pub struct DivExpr {
+    pub left: Box<SqlExpr>,
+    pub right: Box<SqlExpr>,
+}
+
 // Logic
 
-#[derive(Tsify, Serialize, Deserialize, Debug, Clone)]
-#[tsify(from_wasm_abi)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct AndExpr {
     pub left: Box<SqlExpr>,
     pub right: Box<SqlExpr>,
 }
 
-#[derive(Tsify, Serialize, Deserialize, Debug, Clone)]
-#[tsify(from_wasm_abi)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct OrExpr {
     pub left: Box<SqlExpr>,
     pub right: Box<SqlExpr>,
 }
 
-#[derive(Tsify, Serialize, Deserialize, Debug, Clone)]
-#[tsify(from_wasm_abi)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct IsNullExpr {
     pub expr: Box<SqlExpr>,
 }
 
-#[derive(Tsify, Serialize, Deserialize, Debug, Clone)]
-#[tsify(from_wasm_abi)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct IsNotNullExpr {
     pub expr: Box<SqlExpr>,
 }
 
 impl SqlExpr {
+    /// Renders this expression for Postgres.
     pub fn to_sea_expr(&self) -> sea_query::SimpleExpr {
+        self.to_sea_expr_for(&BuilderType::default())
+    }
+
+    /// Renders this expression for `builder`, emulating operators the dialect lacks.
+    pub fn to_sea_expr_for(&self, builder: &BuilderType) -> sea_query::SimpleExpr {
+        let expr = |expr: &SqlExpr| expr.to_sea_expr_for(builder);
         match self {
             SqlExpr::Column(column_ref) => match column_ref.table {
                 Some(ref table) => {
@@ -200,56 +386,393 @@ impl SqlExpr {
                 }
                 None => Expr::column(sea_query::Alias::new(column_ref.column.clone())),
             },
-            SqlExpr::Param(SqlParam { name, value }) => {
-                Expr::cust_with_values(format!("${}", name), &[value.clone()]).into()
+            SqlExpr::Param(SqlParam { value, .. }) => {
+                Expr::val(sea_query::Value::from(value)).into()
             }
 
             SqlExpr::Literal(val) => Expr::val(sea_query::Value::from(val.value.clone())).into(),
 
-            SqlExpr::Raw(raw) => Expr::cust(raw.value.clone()).into(),
+            SqlExpr::Raw(raw) => Expr::cust(quote_renamed(&raw.value, builder)),
 
-            SqlExpr::Eq(EqExpr { left, right }) => Expr::expr(left.to_sea_expr())
-                .eq(right.to_sea_expr())
-                .into(),
-            SqlExpr::Neq(NeqExpr { left, right }) => Expr::expr(left.to_sea_expr())
-                .ne(right.to_sea_expr())
-                .into(),
-            SqlExpr::Gt(GtExpr { left, right }) => Expr::expr(left.to_sea_expr())
-                .gt(right.to_sea_expr())
-                .into(),
-            SqlExpr::Gte(GteExpr { left, right }) => Expr::expr(left.to_sea_expr())
-                .gte(right.to_sea_expr())
-                .into(),
-            SqlExpr::Lt(LtExpr { left, right }) => Expr::expr(left.to_sea_expr())
-                .lt(right.to_sea_expr())
-                .into(),
-            SqlExpr::Lte(LteExpr { left, right }) => Expr::expr(left.to_sea_expr())
-                .lte(right.to_sea_expr())
-                .into(),
+            SqlExpr::Eq(EqExpr { left, right }) => Expr::expr(expr(left)).eq(expr(right)),
+            SqlExpr::Neq(NeqExpr { left, right }) => Expr::expr(expr(left)).ne(expr(right)),
+            SqlExpr::Gt(GtExpr { left, right }) => Expr::expr(expr(left)).gt(expr(right)),
+            SqlExpr::Gte(GteExpr { left, right }) => Expr::expr(expr(left)).gte(expr(right)),
+            SqlExpr::Lt(LtExpr { left, right }) => Expr::expr(expr(left)).lt(expr(right)),
+            SqlExpr::Lte(LteExpr { left, right }) => Expr::expr(expr(left)).lte(expr(right)),
 
-            SqlExpr::And(AndExpr { left, right }) => Expr::expr(left.to_sea_expr())
-                .and(right.to_sea_expr())
-                .into(),
-            SqlExpr::Or(OrExpr { left, right }) => Expr::expr(left.to_sea_expr())
-                .or(right.to_sea_expr())
-                .into(),
-            SqlExpr::Not(NotExpr { expr }) => Expr::expr(expr.to_sea_expr()).not().into(),
+            SqlExpr::And(AndExpr { left, right }) => Expr::expr(expr(left)).and(expr(right)),
+            SqlExpr::Or(OrExpr { left, right }) => Expr::expr(expr(left)).or(expr(right)),
+            SqlExpr::Not(NotExpr { expr: inner }) => Expr::expr(expr(inner)).not(),
 
-            SqlExpr::Like(LikeExpr { left, right }) => Expr::expr(left.to_sea_expr())
-                .like(right.into_like_expr())
-                .into(),
+            SqlExpr::Like(LikeExpr { left, right }) => {
+                Expr::expr(expr(left)).like(right.into_like_expr())
+            }
+            SqlExpr::ILike(LikeExpr { left, right }) => match builder {
+                BuilderType::Postgres => Expr::expr(expr(left)).ilike(right.into_like_expr()),
+                BuilderType::MySql | BuilderType::Sqlite => Expr::expr(Func::lower(expr(left)))
+                    .binary(BinOper::Like, Func::lower(Expr::val(right.as_str()))),
+            },
 
+            SqlExpr::Contains(ContainsExpr { left, right }) => {
+                // Custom SQL marks where `left` and `right` go with the dialect's placeholder.
+                // `%` and `_` in `right` are escaped with `!` so they match themselves.
+                let contains = match builder {
+                    BuilderType::Postgres => {
+                        "$1 LIKE ('%' || REPLACE(REPLACE(REPLACE($2, '!', '!!'), '%', '!%'), '_', '!_') || '%') ESCAPE '!'"
+                    }
+                    BuilderType::Sqlite => {
+                        "? LIKE ('%' || REPLACE(REPLACE(REPLACE(?, '!', '!!'), '%', '!%'), '_', '!_') || '%') ESCAPE '!'"
+                    }
+                    BuilderType::MySql => {
+                        "? LIKE CONCAT('%', REPLACE(REPLACE(REPLACE(?, '!', '!!'), '%', '!%'), '_', '!_'), '%') ESCAPE '!'"
+                    }
+                };
+                Expr::cust_with_exprs(contains, [expr(left), expr(right)])
+            }
             SqlExpr::In(InExpr { left, right }) => {
-                let values = right.iter().map(|e| e.to_sea_expr()).collect::<Vec<_>>();
-                Expr::expr(left.to_sea_expr()).is_in(values).into()
+                Expr::expr(expr(left)).is_in(right.iter().map(expr))
+            }
+            SqlExpr::NotIn(NotInExpr { left, right }) => {
+                Expr::expr(expr(left)).is_not_in(right.iter().map(expr))
+            }
+            SqlExpr::Between(BetweenExpr {
+                expr: inner,
+                low,
+                high,
+            }) => Expr::expr(expr(inner)).between(expr(low), expr(high)),
+
+            SqlExpr::IsNull(IsNullExpr { expr: inner }) => Expr::expr(expr(inner)).is_null(),
+            SqlExpr::IsNotNull(IsNotNullExpr { expr: inner }) => {
+                Expr::expr(expr(inner)).is_not_null()
             }
+            SqlExpr::Exists(ExistsExpr {
+                table,
+                alias,
+                condition,
+            }) => Expr::exists(
+                Query::select()
+                    .expr(Expr::cust("1"))
+                    .from_as(Alias::new(table), Alias::new(alias))
+                    .and_where(expr(condition))
+                    .to_owned(),
+            ),
+
+            SqlExpr::Add(AddExpr { left, right }) => Expr::expr(expr(left)).add(expr(right)),
+            SqlExpr::Sub(SubExpr { left, right }) => Expr::expr(expr(left)).sub(expr(right)),
+            SqlExpr::Mul(MulExpr { left, right }) => Expr::expr(expr(left)).mul(expr(right)),
+            SqlExpr::Div(DivExpr { left, right }) => Expr::expr(expr(left)).div(expr(right)),
+
+            SqlExpr::Function(FunctionExpr { name, args }) => Func::cust(Alias::new(name))
+                .args(args.iter().map(expr))
+                .into(),
+            SqlExpr::Case(CaseExpr {
+                branches,
+                otherwise,
+            }) => {
+                let mut branches = branches.iter();
+                match (branches.next(), otherwise) {
+                    (Some(first), otherwise) => {
+                        let mut case = Expr::case(expr(&first.when), expr(&first.then));
+                        for branch in branches {
+                            case = case.case(expr(&branch.when), expr(&branch.then));
+                        }
+                        match otherwise {
+                            Some(otherwise) => case.finally(expr(otherwise)).into(),
+                            None => case.into(),
+                        }
+                    }
+                    // A CASE needs a WHEN, and without one it is always its ELSE
+                    (None, Some(otherwise)) => expr(otherwise),
+                    (None, None) => Expr::cust("NULL"),
+                }
+            }
+        }
+    }
+
+    /// The direct sub-expressions of this expression.
+    pub fn children(&self) -> Vec<&SqlExpr> {
+        match self {
+            SqlExpr::Column(_) | SqlExpr::Param(_) | SqlExpr::Literal(_) | SqlExpr::Raw(_) => {
+                vec![]
+            }
+            SqlExpr::Eq(EqExpr { left, right })
+            | SqlExpr::Neq(NeqExpr { left, right })
+            | SqlExpr::Gt(GtExpr { left, right })
+            | SqlExpr::Gte(GteExpr { left, right })
+            | SqlExpr::Lt(LtExpr { left, right })
+            | SqlExpr::Lte(LteExpr { left, right })
+            | SqlExpr::And(AndExpr { left, right })
+            | SqlExpr::Or(OrExpr { left, right })
+            | SqlExpr::Add(AddExpr { left, right })
+            | SqlExpr::Sub(SubExpr { left, right })
+            | SqlExpr::Mul(MulExpr { left, right })
+            | SqlExpr::Div(DivExpr { left, right })
+            | SqlExpr::Contains(ContainsExpr { left, right }) => vec![left, right],
+            SqlExpr::Not(NotExpr { expr })
+            | SqlExpr::IsNull(IsNullExpr { expr })
+            | SqlExpr::IsNotNull(IsNotNullExpr { expr }) => vec![expr],
+            SqlExpr::Like(LikeExpr { left, .. }) | SqlExpr::ILike(LikeExpr { left, .. }) => {
+                vec![left]
+            }
+            SqlExpr::In(InExpr { left, right }) | SqlExpr::NotIn(NotInExpr { left, right }) => {
+                std::iter::once(left.as_ref()).chain(right.iter()).collect()
+            }
+            SqlExpr::Between(BetweenExpr { expr, low, high }) => vec![expr, low, high],
+            SqlExpr::Exists(ExistsExpr { condition, .. }) => vec![condition],
+            SqlExpr::Function(FunctionExpr { args, .. }) => args.iter().collect(),
+            SqlExpr::Case(CaseExpr {
+                branches,
+                otherwise,
+            }) => branches
+                .iter()
+                .flat_map(|branch| [&branch.when, &branch.then])
+                .chain(otherwise.as_deref())
+                .collect(),
+        }
+    }
 
-            SqlExpr::IsNull(IsNullExpr { expr }) => Expr::expr(expr.to_sea_expr()).is_null().into(),
-            SqlExpr::IsNotNull(IsNotNullExpr { expr }) => {
-                Expr::expr(expr.to_sea_expr()).is_not_null().into()
+    /// The direct sub-expressions of this expression, mutably.
+    pub fn children_mut(&mut self) -> Vec<&mut SqlExpr> {
+        match self {
+            SqlExpr::Column(_) | SqlExpr::Param(_) | SqlExpr::Literal(_) | SqlExpr::Raw(_) => {
+                vec![]
+            }
+            SqlExpr::Eq(EqExpr { left, right })
+            | SqlExpr::Neq(NeqExpr { left, right })
+            | SqlExpr::Gt(GtExpr { left, right })
+            | SqlExpr::Gte(GteExpr { left, right })
+            | SqlExpr::Lt(LtExpr { left, right })
+            | SqlExpr::Lte(LteExpr { left, right })
+            | SqlExpr::And(AndExpr { left, right })
+            | SqlExpr::Or(OrExpr { left, right })
+            | SqlExpr::Add(AddExpr { left, right })
+            | SqlExpr::Sub(SubExpr { left, right })
+            | SqlExpr::Mul(MulExpr { left, right })
+            | SqlExpr::Div(DivExpr { left, right })
+            | SqlExpr::Contains(ContainsExpr { left, right }) => vec![left, right],
+            SqlExpr::Not(NotExpr { expr })
+            | SqlExpr::IsNull(IsNullExpr { expr })
+            | SqlExpr::IsNotNull(IsNotNullExpr { expr }) => vec![expr],
+            SqlExpr::Like(LikeExpr { left, .. }) | SqlExpr::ILike(LikeExpr { left, .. }) => {
+                vec![left]
+            }
+            SqlExpr::In(InExpr { left, right }) | SqlExpr::NotIn(NotInExpr { left, right }) => {
+                std::iter::once(left.as_mut())
+                    .chain(right.iter_mut())
+                    .collect()
+            }
+            SqlExpr::Between(BetweenExpr { expr, low, high }) => vec![expr, low, high],
+            SqlExpr::Exists(ExistsExpr { condition, .. }) => vec![condition],
+            SqlExpr::Function(FunctionExpr { args, .. }) => args.iter_mut().collect(),
+            SqlExpr::Case(CaseExpr {
+                branches,
+                otherwise,
+            }) => branches
+                .iter_mut()
+                .flat_map(|branch| [&mut branch.when, &mut branch.then])
+                .chain(otherwise.as_deref_mut())
+                .collect(),
+        }
+    }
+
+    /// The table aliases this expression refers to, including `"alias".column` references
+    /// written inside raw SQL.
+    pub fn table_refs(&self) -> Vec<String> {
+        let mut refs = match self {
+            SqlExpr::Column(ColumnRef {
+                table: Some(table), ..
+            }) => vec![table.clone()],
+            SqlExpr::Raw(raw) => raw_table_refs(&raw.value),
+            _ => vec![],
+        };
+        for child in self.children() {
+            refs.extend(child.table_refs());
+        }
+        // The table of an EXISTS subquery is only in scope inside it
+        if let SqlExpr::Exists(ExistsExpr { alias, .. }) = self {
+            refs.retain(|table| table != alias);
+        }
+        refs
+    }
+
+    /// Replaces the value of every `Param` with a marked text naming it, so the positions it
+    /// renders at can be read off the rendered values with `marked_param`.
+    pub fn mark_params(&mut self) {
+        if let SqlExpr::Param(param) = self {
+            param.value =
+                SqlValue::Text(Some(format!("{}{}", PARAM_MARK, param_key(&param.name))).into());
+        }
+        for child in self.children_mut() {
+            child.mark_params();
+        }
+    }
+
+    /// The `Param`s of this expression, in the order they are nested.
+    pub fn params(&self) -> Vec<&SqlParam> {
+        let mut params = match self {
+            SqlExpr::Param(param) => vec![param],
+            _ => vec![],
+        };
+        for child in self.children() {
+            params.extend(child.params());
+        }
+        params
+    }
+
+    /// Whether this expression binds any `Param`.
+    pub fn has_params(&self) -> bool {
+        matches!(self, SqlExpr::Param(_)) || self.children().iter().any(|child| child.has_params())
+    }
+
+    /// Replaces the value of every `Param` whose name is in `values`.
+    pub fn bind_params(&mut self, values: &HashMap<String, SqlValue>) {
+        if let SqlExpr::Param(param) = self
+            && let Some(value) = values.get(param_key(&param.name))
+        {
+            param.value = value.clone();
+        }
+        for child in self.children_mut() {
+            child.bind_params(values);
+        }
+    }
+
+    /// Qualifies every column reference without a table with `table`.
+    pub fn resolve_columns(&mut self, table: &str) {
+        match self {
+            SqlExpr::Column(ColumnRef {
+                table: table_ref @ None,
+                ..
+            }) => *table_ref = Some(table.to_string()),
+            // Columns of an EXISTS subquery belong to its own table
+            SqlExpr::Exists(ExistsExpr {
+                alias, condition, ..
+            }) => condition.resolve_columns(alias),
+            _ => {}
+        }
+        for child in self.children_mut() {
+            child.resolve_columns(table);
+        }
+    }
+
+    /// Points every table reference found in `renames` at its new alias.
+    pub fn rename_tables(&mut self, renames: &HashMap<String, String>) {
+        match self {
+            SqlExpr::Column(ColumnRef {
+                table: Some(table), ..
+            }) => {
+                if let Some(new_name) = renames.get(table) {
+                    *table = new_name.clone();
+                }
+            }
+            SqlExpr::Raw(raw) => raw.value = rename_raw_table_refs(&raw.value, renames),
+            _ => {}
+        }
+        for child in self.children_mut() {
+            child.rename_tables(renames);
+        }
+    }
+}
+
+/// The variable a `Param` is bound from, so metadata can name it `$userId` or `userId`.
+pub fn param_key(name: &str) -> &str {
+    name.strip_prefix('$').unwrap_or(name)
+}
+
+/// Finds the identifiers qualifying a column in a raw SQL string, i.e. `a` and `b` in
+/// `"a".id = b.a_id`. String literals are skipped.
+pub fn raw_table_refs(sql: &str) -> Vec<String> {
+    raw_table_ref_spans(sql)
+        .into_iter()
+        .map(|(_, ident)| ident)
+        .collect()
+}
+
+/// Starts the text `SqlExpr::mark_params` binds a param to.
+const PARAM_MARK: char = '\u{2}';
+
+/// The name of the param a value rendered from a marked expression was bound for, `None` for
+/// literals.
+pub fn marked_param(value: &sea_query::Value) -> Option<&str> {
+    match value {
+        sea_query::Value::String(Some(text)) => text.strip_prefix(PARAM_MARK),
+        _ => None,
+    }
+}
+
+/// Stands in for the quotes around a renamed qualifier until the dialect is known.
+const RENAMED: char = '\u{1}';
+
+/// Rewrites the table qualifiers of a raw SQL string found in `renames`. The new names are
+/// quoted with the dialect's quotes when the expression is rendered.
+pub fn rename_raw_table_refs(sql: &str, renames: &HashMap<String, String>) -> String {
+    let chars = sql.chars().collect::<Vec<_>>();
+    let mut renamed = String::with_capacity(sql.len());
+    let mut last = 0;
+    for (span, ident) in raw_table_ref_spans(sql) {
+        if let Some(new_name) = renames.get(&ident) {
+            renamed.extend(&chars[last..span.start]);
+            renamed.push_str(&format!("{}{}{}", RENAMED, new_name, RENAMED));
+            last = span.end;
+        }
+    }
+    renamed.extend(&chars[last..]);
+    renamed
+}
+
+/// Quotes the qualifiers `rename_raw_table_refs` renamed as identifiers of `builder`.
+fn quote_renamed(sql: &str, builder: &BuilderType) -> String {
+    let quote = match builder {
+        BuilderType::MySql => "`",
+        BuilderType::Postgres | BuilderType::Sqlite => "\"",
+    };
+    sql.replace(RENAMED, quote)
+}
+
+/// The char ranges (quotes included) and names of the table qualifiers in a raw SQL string.
+fn raw_table_ref_spans(sql: &str) -> Vec<(Range<usize>, String)> {
+    let chars = sql.chars().collect::<Vec<_>>();
+    let mut spans = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let (ident, end) = match chars[i] {
+            '\'' => {
+                i += 1;
+                while i < chars.len() && chars[i] != '\'' {
+                    i += 1;
+                }
+                i += 1;
+                continue;
+            }
+            quote @ ('"' | '`' | RENAMED) => {
+                let mut end = i + 1;
+                while end < chars.len() && chars[end] != quote {
+                    end += 1;
+                }
+                (
+                    chars[i + 1..end].iter().collect(),
+                    (end + 1).min(chars.len()),
+                )
+            }
+            // `$` starts the `$parent` and `$child` placeholders
+            c if c.is_alphabetic() || c == '_' || c == '$' => {
+                let mut end = i + 1;
+                while end < chars.len() && (chars[end].is_alphanumeric() || chars[end] == '_') {
+                    end += 1;
+                }
+                (chars[i..end].iter().collect::<String>(), end)
+            }
+            _ => {
+                i += 1;
+                continue;
             }
+        };
+        if chars.get(end) == Some(&'.') {
+            spans.push((i..end, ident));
         }
+        i = end;
     }
+    spans
 }
 
 // impl From<&SqlExpr> for SimpleExpr {
@@ -281,15 +804,15 @@ impl From<&SqlExpr> for sea_query::SimpleExpr {
     }
 }
 
-#[derive(Tsify, Deserialize, Serialize, Clone, Debug)]
-#[tsify(from_wasm_abi)]
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct BinaryOp<L, R> {
     pub left: L,
     pub right: R,
 }
 
-#[derive(Tsify, Deserialize, Serialize, Clone, Debug)]
-#[tsify(from_wasm_abi)]
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 #[serde(rename_all = "snake_case")]
 pub enum JoinType {
     Join,
@@ -313,8 +836,8 @@ impl From<&JoinType> for sea_query::JoinType {
     }
 }
 
-#[derive(Tsify, Deserialize, Serialize, Clone, Debug)]
-#[tsify(from_wasm_abi)]
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(from_wasm_abi))]
 pub struct Join {
     pub on: SqlExpr,
     pub kind: JoinType,
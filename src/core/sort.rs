@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+
+use graphql_parser::query::Value;
+
+use crate::core::{
+    builder::col,
+    error::Error,
+    schema::{Field, Node, Root},
+    shared_schema::{CHILD, Join, JoinType, PARENT},
+    sql_schema::{SqlJoin, SqlNullsOrder, SqlOrderBy, SqlOrderDirection},
+};
+
+/// The argument that takes the sort order of a `sortable` node.
+pub const ORDER_BY_ARGUMENT: &str = "orderBy";
+
+/// The sort keys of an `orderBy` argument and the joins they sort through.
+#[derive(Clone, Debug, Default)]
+pub struct SortKeys {
+    pub order_by: Vec<SqlOrderBy>,
+    /// Every join the keys sort through, including those the selection already joins
+    pub joins: Vec<SqlJoin>,
+}
+
+/// Compiles the `orderBy` argument of a field selecting `node` under `alias`.
+///
+/// The argument is a list of keys like `{ field: TITLE, direction: DESC, nulls: LAST }`, where
+/// `field` names one of the node's columns in upper snake case, or `{ author: { field: NAME } }`
+/// to sort by a column of a to-one join, inheriting the direction and nulls order of the
+/// enclosing key unless it sets its own. A join the field also selects is sorted through
+/// where it is in `selected`, keyed by the alias and field it joins from; any other is LEFT
+/// JOINed under an alias from `claim_alias`, so sorting never drops rows.
+pub fn order_by_argument<'a>(
+    root: &Root,
+    node: &Node,
+    alias: &str,
+    value: &Value<'a, &'a str>,
+    selected: &HashMap<(String, String), SqlJoin>,
+    claim_alias: &mut dyn FnMut(&str) -> String,
+) -> Result<SortKeys, Error> {
+    let keys = match value {
+        Value::List(keys) => keys.iter().collect(),
+        Value::Variable(variable) => {
+            return Err(Error::from(format!(
+                "The {} argument must be written inline, got ${}",
+                ORDER_BY_ARGUMENT, variable
+            )));
+        }
+        // GraphQL coerces a single input object into a list of one
+        key => vec![key],
+    };
+    let mut sort = SortKeys::default();
+    let mut joins = SortJoins {
+        claim_alias,
+        selected,
+        joined: HashMap::new(),
+    };
+    for key in keys {
+        let defaults = (SqlOrderDirection::Asc, None);
+        sort_key(root, node, alias, key, defaults, &mut joins, &mut sort)?;
+    }
+    Ok(sort)
+}
+
+struct SortJoins<'c> {
+    claim_alias: &'c mut dyn FnMut(&str) -> String,
+    selected: &'c HashMap<(String, String), SqlJoin>,
+    // The alias each sort join was claimed under, keyed by the alias and field it joins from
+    joined: HashMap<(String, String), String>,
+}
+
+/// The upper snake case name a column field is sorted by, e.g. `CREATED_AT` for `createdAt`.
+pub fn sort_field_name(field: &str) -> String {
+    let mut name = String::new();
+    for (i, c) in field.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            name.push('_');
+        }
+        name.extend(c.to_uppercase());
+    }
+    name
+}
+
+fn sort_key<'a>(
+    root: &Root,
+    node: &Node,
+    alias: &str,
+    key: &Value<'a, &'a str>,
+    // The direction and nulls order of the enclosing key, which a nested key may override
+    (direction, nulls): (SqlOrderDirection, Option<SqlNullsOrder>),
+    joins: &mut SortJoins,
+    sort: &mut SortKeys,
+) -> Result<(), Error> {
+    let Value::Object(key) = key else {
+        return Err(Error::from(format!(
+            "Each {} key must be an input object, got {}",
+            ORDER_BY_ARGUMENT, key
+        )));
+    };
+    let direction = match key.get("direction") {
+        None => direction,
+        Some(Value::Enum("ASC")) => SqlOrderDirection::Asc,
+        Some(Value::Enum("DESC")) => SqlOrderDirection::Desc,
+        Some(other) => {
+            return Err(Error::from(format!(
+                "direction must be ASC or DESC, got {}",
+                other
+            )));
+        }
+    };
+    let nulls = match key.get("nulls") {
+        None => nulls,
+        Some(Value::Enum("FIRST")) => Some(SqlNullsOrder::First),
+        Some(Value::Enum("LAST")) => Some(SqlNullsOrder::Last),
+        Some(other) => {
+            return Err(Error::from(format!(
+                "nulls must be FIRST or LAST, got {}",
+                other
+            )));
+        }
+    };
+
+    let mut targets = key
+        .iter()
+        .filter(|(name, _)| !matches!(**name, "direction" | "nulls"));
+    let (Some((name, value)), None) = (targets.next(), targets.next()) else {
+        return Err(Error::from(format!(
+            "Each {} key must sort by exactly one field or join",
+            ORDER_BY_ARGUMENT
+        )));
+    };
+
+    if *name == "field" {
+        let field = match value {
+            Value::Enum(field) => *field,
+            other => {
+                return Err(Error::from(format!(
+                    "field must be a {}SortField, got {}",
+                    node.type_name(),
+                    other
+                )));
+            }
+        };
+        let column = node
+            .fields
+            .iter()
+            .find_map(|(name, meta)| match meta {
+                Field::Column(column) if sort_field_name(name) == field => Some(column),
+                _ => None,
+            })
+            .ok_or(format!(
+                "{} has no column {} to sort by",
+                node.type_name(),
+                field
+            ))?;
+        sort.order_by.push(SqlOrderBy {
+            expr: col(alias, column.column.clone()),
+            direction,
+            nulls,
+        });
+        return Ok(());
+    }
+
+    let Some(Field::Join(join_info)) = node.fields.get(*name) else {
+        return Err(Error::from(format!(
+            "{} has no join {} to sort by",
+            node.type_name(),
+            name
+        )));
+    };
+    let target = root.0.get(&join_info.extends.extends).ok_or(format!(
+        "Unable to sort by {}: it joins \"{}\" but no node has that alias",
+        name, join_info.extends.extends
+    ))?;
+    if join_info.many || !target.sortable {
+        return Err(Error::from(format!(
+            "Unable to sort by {}: only sortable to-one joins can be sorted by",
+            name
+        )));
+    }
+
+    let join_key = (alias.to_string(), name.to_string());
+    let join_alias = match joins.joined.get(&join_key) {
+        Some(join_alias) => join_alias.clone(),
+        None if let Some(join) = joins.selected.get(&join_key) => {
+            sort.joins.push(join.clone());
+            joins.joined.insert(join_key, join.alias.clone());
+            join.alias.clone()
+        }
+        None => {
+            let join_alias = (joins.claim_alias)(&format!("{}_{}", alias, name));
+            let mut on = join_info.join.on.clone();
+            let renames = [
+                (node.alias.as_str(), alias),
+                (PARENT, alias),
+                (join_info.extends.alias.as_str(), &join_alias),
+                (CHILD, &join_alias),
+            ]
+            .into_iter()
+            .filter(|(declared, claimed)| declared != claimed)
+            .map(|(declared, claimed)| (declared.to_string(), claimed.to_string()))
+            .collect::<HashMap<_, _>>();
+            on.rename_tables(&renames);
+            // The joined node's own filters are written against its alias or the join's
+            let renames = [
+                (target.alias.as_str(), &join_alias),
+                (join_info.extends.alias.as_str(), &join_alias),
+            ]
+            .into_iter()
+            .filter(|(declared, claimed)| declared != claimed)
+            .map(|(declared, claimed)| (declared.to_string(), claimed.to_string()))
+            .collect::<HashMap<_, _>>();
+            for mut filter in target.metadata_filters() {
+                filter.resolve_columns(&join_alias);
+                filter.rename_tables(&renames);
+                on = on.and(filter);
+            }
+            sort.joins.push(SqlJoin {
+                table: target.table.clone(),
+                alias: join_alias.clone(),
+                join: Join {
+                    on,
+                    kind: JoinType::LeftJoin,
+                },
+                limit: None,
+            });
+            joins.joined.insert(join_key, join_alias.clone());
+            join_alias
+        }
+    };
+    sort_key(
+        root,
+        target,
+        &join_alias,
+        value,
+        (direction, nulls),
+        joins,
+        sort,
+    )
+}
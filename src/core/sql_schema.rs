@@ -1,31 +1,624 @@
-use sea_query::{Alias, Expr, Query, SelectStatement, SimpleExpr};
-use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
 
-use crate::core::shared_schema::{Join, SqlExpr};
+use sea_query::{
+    Alias, Asterisk, CommonTableExpression, DeleteStatement, Expr, GenericBuilder, Iden,
+    InsertStatement, NullOrdering, OnConflict, Order, OrderedStatement, OverStatement, Query,
+    Quote, ReturningClause, SeaRc, SelectStatement, SimpleExpr, TableRef, UnionType,
+    UpdateStatement, Values, WindowStatement, WithClause,
+};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "wasm")]
+use tsify::Tsify;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
 
-#[derive(Debug, Serialize)]
+use crate::core::{
+    schema::BuilderType,
+    shared_schema::{CHILD, ColumnRef, Join, PARENT, SqlExpr, SqlParam, SqlValue, marked_param},
+};
+
+/// A rendered SQL statement and the values of its placeholders, in order.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(into_wasm_abi, from_wasm_abi))]
+pub struct SqlQuery {
+    pub sql: String,
+    pub params: Vec<SqlValue>,
+    /// The indexes into `params` each named `SqlExpr::Param` is bound at
+    #[serde(default)]
+    #[cfg_attr(feature = "wasm", tsify(type = "Record<string, number[]>"))]
+    pub positions: BTreeMap<String, Vec<usize>>,
+    /// Statements to run after this one, in the same transaction. Only mutations on MySQL and
+    /// SQLite, which read the written row back with a `SELECT`, need any; their rows are those
+    /// of the `SELECT`
+    #[serde(default)]
+    pub then: Vec<SqlQuery>,
+    /// The params filled from the rows earlier statements return, each by the column it takes
+    /// the value of, e.g. the generated key of the row a nested create inserted before
+    #[serde(default)]
+    #[cfg_attr(feature = "wasm", tsify(type = "Record<string, string>"))]
+    pub keys: BTreeMap<String, String>,
+}
+
+impl SqlQuery {
+    /// Replaces the param at each position of a name in `values`, in this statement and the
+    /// ones after it.
+    pub fn bind_params(&mut self, values: &HashMap<String, SqlValue>) {
+        for (name, positions) in &self.positions {
+            if let Some(value) = values.get(name) {
+                for &position in positions {
+                    self.params[position] = value.clone();
+                }
+            }
+        }
+        for then in &mut self.then {
+            then.bind_params(values);
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
 pub struct SqlColumn {
     pub name: String,
     pub table: String,
     pub alias: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Clone, Debug, Serialize)]
 pub struct SqlJoin {
     pub table: String,
     pub alias: String,
     pub join: Join,
+    /// Joins the first rows of `table` for each parent row rather than all of them
+    pub limit: Option<SqlLimit>,
 }
 
-#[derive(Debug, Serialize)]
+/// Keeps the first `count` rows of a list, in its own order, for each row of its parent.
+///
+/// The list's table is selected from a subquery numbering each parent's rows with
+/// `ROW_NUMBER()`, since a `LIMIT` would count the rows every other list joined multiplies.
+#[derive(Clone, Debug, Serialize)]
+pub struct SqlLimit {
+    pub count: SqlExpr,
+    /// The filter of the list's rows, applied before they are numbered
+    pub where_clause: Option<SqlExpr>,
+    /// The list's own sort, without that of the lists joined below it
+    pub order_by: Vec<SqlOrderBy>,
+    /// The joins `order_by` sorts through
+    pub joins: Vec<SqlJoin>,
+    /// The parent the rows are numbered per, `None` for a root list
+    pub parent: Option<SqlLimitParent>,
+}
+
+/// The parent a joined list is numbered per.
+#[derive(Clone, Debug, Serialize)]
+pub struct SqlLimitParent {
+    pub table: String,
+    pub alias: String,
+    /// The unique key column of `table`
+    pub key: String,
+    /// The join condition, against `alias` and the alias of the list
+    pub on: SqlExpr,
+}
+
+impl SqlLimit {
+    /// The number of the row among its parent's rows, starting at 1.
+    pub const ROW: &str = "__row";
+    /// The key of the parent row.
+    pub const PARENT_KEY: &str = "__parent_key";
+
+    /// The rows of `table` under `alias`, each with its `ROW` number and `PARENT_KEY`.
+    pub fn to_subquery(&self, table: &str, alias: &str, builder: &BuilderType) -> SelectStatement {
+        let mut select = Query::select();
+        let mut window = WindowStatement::new();
+        select
+            .column((Alias::new(alias), Asterisk))
+            .from_as(Alias::new(table), Alias::new(alias));
+        if let Some(parent) = &self.parent {
+            let key = Expr::col((Alias::new(&parent.alias), Alias::new(&parent.key)));
+            select
+                .expr_as(key.clone(), Alias::new(Self::PARENT_KEY))
+                .join_as(
+                    sea_query::JoinType::InnerJoin,
+                    Alias::new(&parent.table),
+                    Alias::new(&parent.alias),
+                    parent.on.to_sea_expr_for(builder),
+                );
+            window.add_partition_by(key.into());
+        }
+        for join in &self.joins {
+            select.join_as(
+                (&join.join.kind).into(),
+                Alias::new(&join.table),
+                Alias::new(&join.alias),
+                join.join.on.to_sea_expr_for(builder),
+            );
+        }
+        if let Some(where_clause) = &self.where_clause {
+            select.and_where(where_clause.to_sea_expr_for(builder));
+        }
+        push_order_by(&mut window, &self.order_by, builder);
+        select.expr_window_as(Expr::cust("ROW_NUMBER()"), window, Alias::new(Self::ROW));
+        select
+    }
+
+    /// Whether a row of the subquery under `alias` is within the limit.
+    pub fn condition(&self, alias: &str) -> SqlExpr {
+        SqlExpr::Column(ColumnRef {
+            column: Self::ROW.to_string(),
+            table: Some(alias.to_string()),
+        })
+        .lte(self.count.clone())
+    }
+
+    fn exprs(&self) -> Vec<&SqlExpr> {
+        std::iter::once(&self.count)
+            .chain(self.where_clause.as_ref())
+            .chain(self.order_by.iter().map(|order_by| &order_by.expr))
+            .chain(self.joins.iter().map(|join| &join.join.on))
+            .chain(self.parent.iter().map(|parent| &parent.on))
+            .collect()
+    }
+
+    fn exprs_mut(&mut self) -> Vec<&mut SqlExpr> {
+        std::iter::once(&mut self.count)
+            .chain(self.where_clause.as_mut())
+            .chain(self.order_by.iter_mut().map(|order_by| &mut order_by.expr))
+            .chain(self.joins.iter_mut().map(|join| &mut join.join.on))
+            .chain(self.parent.iter_mut().map(|parent| &mut parent.on))
+            .collect()
+    }
+}
+
+/// A `WITH RECURSIVE` table of every row reachable from each row of `table` by repeatedly
+/// following `on`, with the columns `root_key`, `node_key`, `parent_key` and `depth`.
+#[derive(Clone, Debug, Serialize)]
+pub struct SqlRecursiveCte {
+    pub name: String,
+    pub table: String,
+    /// The unique key column of `table`
+    pub key: String,
+    /// How a row joins the rows one level below it, written against `PARENT` and `CHILD`
+    pub on: SqlExpr,
+    /// The deepest level followed, unlimited when `None`
+    pub max_depth: Option<u32>,
+}
+
+impl SqlRecursiveCte {
+    /// The key of the row the walk started from.
+    pub const ROOT_KEY: &str = "root_key";
+    /// The key of the row reached.
+    pub const NODE_KEY: &str = "node_key";
+    /// The key of the row one level above the row reached.
+    pub const PARENT_KEY: &str = "parent_key";
+    /// How many levels below the root the row was reached, starting at 1.
+    pub const DEPTH: &str = "depth";
+
+    /// The CTE: the rows one level below each row, then the rows one level below those.
+    pub fn to_cte(&self, builder: &BuilderType) -> CommonTableExpression {
+        let (parent, child) = (Alias::new("parent"), Alias::new("child"));
+        let key = |table: &Alias| Expr::col((table.clone(), Alias::new(&self.key)));
+        let on = |parent: &str| {
+            let mut on = self.on.clone();
+            on.rename_tables(&HashMap::from([
+                (PARENT.to_string(), parent.to_string()),
+                (CHILD.to_string(), "child".to_string()),
+            ]));
+            on.to_sea_expr_for(builder)
+        };
+
+        let mut anchor = Query::select();
+        anchor
+            .expr_as(key(&parent), Alias::new(Self::ROOT_KEY))
+            .expr_as(key(&child), Alias::new(Self::NODE_KEY))
+            .expr_as(key(&parent), Alias::new(Self::PARENT_KEY))
+            .expr_as(Expr::cust("1"), Alias::new(Self::DEPTH))
+            .from_as(Alias::new(&self.table), parent.clone())
+            .join_as(
+                sea_query::JoinType::InnerJoin,
+                Alias::new(&self.table),
+                child.clone(),
+                on("parent"),
+            );
+
+        // The level above is joined again so `on` can refer to any of its columns
+        let (cte, above) = (Alias::new(&self.name), Alias::new("above"));
+        let mut step = Query::select();
+        step.expr(Expr::col((cte.clone(), Alias::new(Self::ROOT_KEY))))
+            .expr(key(&child))
+            .expr(Expr::col((cte.clone(), Alias::new(Self::NODE_KEY))))
+            .expr(Expr::col((cte.clone(), Alias::new(Self::DEPTH))).add(Expr::cust("1")))
+            .from(cte.clone())
+            .join_as(
+                sea_query::JoinType::InnerJoin,
+                Alias::new(&self.table),
+                above.clone(),
+                key(&above).equals((cte.clone(), Alias::new(Self::NODE_KEY))),
+            )
+            .join_as(
+                sea_query::JoinType::InnerJoin,
+                Alias::new(&self.table),
+                child,
+                on("above"),
+            );
+        if let Some(max_depth) = self.max_depth {
+            step.and_where(
+                Expr::col((cte.clone(), Alias::new(Self::DEPTH)))
+                    .lt(Expr::cust(max_depth.to_string())),
+            );
+        }
+        anchor.union(UnionType::All, step);
+
+        let mut cte = CommonTableExpression::new();
+        cte.table_name(Alias::new(&self.name))
+            .columns([
+                Alias::new(Self::ROOT_KEY),
+                Alias::new(Self::NODE_KEY),
+                Alias::new(Self::PARENT_KEY),
+                Alias::new(Self::DEPTH),
+            ])
+            .query(anchor);
+        cte
+    }
+}
+
+/// The insert, update or delete of a mutation.
+#[derive(Clone, Debug, Serialize)]
+pub struct SqlMutation {
+    pub kind: SqlMutationKind,
+    pub table: String,
+    /// The alias an update or delete gives the table, which `where_clause` refers to
+    pub alias: String,
+    /// The columns an insert or update sets, with their values
+    pub values: Vec<(String, SqlExpr)>,
+    /// The rows an update or delete writes
+    pub where_clause: Option<SqlExpr>,
+    /// What an insert does when a row with the same key exists, making it an upsert
+    pub on_conflict: Option<SqlOnConflict>,
+    /// The rows a nested input inserts after this one
+    pub nested: Vec<SqlNestedInsert>,
+    /// The key column an insert returns as `<alias>_<key>`, which the `SELECT` reading its row
+    /// back on SQLite finds it by
+    pub returning: Option<String>,
+}
+
+/// A row inserted after its parent's, whose foreign key takes the value of the parent's key.
+#[derive(Clone, Debug, Serialize)]
+pub struct SqlNestedInsert {
+    pub insert: SqlMutation,
+    /// The column of `insert` referencing the parent row
+    pub foreign_key: String,
+    /// The unique key column of the parent row
+    pub parent_key: String,
+}
+
+/// Updates the existing row when an insert conflicts with it.
+#[derive(Clone, Debug, Serialize)]
+pub struct SqlOnConflict {
+    /// The columns of the unique index a conflict is on, which MySQL infers
+    pub target: Vec<String>,
+    /// The columns set to the value the insert had for them
+    pub update: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub enum SqlMutationKind {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl SqlMutation {
+    pub fn has_params(&self) -> bool {
+        self.exprs().iter().any(|expr| expr.has_params())
+    }
+
+    pub fn bind_params(&mut self, values: &HashMap<String, SqlValue>) {
+        for expr in self.exprs_mut() {
+            expr.bind_params(values);
+        }
+    }
+
+    /// Marks every `SqlExpr::Param`, see `SqlExpr::mark_params`.
+    pub fn mark_params(&mut self) {
+        for expr in self.exprs_mut() {
+            expr.mark_params();
+        }
+    }
+
+    /// The expressions of the write and of every nested insert.
+    fn exprs(&self) -> Vec<&SqlExpr> {
+        self.values
+            .iter()
+            .map(|(_, value)| value)
+            .chain(self.where_clause.as_ref())
+            .chain(self.nested.iter().flat_map(|nested| nested.insert.exprs()))
+            .collect()
+    }
+
+    fn exprs_mut(&mut self) -> Vec<&mut SqlExpr> {
+        self.values
+            .iter_mut()
+            .map(|(_, value)| value)
+            .chain(self.where_clause.as_mut())
+            .chain(
+                self.nested
+                    .iter_mut()
+                    .flat_map(|nested| nested.insert.exprs_mut()),
+            )
+            .collect()
+    }
+
+    /// The write and every nested insert for Postgres as CTEs of one statement, each named by
+    /// its alias: the write returns the rows it wrote for the statement to select from, and
+    /// each nested insert selects its foreign key from its parent's CTE.
+    pub fn with_clause(&self, builder_type: &BuilderType) -> WithClause {
+        let mut with_clause = WithClause::new();
+        self.push_ctes(&mut with_clause, builder_type, None);
+        with_clause
+    }
+
+    fn push_ctes(
+        &self,
+        with_clause: &mut WithClause,
+        builder_type: &BuilderType,
+        parent: Option<(&str, &SqlNestedInsert)>,
+    ) {
+        let mut cte = CommonTableExpression::new();
+        cte.table_name(Alias::new(&self.alias));
+        match parent {
+            Some((parent, nested)) => {
+                let mut select = Query::select();
+                select
+                    .exprs(
+                        self.values
+                            .iter()
+                            .map(|(_, value)| value.to_sea_expr_for(builder_type)),
+                    )
+                    .expr(Expr::col((
+                        Alias::new(parent),
+                        Alias::new(&nested.parent_key),
+                    )))
+                    .from(Alias::new(parent));
+                let mut insert = Query::insert();
+                insert
+                    .into_table(Alias::new(&self.table))
+                    .columns(
+                        self.values
+                            .iter()
+                            .map(|(column, _)| column)
+                            .chain([&nested.foreign_key])
+                            .map(Alias::new),
+                    )
+                    .select_from(select)
+                    .expect("one selected value per column");
+                // Only returned to its own nested inserts
+                if !self.nested.is_empty() {
+                    insert.returning_all();
+                }
+                cte.query(insert);
+            }
+            None => {
+                let returning = Some(Query::returning().all());
+                match self.kind {
+                    SqlMutationKind::Insert => cte.query(self.insert(builder_type, returning)),
+                    SqlMutationKind::Update => cte.query(self.update(builder_type, returning)),
+                    SqlMutationKind::Delete => cte.query(self.delete(builder_type, returning)),
+                };
+            }
+        }
+        with_clause.cte(cte);
+        for nested in &self.nested {
+            nested
+                .insert
+                .push_ctes(with_clause, builder_type, Some((&self.alias, nested)));
+        }
+    }
+
+    /// Builds the statement for `builder`, returning `returning` when given. Nested inserts
+    /// are left to `with_clause`, or to statements of their own.
+    pub fn build<T: GenericBuilder>(
+        &self,
+        builder_type: &BuilderType,
+        builder: T,
+        returning: Option<ReturningClause>,
+    ) -> (String, Values) {
+        match self.kind {
+            SqlMutationKind::Insert => self.insert(builder_type, returning).build(builder),
+            SqlMutationKind::Update => self.update(builder_type, returning).build(builder),
+            SqlMutationKind::Delete => self.delete(builder_type, returning).build(builder),
+        }
+    }
+
+    fn insert(
+        &self,
+        builder_type: &BuilderType,
+        returning: Option<ReturningClause>,
+    ) -> InsertStatement {
+        let mut insert = Query::insert();
+        insert.into_table(Alias::new(&self.table));
+        if self.values.is_empty() {
+            insert.or_default_values();
+        } else {
+            insert
+                .columns(self.values.iter().map(|(column, _)| Alias::new(column)))
+                .values_panic(self.value_exprs(builder_type));
+        }
+        if let Some(on_conflict) = &self.on_conflict {
+            insert.on_conflict(
+                OnConflict::columns(on_conflict.target.iter().map(Alias::new))
+                    .update_columns(on_conflict.update.iter().map(Alias::new))
+                    .to_owned(),
+            );
+        }
+        if let Some(returning) = returning {
+            insert.returning(returning);
+        }
+        insert
+    }
+
+    fn update(
+        &self,
+        builder_type: &BuilderType,
+        returning: Option<ReturningClause>,
+    ) -> UpdateStatement {
+        let mut update = Query::update();
+        update.table(self.aliased_table()).values(
+            self.values
+                .iter()
+                .map(|(column, _)| Alias::new(column))
+                .zip(self.value_exprs(builder_type)),
+        );
+        if let Some(where_clause) = &self.where_clause {
+            update.and_where(where_clause.to_sea_expr_for(builder_type));
+        }
+        if let Some(returning) = returning {
+            update.returning(returning);
+        }
+        update
+    }
+
+    fn delete(
+        &self,
+        builder_type: &BuilderType,
+        returning: Option<ReturningClause>,
+    ) -> DeleteStatement {
+        let mut delete = Query::delete();
+        delete.from_table(self.aliased_table());
+        if let Some(where_clause) = &self.where_clause {
+            delete.and_where(where_clause.to_sea_expr_for(builder_type));
+        }
+        if let Some(returning) = returning {
+            delete.returning(returning);
+        }
+        delete
+    }
+
+    fn aliased_table(&self) -> TableRef {
+        TableRef::TableAlias(
+            SeaRc::new(Alias::new(&self.table)),
+            SeaRc::new(Alias::new(&self.alias)),
+        )
+    }
+
+    fn value_exprs(&self, builder_type: &BuilderType) -> Vec<SimpleExpr> {
+        self.values
+            .iter()
+            .map(|(_, value)| value.to_sea_expr_for(builder_type))
+            .collect()
+    }
+}
+
+/// `"column" AS "alias"` for each column and alias.
+pub fn aliased_columns<'c>(
+    columns: impl IntoIterator<Item = (&'c str, &'c str)>,
+    quote: Quote,
+) -> Vec<SimpleExpr> {
+    let quoted = |ident: &str| {
+        let mut quoted = String::new();
+        Alias::new(ident).prepare(&mut quoted, quote);
+        quoted
+    };
+    columns
+        .into_iter()
+        .map(|(name, alias)| Expr::cust(format!("{} AS {}", quoted(name), quoted(alias))))
+        .collect()
+}
+
+/// Which indexes of a statement's params each named `SqlExpr::Param` is bound at, read off
+/// `marked`, the params of the same statement rendered with its params marked.
+pub fn param_positions(marked: &Values) -> BTreeMap<String, Vec<usize>> {
+    let mut positions = BTreeMap::<String, Vec<usize>>::new();
+    for (index, value) in marked.iter().enumerate() {
+        if let Some(name) = marked_param(value) {
+            positions.entry(name.to_string()).or_default().push(index);
+        }
+    }
+    positions
+}
+
+#[derive(Clone, Debug, Serialize)]
 pub struct SqlSelect {
+    /// The write of a mutation, whose rows this select answers with
+    pub mutation: Option<SqlMutation>,
+    /// The recursive CTEs of this select and every select joined into it
+    pub ctes: Vec<SqlRecursiveCte>,
     pub table: String,
     pub alias: String,
     pub columns: Vec<SqlColumn>,
     pub joins: Vec<SqlJoin>,
     pub where_clause: Option<SqlExpr>,
     pub order_by: Vec<SqlOrderBy>,
-    pub limit: Option<u32>,
+    /// Keeps the first rows of a root list
+    pub limit: Option<SqlLimit>,
+}
+
+impl SqlSelect {
+    /// Whether any expression of this select binds a `SqlExpr::Param`.
+    pub fn has_params(&self) -> bool {
+        self.mutation
+            .as_ref()
+            .is_some_and(|mutation| mutation.has_params())
+            || self.exprs().iter().any(|expr| expr.has_params())
+    }
+
+    /// Replaces the value of every `SqlExpr::Param` whose name is in `values`.
+    pub fn bind_params(&mut self, values: &HashMap<String, SqlValue>) {
+        if let Some(mutation) = &mut self.mutation {
+            mutation.bind_params(values);
+        }
+        for expr in self.exprs_mut() {
+            expr.bind_params(values);
+        }
+    }
+
+    /// Marks every `SqlExpr::Param`, see `SqlExpr::mark_params`.
+    pub fn mark_params(&mut self) {
+        if let Some(mutation) = &mut self.mutation {
+            mutation.mark_params();
+        }
+        for expr in self.exprs_mut() {
+            expr.mark_params();
+        }
+    }
+
+    /// The `SqlExpr::Param`s of the select and its mutation, in the same order for any two
+    /// selects planned from queries of the same shape.
+    pub fn params(&self) -> Vec<&SqlParam> {
+        self.mutation
+            .iter()
+            .flat_map(SqlMutation::exprs)
+            .chain(self.exprs())
+            .flat_map(SqlExpr::params)
+            .collect()
+    }
+
+    /// The expressions of the select, leaving out those of the mutation.
+    fn exprs(&self) -> Vec<&SqlExpr> {
+        self.ctes
+            .iter()
+            .map(|cte| &cte.on)
+            .chain(self.joins.iter().flat_map(|join| {
+                std::iter::once(&join.join.on).chain(join.limit.iter().flat_map(SqlLimit::exprs))
+            }))
+            .chain(self.where_clause.as_ref())
+            .chain(self.order_by.iter().map(|order_by| &order_by.expr))
+            .chain(self.limit.iter().flat_map(SqlLimit::exprs))
+            .collect()
+    }
+
+    fn exprs_mut(&mut self) -> Vec<&mut SqlExpr> {
+        self.ctes
+            .iter_mut()
+            .map(|cte| &mut cte.on)
+            .chain(self.joins.iter_mut().flat_map(|join| {
+                std::iter::once(&mut join.join.on)
+                    .chain(join.limit.iter_mut().flat_map(SqlLimit::exprs_mut))
+            }))
+            .chain(self.where_clause.as_mut())
+            .chain(self.order_by.iter_mut().map(|order_by| &mut order_by.expr))
+            .chain(self.limit.iter_mut().flat_map(SqlLimit::exprs_mut))
+            .collect()
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -34,10 +627,12 @@ pub struct SqlColumnRef {
     pub column: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Clone, Debug, Serialize)]
 pub struct SqlOrderBy {
     pub expr: SqlExpr,
     pub direction: SqlOrderDirection,
+    /// Where nulls sort, the dialect's default when `None`
+    pub nulls: Option<SqlNullsOrder>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -46,12 +641,50 @@ pub enum SqlOrderDirection {
     Desc,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub enum SqlNullsOrder {
+    First,
+    Last,
+}
+
 impl From<&SqlSelect> for SelectStatement {
     fn from(ast: &SqlSelect) -> Self {
+        ast.to_statement(&BuilderType::default())
+    }
+}
+
+impl SqlSelect {
+    /// The `WITH RECURSIVE` clause of the select's CTEs, `None` when it has none.
+    pub fn with_clause(&self, builder: &BuilderType) -> Option<WithClause> {
+        if self.ctes.is_empty() {
+            return None;
+        }
+        let mut with_clause = WithClause::new();
+        with_clause.recursive(true);
+        for cte in &self.ctes {
+            with_clause.cte(cte.to_cte(builder));
+        }
+        Some(with_clause)
+    }
+
+    /// Builds the statement for `builder`, emulating operators the dialect lacks.
+    pub fn to_statement(&self, builder: &BuilderType) -> SelectStatement {
+        let ast = self;
         let mut select = Query::select();
 
-        // FROM "table" AS "alias"
-        select.from_as(Alias::new(&ast.table), Alias::new(&ast.alias));
+        // FROM "table" AS "alias", or the first rows of it
+        match &ast.limit {
+            Some(limit) => {
+                select.from_subquery(
+                    limit.to_subquery(&ast.table, &ast.alias, builder),
+                    Alias::new(&ast.alias),
+                );
+                select.and_where(limit.condition(&ast.alias).to_sea_expr_for(builder));
+            }
+            None => {
+                select.from_as(Alias::new(&ast.table), Alias::new(&ast.alias));
+            }
+        }
 
         // SELECT columns: "table"."column" AS "alias"
         for col in &ast.columns {
@@ -62,15 +695,55 @@ impl From<&SqlSelect> for SelectStatement {
         // JOINs
         for join in &ast.joins {
             let join_type: sea_query::JoinType = (&join.join.kind).into();
-            let join_on: SimpleExpr = (&join.join.on).into();
-            select.join_as(
-                join_type,
-                Alias::new(&join.table),
-                Alias::new(&join.alias),
-                join_on,
-            );
+            let join_on: SimpleExpr = join.join.on.to_sea_expr_for(builder);
+            match &join.limit {
+                Some(limit) => select.join_subquery(
+                    join_type,
+                    limit.to_subquery(&join.table, &join.alias, builder),
+                    Alias::new(&join.alias),
+                    join_on,
+                ),
+                None => select.join_as(
+                    join_type,
+                    Alias::new(&join.table),
+                    Alias::new(&join.alias),
+                    join_on,
+                ),
+            };
+        }
+
+        // WHERE
+        if let Some(where_clause) = &ast.where_clause {
+            select.and_where(where_clause.to_sea_expr_for(builder));
         }
 
+        // ORDER BY
+        push_order_by(&mut select, &ast.order_by, builder);
+
         select
     }
 }
+
+/// Appends `order_by` to the `ORDER BY` of a select or window.
+fn push_order_by<S: OrderedStatement>(
+    statement: &mut S,
+    order_by: &[SqlOrderBy],
+    builder: &BuilderType,
+) {
+    for order_by in order_by {
+        let expr = order_by.expr.to_sea_expr_for(builder);
+        let order = match order_by.direction {
+            SqlOrderDirection::Asc => Order::Asc,
+            SqlOrderDirection::Desc => Order::Desc,
+        };
+        match order_by.nulls {
+            Some(SqlNullsOrder::First) => {
+                statement.order_by_expr_with_nulls(expr, order, NullOrdering::First)
+            }
+            Some(SqlNullsOrder::Last) => {
+                statement.order_by_expr_with_nulls(expr, order, NullOrdering::Last)
+            }
+            None => statement.order_by_expr(expr, order),
+        };
+    }
+}
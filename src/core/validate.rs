@@ -0,0 +1,333 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use serde::Serialize;
+#[cfg(feature = "wasm")]
+use tsify::Tsify;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::core::{
+    schema::{Field, Node, Polymorphic, Root},
+    shared_schema::{CHILD, PARENT, SqlExpr},
+};
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(into_wasm_abi))]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SchemaIssue {
+    /// A join `extends` an alias that no node has.
+    DanglingExtends {
+        node: String,
+        field: String,
+        extends: String,
+    },
+    /// One SQL alias is used for more than one node or join. Each usage is a node's table or
+    /// a join's field, e.g. `post_1.author`.
+    DuplicateAlias { alias: String, usages: Vec<String> },
+    /// More than one node claims the same root query field.
+    DuplicateFieldName {
+        field_name: String,
+        nodes: Vec<String>,
+    },
+    /// A join condition refers to an alias that is neither side of its join.
+    AliasOutOfScope {
+        node: String,
+        field: String,
+        alias: String,
+    },
+    /// No query can select this node.
+    UnreachableNode { node: String },
+    /// A union member is an alias that no node has.
+    DanglingMember { node: String, member: String },
+    /// A union member has no `unique_key`, so rows that join it can not be told apart.
+    MemberWithoutUniqueKey { node: String, member: String },
+    /// A node with a recursive field has no `unique_key`, so the levels can not be linked.
+    RecursiveWithoutUniqueKey { node: String, field: String },
+}
+
+impl fmt::Display for SchemaIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaIssue::DanglingExtends {
+                node,
+                field,
+                extends,
+            } => write!(
+                f,
+                "{}.{} extends \"{}\" but no node has that alias",
+                node, field, extends
+            ),
+            SchemaIssue::DuplicateAlias { alias, usages } => write!(
+                f,
+                "alias \"{}\" is used by more than one node: {}",
+                alias,
+                usages.join(", ")
+            ),
+            SchemaIssue::DuplicateFieldName { field_name, nodes } => write!(
+                f,
+                "root field \"{}\" is claimed by more than one node: {}",
+                field_name,
+                nodes.join(", ")
+            ),
+            SchemaIssue::AliasOutOfScope { node, field, alias } => write!(
+                f,
+                "the join condition of {}.{} refers to \"{}\" which is not in scope",
+                node, field, alias
+            ),
+            SchemaIssue::UnreachableNode { node } => {
+                write!(f, "node \"{}\" can not be reached by any query", node)
+            }
+            SchemaIssue::DanglingMember { node, member } => write!(
+                f,
+                "{} has the union member \"{}\" but no node has that alias",
+                node, member
+            ),
+            SchemaIssue::MemberWithoutUniqueKey { node, member } => write!(
+                f,
+                "union member \"{}\" of {} needs a unique_key",
+                member, node
+            ),
+            SchemaIssue::RecursiveWithoutUniqueKey { node, field } => write!(
+                f,
+                "{}.{} is recursive, so {} needs a unique_key",
+                node, field, node
+            ),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[cfg_attr(feature = "wasm", derive(Tsify), tsify(into_wasm_abi))]
+pub struct SchemaIssues(pub Vec<SchemaIssue>);
+
+/// Checks `root` for metadata mistakes that would otherwise only surface at query time.
+///
+/// An empty list means the schema is consistent.
+pub fn validate_schema(root: &Root) -> Vec<SchemaIssue> {
+    let mut nodes = root.0.iter().collect::<Vec<_>>();
+    nodes.sort_by(|a, b| a.0.cmp(b.0));
+    let mut issues = validate_root(root);
+    issues.extend(duplicate_aliases(
+        nodes.into_iter().map(|(key, node)| (key.as_str(), node)),
+    ));
+    issues
+}
+
+/// Validates nodes about to be collected into a `Root`, catching nodes that share an alias
+/// before they collapse into one entry.
+pub fn validate_nodes(nodes: Vec<Node>) -> (Root, Vec<SchemaIssue>) {
+    let mut issues = duplicate_aliases(nodes.iter().map(|node| (node.alias.as_str(), node)));
+    let root = Root::from(nodes);
+    let mut root_issues = validate_root(&root);
+    root_issues.append(&mut issues);
+    (root, root_issues)
+}
+
+/// The aliases claimed more than once, by nodes under their key in `Root` (and their own
+/// alias, when it differs) or by joins. A join may reuse the alias of the node it names.
+fn duplicate_aliases<'n>(nodes: impl IntoIterator<Item = (&'n str, &'n Node)>) -> Vec<SchemaIssue> {
+    let mut alias_usages: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for (key, node) in nodes {
+        alias_usages
+            .entry(key)
+            .or_default()
+            .push(node.table.clone());
+        if key != node.alias {
+            alias_usages
+                .entry(node.alias.as_str())
+                .or_default()
+                .push(node.table.clone());
+        }
+        let mut fields = node.fields.iter().collect::<Vec<_>>();
+        fields.sort_by(|a, b| a.0.cmp(b.0));
+        for (field_name, field) in fields {
+            if let Field::Join(join_info) = field
+                && join_info.extends.alias != join_info.extends.extends
+            {
+                alias_usages
+                    .entry(join_info.extends.alias.as_str())
+                    .or_default()
+                    .push(format!("{}.{}", node.alias, field_name));
+            }
+        }
+    }
+    alias_usages
+        .into_iter()
+        .filter(|(_, usages)| usages.len() > 1)
+        .map(|(alias, usages)| SchemaIssue::DuplicateAlias {
+            alias: alias.to_string(),
+            usages,
+        })
+        .collect()
+}
+
+/// Every check but the aliases, which depend on how the nodes were collected.
+fn validate_root(root: &Root) -> Vec<SchemaIssue> {
+    let mut nodes = root.0.iter().collect::<Vec<_>>();
+    nodes.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut issues = vec![];
+    let mut field_names: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+
+    for (_, node) in &nodes {
+        field_names
+            .entry(node.field_name.as_str())
+            .or_default()
+            .push(node.alias.clone());
+
+        let mut fields = node.fields.iter().collect::<Vec<_>>();
+        fields.sort_by(|a, b| a.0.cmp(b.0));
+        for (field_name, field) in fields {
+            if let Field::Join(join_info) = field {
+                let extends = &join_info.extends;
+                if !root.0.contains_key(&extends.extends) {
+                    issues.push(SchemaIssue::DanglingExtends {
+                        node: node.alias.clone(),
+                        field: field_name.clone(),
+                        extends: extends.extends.clone(),
+                    });
+                }
+            }
+            if let Field::Recursive(_) = field
+                && node.unique_key.is_none()
+            {
+                issues.push(SchemaIssue::RecursiveWithoutUniqueKey {
+                    node: node.alias.clone(),
+                    field: field_name.clone(),
+                });
+            }
+        }
+        if let Some(Polymorphic::Union(union)) = &node.polymorphic {
+            for member in &union.members {
+                match root.0.get(&member.extends) {
+                    None => issues.push(SchemaIssue::DanglingMember {
+                        node: node.alias.clone(),
+                        member: member.extends.clone(),
+                    }),
+                    Some(member_node) if member_node.unique_key.is_none() => {
+                        issues.push(SchemaIssue::MemberWithoutUniqueKey {
+                            node: node.alias.clone(),
+                            member: member.extends.clone(),
+                        })
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+    }
+
+    let mut entry_points = vec![];
+    for (field_name, aliases) in field_names {
+        // Nodes that are only ever joined have no root field to share
+        if aliases.len() > 1 && !field_name.is_empty() {
+            issues.push(SchemaIssue::DuplicateFieldName {
+                field_name: field_name.to_string(),
+                nodes: aliases,
+            });
+        } else if is_graphql_name(field_name) {
+            entry_points.extend(aliases);
+        }
+    }
+
+    let mut reached = HashSet::new();
+    let mut pending = entry_points
+        .iter()
+        .filter_map(|alias| root.0.get(alias))
+        .collect::<Vec<_>>();
+    while let Some(node) = pending.pop() {
+        if reached.insert(node.alias.clone()) {
+            pending.extend(
+                joins(node)
+                    .into_iter()
+                    .filter_map(|(_, _, extends, _)| root.0.get(extends)),
+            );
+        }
+    }
+    issues.extend(out_of_scope(root));
+    for (_, node) in nodes {
+        if !reached.contains(&node.alias) {
+            issues.push(SchemaIssue::UnreachableNode {
+                node: node.alias.clone(),
+            });
+        }
+    }
+
+    issues
+}
+
+/// The joins and union members of `node`, as the field, alias and node each joins and its
+/// condition. Members are joined under their own alias.
+fn joins(node: &Node) -> Vec<(&str, &String, &String, &SqlExpr)> {
+    let mut fields = node.fields.iter().collect::<Vec<_>>();
+    fields.sort_by(|a, b| a.0.cmp(b.0));
+    let mut joins = fields
+        .into_iter()
+        .filter_map(|(field_name, field)| match field {
+            Field::Join(join_info) => Some((
+                field_name.as_str(),
+                &join_info.extends.alias,
+                &join_info.extends.extends,
+                &join_info.join.on,
+            )),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if let Some(Polymorphic::Union(union)) = &node.polymorphic {
+        joins.extend(
+            union
+                .members
+                .iter()
+                .map(|member| ("members", &member.extends, &member.extends, &member.on)),
+        );
+    }
+    joins
+}
+
+/// The join conditions referring to an alias other than the two sides of their join: the
+/// node, under its own alias or any it is joined as, and the joined alias, besides `$parent`
+/// and `$child`. Each node is checked once, however many paths reach it.
+fn out_of_scope(root: &Root) -> Vec<SchemaIssue> {
+    let mut nodes = root.0.values().collect::<Vec<_>>();
+    nodes.sort_by(|a, b| a.alias.cmp(&b.alias));
+
+    let mut joined_as: HashMap<&str, HashSet<&str>> = HashMap::new();
+    for node in &nodes {
+        for (_, child_alias, extends, _) in joins(node) {
+            joined_as
+                .entry(extends.as_str())
+                .or_default()
+                .insert(child_alias.as_str());
+        }
+    }
+
+    let mut issues = vec![];
+    for node in nodes {
+        for (field_name, child_alias, _, on) in joins(node) {
+            for table in on.table_refs() {
+                let in_scope = table == *child_alias
+                    || table == node.alias
+                    || table == PARENT
+                    || table == CHILD
+                    || joined_as
+                        .get(node.alias.as_str())
+                        .is_some_and(|aliases| aliases.contains(table.as_str()));
+                let issue = SchemaIssue::AliasOutOfScope {
+                    node: node.alias.clone(),
+                    field: field_name.to_string(),
+                    alias: table,
+                };
+                if !in_scope && !issues.contains(&issue) {
+                    issues.push(issue);
+                }
+            }
+        }
+    }
+    issues
+}
+
+fn is_graphql_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -0,0 +1,126 @@
+use std::collections::{BTreeMap, HashMap};
+
+use graphql_parser::query::Value;
+
+use crate::core::{error::Error, schema::Variables, shared_schema::SqlValue};
+
+/// The value of a variable as an argument value. Objects and lists keep their structure, and
+/// each scalar in them is a variable of its own named by its path, e.g. `$filter_title_eq`.
+#[derive(Clone, Debug)]
+enum Input {
+    Null,
+    Scalar(String),
+    List(Vec<Input>),
+    Object(BTreeMap<String, Input>),
+}
+
+impl Input {
+    fn new(
+        name: String,
+        value: &serde_json::Value,
+        values: &mut HashMap<String, SqlValue>,
+    ) -> Result<Self, Error> {
+        Ok(match value {
+            serde_json::Value::Null => Input::Null,
+            serde_json::Value::Array(items) => Input::List(
+                items
+                    .iter()
+                    .enumerate()
+                    .map(|(index, item)| Input::new(format!("{}_{}", name, index), item, values))
+                    .collect::<Result<_, _>>()?,
+            ),
+            serde_json::Value::Object(fields) => Input::Object(
+                fields
+                    .iter()
+                    .map(|(key, field)| {
+                        let input = Input::new(format!("{}_{}", name, key), field, values)?;
+                        Ok((key.clone(), input))
+                    })
+                    .collect::<Result<_, Error>>()?,
+            ),
+            scalar => {
+                values.insert(name.clone(), SqlValue::try_from(scalar)?);
+                Input::Scalar(name)
+            }
+        })
+    }
+
+    fn to_value(&self) -> Value<'_, &str> {
+        match self {
+            Input::Null => Value::Null,
+            Input::Scalar(name) => Value::Variable(name.as_str()),
+            Input::List(items) => Value::List(items.iter().map(Input::to_value).collect()),
+            Input::Object(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(key, field)| (key.as_str(), field.to_value()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// The variables given with a query: the argument values they stand for, and the values
+/// their params bind.
+///
+/// An object or list variable, like `where: $filter` or `input: $input`, is compiled as if it
+/// were written inline with a variable in place of each scalar, so only its structure shapes
+/// the SQL.
+#[derive(Clone, Debug, Default)]
+pub struct InputVariables {
+    inputs: BTreeMap<String, Input>,
+    /// The value of each scalar variable, and of each scalar in an object or list variable
+    pub values: HashMap<String, SqlValue>,
+}
+
+impl InputVariables {
+    pub fn new(variables: Option<&Variables>) -> Result<Self, Error> {
+        let mut inputs = BTreeMap::new();
+        let mut values = HashMap::new();
+        for (name, value) in variables.iter().flat_map(|variables| &variables.0) {
+            inputs.insert(name.clone(), Input::new(name.clone(), value, &mut values)?);
+        }
+        Ok(InputVariables { inputs, values })
+    }
+
+    /// Marks `name` given without a value of its own, as a variable with a default or a
+    /// hoisted literal is.
+    pub fn give(&mut self, name: &str) {
+        self.inputs
+            .entry(name.to_string())
+            .or_insert_with(|| Input::Scalar(name.to_string()));
+    }
+
+    /// Whether `value` is a variable that isn't given, which leaves out whatever it is the
+    /// value of rather than binding a null.
+    pub fn omits<'a>(&self, value: &Value<'a, &'a str>) -> bool {
+        match value {
+            Value::Variable(name) => {
+                !self.inputs.contains_key(*name) && !self.values.contains_key(*name)
+            }
+            _ => false,
+        }
+    }
+
+    /// Which variables are given and the structure of each, which is all of them a plan
+    /// depends on.
+    pub fn shape(&self) -> String {
+        format!("{:?}", self.inputs)
+    }
+
+    /// Calls `f` with `value`, or when it is a variable with the argument value it stands for.
+    /// `None` when the variable isn't given.
+    pub fn expand<'a, R>(
+        &self,
+        value: &Value<'a, &'a str>,
+        f: impl for<'v> FnOnce(&Value<'v, &'v str>) -> Result<R, Error>,
+    ) -> Result<Option<R>, Error> {
+        match value {
+            Value::Variable(name) => match self.inputs.get(*name) {
+                Some(input) => f(&input.to_value()).map(Some),
+                None => Ok(None),
+            },
+            value => f(value).map(Some),
+        }
+    }
+}
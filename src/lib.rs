@@ -4,15 +4,45 @@
 // - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
 // - MIT license (https://opensource.org/licenses/MIT)
 // at your option.
-mod core;
+pub mod core;
+#[cfg(feature = "wasm")]
 pub mod wasm;
 
+pub use crate::core::{
+    builder::{RootBuilder, case_when, child, col, exists, func, literal, on, param, parent, raw},
+    compiled::CompiledSchema,
+    error::Error,
+    fns::{build_sql_query, build_sql_query_with_params},
+    hydrate::{Row, hydrate, hydrate_results},
+    schema::{
+        BuilderType, ColumnInfo, ExtendsNode, Field, JoinInfo, Node, Options, RecursiveInfo, Root,
+        Variables,
+    },
+    shared_schema::{SqlExpr, SqlValue},
+    sql_schema::SqlQuery,
+};
+
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
+
     use crate::core::{
-        fns::build_sql_query,
-        schema::{ExtendsNode, Field, JoinInfo, Node, Root},
-        shared_schema::{Join, JoinType, SqlExpr},
+        builder::{case_when, child, col, exists, func, literal, on, param, parent, raw},
+        cache::normalize_query,
+        compiled::CompiledSchema,
+        cost::{CostPath, estimate_cost},
+        error::Error,
+        fns::{build_sql_query, build_sql_query_with_params, parse_gql},
+        hydrate::{Row, hydrate_results},
+        persisted::{Manifest, NamedQuery, QueryRegistry, query_hash},
+        schema::{
+            BuilderType, ColumnInfo, ExtendsNode, Field, JoinInfo, METADATA_VERSION, Node, Options,
+            OrderBy, OrderDirection, RecursiveInfo, Root, Variables, WhereInfo,
+        },
+        sdl::{parse_sdl, print_sdl},
+        shared_schema::{AndExpr, ColumnRef, EqExpr, Join, JoinType, SqlExpr, SqlParam, SqlValue},
+        validate::{SchemaIssue, validate_nodes, validate_schema},
+        variables::InputVariables,
     };
 
     fn assert_contains(string: &str, substring: &str) {
@@ -56,6 +86,14 @@ mod tests {
             alias: comment_alias.into(),
             field_name: "comments".into(),
             table: "comments".into(),
+            graphql_type: None,
+            many: true,
+            unique_key: None,
+            filterable: false,
+            sortable: false,
+            mutable: false,
+            upsert_columns: None,
+            polymorphic: None,
             fields: hm! {
                 "title" => Field::Column("title".into()),
                 "content" => Field::Column("content".into()),
@@ -69,6 +107,8 @@ mod tests {
                             alias: comment_author_alias.into(),
                             field_name: "author".into(),
                         },
+                        many: false,
+                        cost: None,
                 }),
             },
         };
@@ -77,6 +117,14 @@ mod tests {
             alias: post_alias.into(),
             field_name: "posts".into(),
             table: "posts".into(),
+            graphql_type: None,
+            many: true,
+            unique_key: None,
+            filterable: false,
+            sortable: false,
+            mutable: false,
+            upsert_columns: None,
+            polymorphic: None,
             fields: hm! {
                 "title" => Field::Column("title".into()),
                 "author" => Field::Join(JoinInfo {
@@ -89,6 +137,8 @@ mod tests {
                             alias: post_author_alias.into(),
                             field_name: "author".into(),
                         },
+                        many: false,
+                        cost: None,
                 }),
                 // "comments" => FieldMetadata::Join(JoinInfo { on_clause: format!("\"{}\".comment_ids IN (SELECT \"{}\".id)", post_alias, comment_alias), extends: SuperJoinExtendsNode { alias: comment_alias.into(), field_name: "comments".into(), extends: comment_alias.into() } })
             },
@@ -98,6 +148,14 @@ mod tests {
             alias: user_alias.into(),
             field_name: "user".into(),
             table: "users".into(),
+            graphql_type: None,
+            many: false,
+            unique_key: None,
+            filterable: false,
+            sortable: false,
+            mutable: false,
+            upsert_columns: None,
+            polymorphic: None,
             fields: hm! {
                 "id" => Field::Column("id".into()),
                 "name" => Field::Column("name".into()),
@@ -111,7 +169,8 @@ mod tests {
                             alias: post_alias.into(),
                             field_name: "posts".into(),
                         },
-
+                        many: true,
+                        cost: None,
                 }),
             },
         };
@@ -155,7 +214,7 @@ mod tests {
         assert_contains(&sql, "JOIN");
         assert_contains(&sql, &format!("\"{}\".post_id", &schema.aliases.user));
         assert_contains(&sql, &format!("\"{}\".author_id", &schema.aliases.post));
-        assert_contains(&sql, &schema.aliases.user);
+        assert_contains(&sql, schema.aliases.user);
     }
 
     #[test]
@@ -174,4 +233,1875 @@ mod tests {
         assert_contains(&sql, "JOIN");
         assert_contains(&sql, schema.aliases.user);
     }
+
+    #[test]
+    fn test_print_sdl() {
+        let schema = get_schema();
+        let sdl = print_sdl(&schema.schema);
+
+        assert_contains(
+            &sdl,
+            "type User {\n  id: ID\n  name: String\n  posts: [Post!]!\n}",
+        );
+        assert_contains(&sdl, "type Post {\n  author: User\n  title: String\n}");
+        assert_contains(&sdl, "type Comment {");
+        assert_contains(
+            &sdl,
+            "type Query {\n  comments: [Comment!]!\n  posts: [Post!]!\n  user: User\n}",
+        );
+    }
+
+    #[test]
+    fn test_validate_schema() {
+        let mut schema = get_schema().schema;
+
+        assert_eq!(validate_schema(&schema), vec![]);
+
+        let comment = schema.0.get_mut("comment_1").unwrap();
+        comment.field_name = "posts".into();
+        if let Some(Field::Join(join_info)) = comment.fields.get_mut("author") {
+            join_info.extends.extends = "account_1".into();
+        }
+        let post = schema.0.get_mut("post_1").unwrap();
+        if let Some(Field::Join(join_info)) = post.fields.get_mut("author") {
+            join_info.join.on =
+                SqlExpr::Raw(String::from("comment_1.author_id = user_2.id").into());
+        }
+        let issues = validate_schema(&schema);
+
+        assert!(issues.contains(&SchemaIssue::DanglingExtends {
+            node: "comment_1".into(),
+            field: "author".into(),
+            extends: "account_1".into(),
+        }));
+        assert!(issues.contains(&SchemaIssue::DuplicateFieldName {
+            field_name: "posts".into(),
+            nodes: vec!["comment_1".into(), "post_1".into()],
+        }));
+        assert!(issues.contains(&SchemaIssue::AliasOutOfScope {
+            node: "post_1".into(),
+            field: "author".into(),
+            alias: "comment_1".into(),
+        }));
+        assert!(issues.contains(&SchemaIssue::UnreachableNode {
+            node: "comment_1".into(),
+        }));
+
+        // Nodes sharing an alias, and joins from different parents sharing one
+        let author = |node: Node| {
+            node.join(
+                "author",
+                JoinInfo::to("user_1", on(parent("author_id").eq(child("id")))).alias("user_2"),
+            )
+        };
+        let (_, issues) = validate_nodes(vec![
+            Node::table("users").alias("user_1").field_name("users"),
+            author(Node::table("posts").alias("post_1").field_name("posts")),
+            author(
+                Node::table("comments")
+                    .alias("comment_1")
+                    .field_name("comments"),
+            ),
+            Node::table("people").alias("user_1"),
+        ]);
+        assert!(issues.contains(&SchemaIssue::DuplicateAlias {
+            alias: "user_1".into(),
+            usages: vec!["users".into(), "people".into()],
+        }));
+        assert!(issues.contains(&SchemaIssue::DuplicateAlias {
+            alias: "user_2".into(),
+            usages: vec!["post_1.author".into(), "comment_1.author".into()],
+        }));
+
+        // Every node joining every other still validates at once, each node checked once
+        let names = (0..8).map(|n| format!("t{}", n)).collect::<Vec<_>>();
+        let nodes = names.iter().map(|name| {
+            let mut node = Node::table(name.as_str())
+                .alias(name.as_str())
+                .field_name(name.as_str());
+            for other in names.iter().filter(|other| *other != name) {
+                node = node.join(
+                    other.as_str(),
+                    JoinInfo::to(other.as_str(), on(parent("id").eq(child("id"))))
+                        .alias(format!("{}_{}", name, other)),
+                );
+            }
+            node
+        });
+        let (_, issues) = validate_nodes(nodes.collect());
+        assert_eq!(issues, vec![]);
+    }
+
+    fn get_friends_schema() -> Root {
+        let user = Node {
+            alias: "user_1".into(),
+            field_name: "user".into(),
+            table: "users".into(),
+            graphql_type: None,
+            many: false,
+            unique_key: None,
+            filterable: false,
+            sortable: false,
+            mutable: false,
+            upsert_columns: None,
+            polymorphic: None,
+            fields: hm! {
+                "name" => Field::Column("name".into()),
+                "friends" => Field::Join(JoinInfo {
+                    join: Join {
+                        on: SqlExpr::Raw(String::from("\"user_1\".id = \"friend_1\".friend_of_id").into()),
+                        kind: JoinType::LeftJoin,
+                    },
+                    extends: ExtendsNode {
+                        extends: "user_1".into(),
+                        alias: "friend_1".into(),
+                        field_name: "friends".into(),
+                    },
+                    many: true,
+                    cost: None,
+                }),
+            },
+        };
+        Root::from(vec![user])
+    }
+
+    #[test]
+    fn test_recursive_joins_get_fresh_aliases() {
+        let query = "{ user { friends { friends { name } } } }";
+        let sql = build_sql_query(query, get_friends_schema(), None).unwrap();
+
+        assert_contains(
+            &sql,
+            "\"users\" AS \"friend_1\" ON \"user_1\".id = \"friend_1\".friend_of_id",
+        );
+        assert_contains(
+            &sql,
+            "\"users\" AS \"friend_1_2\" ON \"friend_1\".id = \"friend_1_2\".friend_of_id",
+        );
+        assert_contains(&sql, "\"friend_1_2\".\"name\"");
+
+        // Renamed qualifiers are quoted for the dialect, which MySQL reads as identifiers
+        let options = Options {
+            builder: BuilderType::MySql,
+            ..Default::default()
+        };
+        let sql = build_sql_query(query, get_friends_schema(), Some(options)).unwrap();
+        assert_contains(
+            &sql,
+            "`users` AS `friend_1_2` ON `friend_1`.id = `friend_1_2`.friend_of_id",
+        );
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let query = "{ user { friends { friends { friends { name } } } } }";
+        let options = Options {
+            max_depth: Some(2),
+            ..Default::default()
+        };
+        let err = build_sql_query(query, get_friends_schema(), Some(options)).unwrap_err();
+
+        assert_eq!(
+            err,
+            Error::MaxDepthExceeded {
+                max_depth: 2,
+                path: vec![
+                    "user".into(),
+                    "friends".into(),
+                    "friends".into(),
+                    "friends".into()
+                ],
+            }
+        );
+
+        let options = Options {
+            max_depth: Some(3),
+            ..Default::default()
+        };
+        assert!(build_sql_query(query, get_friends_schema(), Some(options)).is_ok());
+    }
+
+    #[test]
+    fn test_estimate_cost() {
+        let mut schema = get_schema().schema;
+        if let Some(Field::Column(column)) = schema
+            .0
+            .get_mut("user_1")
+            .and_then(|user| user.fields.get_mut("name"))
+        {
+            column.cost = Some(3);
+        }
+        let doc = parse_gql("{ user { posts(limit: 4) { title author { name } } } }").unwrap();
+        let cost = estimate_cost(&doc, &schema, &InputVariables::default()).unwrap();
+
+        // posts: 1, each of the 4 posts joins an author (1) and selects its name (3)
+        assert_eq!(cost.total, 1 + 4 * (1 + 3));
+        assert_eq!(
+            cost.paths[0],
+            CostPath {
+                path: "user.posts.author.name".into(),
+                cost: 12,
+            }
+        );
+
+        // Named operations and mutations cost the same as the selection written on its own
+        for query in [
+            "query { user { posts(limit: 4) { title author { name } } } }",
+            "query Posts($id: Int) { user { posts(limit: 4) { title author { name } } } }",
+        ] {
+            let doc = parse_gql(query).unwrap();
+            let variables = InputVariables::default();
+            assert_eq!(estimate_cost(&doc, &schema, &variables).unwrap().total, 17);
+        }
+        schema.0.get_mut("user_1").unwrap().mutable = true;
+        let doc = parse_gql("mutation { updateUser(id: 1, patch: {}) { name } }").unwrap();
+        let variables = InputVariables::default();
+        assert_eq!(estimate_cost(&doc, &schema, &variables).unwrap().total, 3);
+
+        // A variable limit costs what it is given, or else what it falls back to
+        let doc = parse_gql(
+            "query($limit: Int) { user { posts(limit: $limit) { title author { name } } } }",
+        )
+        .unwrap();
+        let cost = |variables: Option<serde_json::Value>| {
+            let variables =
+                variables.map(|variables| Variables(serde_json::from_value(variables).unwrap()));
+            let variables = InputVariables::new(variables.as_ref()).unwrap();
+            estimate_cost(&doc, &schema, &variables).unwrap().total
+        };
+        assert_eq!(cost(Some(serde_json::json!({ "limit": 2 }))), 1 + 2 * 4);
+        assert_eq!(cost(Some(serde_json::json!({ "limit": 100 }))), 1 + 100 * 4);
+        assert_eq!(cost(None), 1 + 10 * 4);
+    }
+
+    #[test]
+    fn test_limits() {
+        let root = || {
+            let mut root = Root::builder()
+                .node(
+                    Node::table("users")
+                        .alias("user_1")
+                        .field_name("users")
+                        .many()
+                        .unique_key("id")
+                        .column("name")
+                        .join_many("posts", "post_1", on(parent("id").eq(child("author_id")))),
+                )
+                .node(Node::table("posts").alias("post_1").column("title"))
+                .build()
+                .unwrap();
+            let post = root.0.get_mut("post_1").unwrap();
+            post.fields.insert("limit".into(), Field::Limit(3.into()));
+            root
+        };
+
+        let built = build_sql_query_with_params(
+            "{ users(first: 2) { name posts { title } } }",
+            &root(),
+            None,
+        )
+        .unwrap();
+        assert_contains(
+            &built.sql,
+            "FROM (SELECT \"user_1\".*, ROW_NUMBER() OVER (  ORDER BY \"user_1\".\"id\" ASC ) AS \"__row\" FROM \"users\" AS \"user_1\") AS \"user_1\"",
+        );
+        assert_contains(
+            &built.sql,
+            "LEFT JOIN (SELECT \"post_1\".*, \"user_1\".\"id\" AS \"__parent_key\", ROW_NUMBER() OVER ( PARTITION BY \"user_1\".\"id\" ) AS \"__row\" FROM \"posts\" AS \"post_1\" INNER JOIN \"users\" AS \"user_1\" ON \"user_1\".\"id\" = \"post_1\".\"author_id\") AS \"post_1\" ON \"post_1\".\"__parent_key\" = \"user_1\".\"id\" AND \"post_1\".\"__row\" <= $1",
+        );
+        assert_contains(&built.sql, "WHERE \"user_1\".\"__row\" <= $2");
+        assert_eq!(
+            built.params,
+            vec![SqlValue::Int(Some(3).into()), SqlValue::Int(Some(2).into())]
+        );
+
+        let err = build_sql_query("{ users { posts(limit: \"3\") { title } } }", root(), None)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The limit argument of posts must be an Int, got \"3\""
+        );
+
+        // Nested lists are numbered per parent row, told apart by its key
+        let mut root = root();
+        root.0.get_mut("user_1").unwrap().unique_key = None;
+        let err = build_sql_query("{ users { posts { title } } }", root, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "User.posts is limited, so User needs a unique_key"
+        );
+    }
+
+    #[test]
+    fn test_omitted_variables() {
+        let compiled = CompiledSchema::with_plan_cache(
+            Root::builder()
+                .node(
+                    Node::table("posts")
+                        .alias("post_1")
+                        .field_name("posts")
+                        .many()
+                        .unique_key("id")
+                        .filterable()
+                        .mutable()
+                        .column("id")
+                        .column("title"),
+                )
+                .build()
+                .unwrap(),
+            4,
+        );
+        let query = "query($id: Int, $title: String, $limit: Int) { posts(id: $id, where: { title: { eq: $title }, id: { in: [1, $id] } }, limit: $limit) { title } }";
+
+        // What a variable that isn't given is the value of is left out, never bound as null
+        let sql = compiled.build(query, None, None).unwrap();
+        assert_eq!(
+            sql.sql,
+            "SELECT \"post_1\".\"title\" AS \"post_1_title\", \"post_1\".\"id\" AS \"post_1_id\" FROM \"posts\" AS \"post_1\" WHERE \"post_1\".\"id\" IN ($1) ORDER BY \"post_1\".\"id\" ASC"
+        );
+        assert_eq!(sql.params, vec![SqlValue::Int(Some(1).into())]);
+        let variables =
+            Variables(hm! { "id" => serde_json::json!(2), "limit" => serde_json::json!(5) });
+        let sql = compiled.build(query, Some(&variables), None).unwrap();
+        assert_contains(
+            &sql.sql,
+            "WHERE \"post_1\".\"id\" = $1 AND \"post_1\".\"id\" IN ($2, $3)) AS \"post_1\" WHERE \"post_1\".\"__row\" <= $4",
+        );
+        assert_eq!(sql.positions["id"], vec![0, 2]);
+
+        let sql = compiled
+            .build(
+                "mutation($title: String) { createPost(input: { title: $title }) { id } }",
+                None,
+                None,
+            )
+            .unwrap();
+        assert_contains(&sql.sql, "INSERT INTO \"posts\" VALUES (DEFAULT)");
+        let err = compiled
+            .build(
+                "mutation($id: Int) { deletePost(id: $id) { id } }",
+                None,
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(err.to_string(), "deletePost is missing its id argument");
+    }
+
+    #[test]
+    fn test_max_cost() {
+        let query = "{ user { posts { title author { name } } } }";
+        let options = Options {
+            max_cost: Some(5),
+            ..Default::default()
+        };
+        let err = build_sql_query(query, get_schema().schema, Some(options)).unwrap_err();
+
+        let Error::MaxCostExceeded { cost, paths, .. } = err else {
+            panic!("expected a cost error, got {:?}", err);
+        };
+        // The default list size of 10 applies to posts
+        assert_eq!(cost, 11);
+        assert_eq!(paths[0].path, "user.posts.author");
+
+        let options = Options {
+            max_cost: Some(11),
+            ..Default::default()
+        };
+        assert!(build_sql_query(query, get_schema().schema, Some(options)).is_ok());
+
+        let options = Options {
+            max_cost: Some(5),
+            ..Default::default()
+        };
+        let query = "query { user { posts { title author { name } } } }";
+        let err = build_sql_query(query, get_schema().schema, Some(options.clone())).unwrap_err();
+        assert!(
+            matches!(err, Error::MaxCostExceeded { cost: 11, .. }),
+            "{:?}",
+            err
+        );
+
+        // A variable limit is checked against the value each call gives it, cached or not
+        let query = "query($n: Int) { user { posts(limit: $n) { title author { name } } } }";
+        let limit = |n: i32| Variables(hm! { "n" => serde_json::json!(n) });
+        let schema = || {
+            let mut schema = get_schema().schema;
+            schema.0.get_mut("user_1").unwrap().unique_key = Some("id".into());
+            schema
+        };
+        let compiled = CompiledSchema::new(schema());
+        let cached = CompiledSchema::with_plan_cache(schema(), 8);
+        for schema in [&compiled, &cached] {
+            schema
+                .build(query, Some(&limit(2)), Some(&options))
+                .unwrap();
+            let err = schema
+                .build(query, Some(&limit(100)), Some(&options))
+                .unwrap_err();
+            assert!(
+                matches!(err, Error::MaxCostExceeded { cost: 101, .. }),
+                "{:?}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_persisted_queries() {
+        let mut schema = get_schema().schema;
+        schema.0.get_mut("post_1").unwrap().filterable = true;
+        let posts = "{ posts { title author { name } } }";
+        let titled = "query($title: String, $limit: Int = 5) { posts(where: { title: { eq: $title } }, limit: $limit) { title } }";
+        let queries = vec![
+            NamedQuery {
+                name: "Posts".into(),
+                query: posts.into(),
+            },
+            NamedQuery {
+                name: "User".into(),
+                query: "{ user { name } }".into(),
+            },
+            NamedQuery {
+                name: "Titled".into(),
+                query: titled.into(),
+            },
+        ];
+        let manifest = Manifest::compile(&queries, &schema, None).unwrap();
+        let manifest = Manifest::from_json(&manifest.to_json().unwrap()).unwrap();
+
+        assert_eq!(manifest.queries.len(), 3);
+        let persisted = manifest.get(&query_hash(posts)).unwrap().clone();
+        assert_eq!(persisted.name, "Posts");
+        assert_eq!(
+            persisted.sql,
+            build_sql_query(posts, get_schema().schema, None).unwrap()
+        );
+
+        let registry = QueryRegistry::new(manifest.clone(), false);
+        let unregistered = "{ user { id } }";
+        assert!(registry.build(unregistered, None, &schema, None).is_ok());
+
+        // Every declared variable has a param, whether or not a call gives it
+        let text = |value: Option<&str>| SqlValue::Text(value.map(str::to_string).into());
+        let param =
+            |query: &crate::SqlQuery, name: &str| query.params[query.positions[name][0]].clone();
+        let hash = query_hash(titled);
+        let variables = Variables(hm! { "title" => serde_json::json!("Rust") });
+        let given = registry.build_by_hash(&hash, Some(&variables)).unwrap();
+        let omitted = registry.build_by_hash(&hash, None).unwrap();
+        assert_contains(&given.sql, "WHERE");
+        assert_eq!(given.sql, omitted.sql);
+        assert_eq!(param(&given, "title"), text(Some("Rust")));
+        assert_eq!(param(&given, "limit"), SqlValue::Int(Some(5).into()));
+        assert_eq!(param(&omitted, "title"), text(None));
+
+        let registry = QueryRegistry::new(manifest, true);
+        assert_eq!(
+            registry.build(posts, None, &schema, None).unwrap().sql,
+            persisted.sql
+        );
+        assert_eq!(
+            registry
+                .build(unregistered, None, &schema, None)
+                .unwrap_err(),
+            Error::UnregisteredQuery {
+                hash: query_hash(unregistered)
+            }
+        );
+    }
+
+    #[test]
+    fn test_compiled_schema() {
+        let query = "{ user { id posts { title author { name } } } }";
+        let compiled = CompiledSchema::new(get_schema().schema);
+
+        assert_eq!(
+            compiled.build(query, None, None).unwrap().sql,
+            build_sql_query(query, get_schema().schema, None).unwrap()
+        );
+        assert_eq!(compiled.root_node("posts").unwrap().alias, "post_1");
+        assert!(compiled.build("{ nope { id } }", None, None).is_err());
+    }
+
+    #[test]
+    fn test_variables_bind_params() {
+        let mut schema = get_schema().schema;
+        if let Some(Field::Join(join_info)) = schema
+            .0
+            .get_mut("post_1")
+            .and_then(|post| post.fields.get_mut("author"))
+        {
+            join_info.join.on = SqlExpr::And(AndExpr {
+                left: Box::new(join_info.join.on.clone()),
+                right: Box::new(SqlExpr::Eq(EqExpr {
+                    left: Box::new(SqlExpr::Column(ColumnRef {
+                        column: "status".into(),
+                        table: Some("user_2".into()),
+                    })),
+                    right: Box::new(SqlExpr::Param(SqlParam {
+                        name: "status".into(),
+                        value: SqlValue::Text(Some("active".to_string()).into()),
+                    })),
+                })),
+            });
+        }
+        let compiled = CompiledSchema::new(schema);
+        let status = |query: &str, variables: Option<&Variables>| {
+            let sql_ast = compiled.plan(query, variables, None).unwrap();
+            let SqlExpr::And(AndExpr { right, .. }) = &sql_ast.joins[0].join.on else {
+                panic!("expected the status condition");
+            };
+            let SqlExpr::Eq(EqExpr { right, .. }) = right.as_ref() else {
+                panic!("expected the status condition");
+            };
+            let SqlExpr::Param(param) = right.as_ref() else {
+                panic!("expected the status param");
+            };
+            param.value.clone()
+        };
+        let text = |value: &str| SqlValue::Text(Some(value.to_string()).into());
+
+        let query = "{ posts { author { name } } }";
+        assert_eq!(status(query, None), text("active"));
+
+        let query = "query Posts($status: String = \"draft\") { posts { author { name } } }";
+        assert_eq!(status(query, None), text("draft"));
+
+        let variables = Variables(hm! { "status" => serde_json::json!("banned") });
+        assert_eq!(status(query, Some(&variables)), text("banned"));
+    }
+
+    #[test]
+    fn test_normalize_query() {
+        let a = parse_gql("query A { user(id: 1) { posts { title } name } }").unwrap();
+        let b = parse_gql("{\n  user(id: 2) {\n    posts { title }\n    name\n  }\n}").unwrap();
+        let a = normalize_query(&a).unwrap();
+        let b = normalize_query(&b).unwrap();
+
+        // Selections keep their order, which decides the order of the SQL's columns
+        assert_eq!(
+            a.text,
+            "query { user(id: $__int0) { posts { title } name } }"
+        );
+        assert_eq!(a.text, b.text);
+        assert_eq!(a.values["__int0"], SqlValue::Int(Some(1).into()));
+        assert_eq!(b.values["__int0"], SqlValue::Int(Some(2).into()));
+
+        // Literals of different kinds never share a plan, and input object fields are
+        // printed in name order
+        let normalize = |query: &str| normalize_query(&parse_gql(query).unwrap()).unwrap().text;
+        assert_eq!(
+            normalize("{ posts(limit: \"2\") { title } }"),
+            "query { posts(limit: $__string0) { title } }"
+        );
+        assert_eq!(
+            normalize("{ posts(limit: 2.5) { title } }"),
+            "query { posts(limit: $__float0) { title } }"
+        );
+        assert_eq!(
+            normalize("{ posts(where: { title: { eq: \"a\" }, id: { eq: 1 } }) { title } }"),
+            normalize("{ posts(where: { id: { eq: 2 }, title: { eq: \"b\" } }) { title } }"),
+        );
+
+        // Enums can change the shape of the SQL, so they are part of the text
+        let c = parse_gql("{ user(order: DESC) { name } }").unwrap();
+        assert_eq!(
+            normalize_query(&c).unwrap().text,
+            "query { user(order: DESC) { name } }"
+        );
+    }
+
+    #[test]
+    fn test_plan_cache() {
+        let compiled = CompiledSchema::with_plan_cache(get_schema().schema, 8);
+        let uncached = CompiledSchema::new(get_schema().schema);
+        let query = "{ user(id: 7) { posts { title author { name } } name } posts { title } }";
+        let rebound = "query Rebound { user(id: 8) { posts { title author { name } } name } posts { title } }";
+
+        // Built from the cache or not, the same query gives the same SQL, params and positions
+        let first = compiled.build(query, None, None).unwrap();
+        assert_eq!(first, uncached.build(query, None, None).unwrap());
+        assert_contains(&first.sql, "FROM \"users\" AS \"user_1\"");
+        assert_eq!(
+            first.positions,
+            BTreeMap::from([("user_1_id".into(), vec![0])])
+        );
+        assert_eq!(compiled.build(query, None, None).unwrap(), first);
+        assert_eq!(
+            compiled.build(rebound, None, None).unwrap(),
+            uncached.build(rebound, None, None).unwrap()
+        );
+
+        // A literal of another kind is planned on its own, and checked again
+        assert!(
+            compiled
+                .build("{ posts(limit: 2) { title } }", None, None)
+                .is_ok()
+        );
+        assert!(
+            compiled
+                .build("{ posts(limit: \"2\") { title } }", None, None)
+                .is_err()
+        );
+
+        let reordered = "{ user(id: 7) { name posts { title author { name } } } }";
+        assert_ne!(
+            compiled.build(reordered, None, None).unwrap().sql,
+            first.sql
+        );
+
+        let options = Options {
+            builder: BuilderType::MySql,
+            ..Default::default()
+        };
+        assert_ne!(
+            compiled.build(query, None, Some(&options)).unwrap().sql,
+            first.sql
+        );
+    }
+
+    fn rows(rows: serde_json::Value) -> Vec<Row> {
+        serde_json::from_value(rows).unwrap()
+    }
+
+    #[test]
+    fn test_hydrate_results() {
+        let mut schema = get_schema().schema;
+        schema.0.get_mut("post_1").unwrap().unique_key = Some("id".into());
+        let query = "{ user { name posts { title writer: author { name } } } }";
+
+        // The unique key is selected even though the query doesn't ask for it
+        let sql = build_sql_query(query, Root(schema.0.clone()), None).unwrap();
+        assert_contains(&sql, "\"post_1\".\"id\" AS \"post_1_id\"");
+
+        let hydrated = hydrate_results(
+            query,
+            &rows(serde_json::json!([
+                { "user_1_name": "Ann", "post_1_id": 1, "post_1_title": "Hi", "user_2_name": "Ann" },
+                { "user_1_name": "Ann", "post_1_id": 2, "post_1_title": "Hi", "user_2_name": null },
+            ])),
+            &schema,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            hydrated,
+            serde_json::json!({ "user": { "name": "Ann", "posts": [
+                { "title": "Hi", "writer": { "name": "Ann" } },
+                { "title": "Hi", "writer": null },
+            ] } })
+        );
+
+        // A LEFT JOIN that matched nothing hydrates to an empty list
+        let hydrated = hydrate_results(
+            query,
+            &rows(serde_json::json!([
+                { "user_1_name": "Bob", "post_1_id": null, "post_1_title": null, "user_2_name": null },
+            ])),
+            &schema,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            hydrated,
+            serde_json::json!({ "user": { "name": "Bob", "posts": [] } })
+        );
+
+        // Without a unique key, equal rows are still different posts
+        schema.0.get_mut("post_1").unwrap().unique_key = None;
+        let hydrated = hydrate_results(
+            "{ user { name posts { title } } }",
+            &rows(serde_json::json!([
+                { "user_1_name": "Ann", "post_1_title": "Hi" },
+                { "user_1_name": "Ann", "post_1_title": "Hi" },
+            ])),
+            &schema,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            hydrated,
+            serde_json::json!({ "user": { "name": "Ann", "posts": [
+                { "title": "Hi" },
+                { "title": "Hi" },
+            ] } })
+        );
+    }
+
+    #[test]
+    fn test_parse_sdl() {
+        let root = parse_sdl(
+            r#"
+            type User @sqlTable(name: "users", alias: "user_1", uniqueKey: "id") {
+              id: ID!
+              name: String @sqlColumn(name: "full_name")
+              posts: [Post!]! @sqlJoin(on: "\"user_1\".id = \"post_1\".author_id")
+            }
+            type Post @sqlTable(name: "posts", alias: "post_1") {
+              title: String
+              author: User @sqlJoin(on: "\"post_1\".author_id = \"user_2\".id", alias: "user_2")
+            }
+            type Query { users: [User!]! }
+            "#,
+        )
+        .unwrap();
+
+        let user = &root.0["user_1"];
+        assert_eq!(user.field_name, "users");
+        assert!(user.many);
+        assert_eq!(user.unique_key.as_deref(), Some("id"));
+        assert_eq!(root.0["post_1"].field_name, "");
+        assert!(validate_schema(&root).is_empty());
+
+        let sql =
+            build_sql_query("{ users { name posts { author { name } } } }", root, None).unwrap();
+        assert_contains(&sql, "\"user_1\".\"full_name\" AS \"user_1_full_name\"");
+        assert_contains(&sql, "LEFT JOIN \"users\" AS \"user_2\"");
+
+        let err =
+            parse_sdl("type Post @sqlTable(name: \"posts\") { author: User @sqlJoin(on: \"\") }")
+                .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Post.author joins User which has no @sqlTable"
+        );
+    }
+
+    #[test]
+    fn test_metadata_round_trip() {
+        let mut root = get_schema().schema;
+        let post = root.0.get_mut("post_1").unwrap();
+        post.fields.insert("limit".into(), Field::Limit(3.into()));
+        post.fields.insert(
+            "where".into(),
+            Field::Where(WhereInfo {
+                expr: raw("\"post_1\".draft = false"),
+            }),
+        );
+
+        let json = root.to_json().unwrap();
+        assert_contains(&json, &format!("\"version\": {}", METADATA_VERSION));
+        assert_contains(&json, "\"kind\": \"limit\",\n          \"value\": 3");
+        assert_eq!(Root::from_json(&json).unwrap().to_json().unwrap(), json);
+
+        // Unversioned arrays of nodes predate the envelope
+        let nodes = serde_json::to_string(
+            &serde_json::from_str::<serde_json::Value>(&json).unwrap()["nodes"],
+        )
+        .unwrap();
+        assert_eq!(Root::from_json(&nodes).unwrap().to_json().unwrap(), json);
+
+        // Version 1 `where` fields held raw SQL
+        let v1 = json
+            .replace("\"version\": 2", "\"version\": 1")
+            .replace(
+                "\"expr\": {\n            \"kind\": \"raw\",\n            \"value\": \"\\\"post_1\\\".draft = false\"\n          }",
+                "\"value\": \"\\\"post_1\\\".draft = false\"",
+            );
+        assert_ne!(v1, json);
+        assert_eq!(Root::from_json(&v1).unwrap().to_json().unwrap(), json);
+
+        let newer = Root::from_json("{ \"version\": 99, \"nodes\": [] }").unwrap_err();
+        assert_eq!(
+            newer.to_string(),
+            "metadata version 99 is newer than the supported version 2"
+        );
+    }
+
+    #[test]
+    fn test_root_builder() {
+        let root = Root::builder()
+            .node(
+                Node::table("users")
+                    .alias("user_1")
+                    .field_name("users")
+                    .many()
+                    .column("id")
+                    .column_as("name", "full_name")
+                    .join_many(
+                        "posts",
+                        "post_1",
+                        on(col("user_1", "id").eq(col("post_1", "author_id"))),
+                    ),
+            )
+            .node(
+                Node::table("posts").alias("post_1").column("title").join(
+                    "author",
+                    JoinInfo::to(
+                        "user_1",
+                        on(col("post_1", "author_id").eq(col("user_2", "id"))),
+                    )
+                    .alias("user_2"),
+                ),
+            )
+            .build()
+            .unwrap();
+
+        let sql =
+            build_sql_query("{ users { name posts { author { id } } } }", root, None).unwrap();
+        assert_contains(&sql, "\"user_1\".\"full_name\" AS \"user_1_full_name\"");
+        assert_contains(
+            &sql,
+            "LEFT JOIN \"posts\" AS \"post_1\" ON \"user_1\".\"id\" = \"post_1\".\"author_id\"",
+        );
+        assert_contains(
+            &sql,
+            "LEFT JOIN \"users\" AS \"user_2\" ON \"post_1\".\"author_id\" = \"user_2\".\"id\"",
+        );
+
+        let err = Root::builder()
+            .node(Node::table("users").field_name("users"))
+            .node(Node::table("users").field_name("people"))
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidSchema { ref issues } if issues.len() == 1));
+        assert_contains(
+            &err.to_string(),
+            "alias \"users\" is used by more than one node",
+        );
+    }
+
+    #[test]
+    fn test_argument_filters() {
+        let query = "{ user(id: 7) { name } }";
+        let built = build_sql_query_with_params(query, &get_schema().schema, None).unwrap();
+        assert_contains(&built.sql, "WHERE \"user_1\".\"id\" = $1");
+        assert_eq!(built.params, vec![SqlValue::Int(Some(7).into())]);
+
+        // Cached plans bind each query's literals
+        let compiled = CompiledSchema::with_plan_cache(get_schema().schema, 8);
+        let first = compiled.build(query, None, None).unwrap();
+        let second = compiled
+            .build("{ user(id: 8) { name } }", None, None)
+            .unwrap();
+        assert_eq!(first.sql, second.sql);
+        assert_eq!(first.params, vec![SqlValue::Int(Some(7).into())]);
+        assert_eq!(second.params, vec![SqlValue::Int(Some(8).into())]);
+    }
+
+    #[test]
+    fn test_join_placeholders() {
+        let mut schema = get_schema().schema;
+        for (alias, field, condition) in [
+            ("user_1", "posts", "$parent.post_id = $child.id"),
+            ("post_1", "author", "$parent.author_id = $child.id"),
+        ] {
+            if let Some(Field::Join(join_info)) =
+                schema.0.get_mut(alias).unwrap().fields.get_mut(field)
+            {
+                join_info.join.on = SqlExpr::Raw(String::from(condition).into());
+            }
+        }
+
+        // `posts` is joined twice, so its second join gets a fresh alias
+        let query = "{ user { posts { author { posts { title } } } } }";
+        let sql = build_sql_query(query, schema, None).unwrap();
+        assert_contains(&sql, "ON \"user_1\".post_id = \"post_1\".id");
+        assert_contains(&sql, "ON \"post_1\".author_id = \"user_2\".id");
+        assert_contains(&sql, "ON \"user_2\".post_id = \"post_1_2\".id");
+
+        let root = Root::builder()
+            .node(
+                Node::table("users")
+                    .alias("user_1")
+                    .field_name("users")
+                    .join_many("posts", "post_1", on(parent("id").eq(child("author_id")))),
+            )
+            .node(Node::table("posts").alias("post_1").column("title"))
+            .build()
+            .unwrap();
+        let sql = build_sql_query("{ users { posts { title } } }", root, None).unwrap();
+        assert_contains(&sql, "ON \"user_1\".\"id\" = \"post_1\".\"author_id\"");
+    }
+
+    #[test]
+    fn test_sql_expressions() {
+        let column = |name: &str| SqlExpr::Column(ColumnRef::from(name.to_string()));
+        let root = |filter: SqlExpr| {
+            Root::builder()
+                .node(
+                    Node::table("users")
+                        .alias("user_1")
+                        .field_name("users")
+                        .column("name")
+                        .filter(filter),
+                )
+                .build()
+                .unwrap()
+        };
+        let sql = |filter: SqlExpr, builder: BuilderType| {
+            let options = Options {
+                builder,
+                ..Default::default()
+            };
+            build_sql_query("{ users { name } }", root(filter), Some(options)).unwrap()
+        };
+
+        let ilike = column("name").ilike("a%");
+        assert_contains(
+            &sql(ilike.clone(), BuilderType::Postgres),
+            "\"user_1\".\"name\" ILIKE $1",
+        );
+        assert_contains(
+            &sql(ilike.clone(), BuilderType::Sqlite),
+            "LOWER(\"user_1\".\"name\") LIKE LOWER(?)",
+        );
+        assert_contains(
+            &sql(ilike, BuilderType::MySql),
+            "LOWER(`user_1`.`name`) LIKE LOWER(?)",
+        );
+
+        let filter = (column("age") + literal("1") * column("bonus"))
+            .between(literal("18"), literal("65"))
+            .and(column("id").not_in(vec![literal("1"), literal("2")]))
+            .and(func("coalesce", vec![column("name"), literal("")]).neq(literal("")))
+            .and(exists(
+                "posts",
+                "post_1",
+                column("author_id").eq(col("user_1", "id")),
+            ))
+            .and(
+                case_when(column("age").lt(literal("18")), literal("minor"))
+                    .otherwise(literal("adult"))
+                    .eq(literal("adult")),
+            );
+        let sql = sql(filter, BuilderType::Postgres);
+        assert_contains(
+            &sql,
+            "\"user_1\".\"age\" + ($1 * \"user_1\".\"bonus\") BETWEEN $2 AND $3",
+        );
+        assert_contains(&sql, "\"user_1\".\"id\" NOT IN ($4, $5)");
+        assert_contains(&sql, "coalesce(\"user_1\".\"name\", $6) <> $7");
+        assert_contains(
+            &sql,
+            "EXISTS(SELECT 1 FROM \"posts\" AS \"post_1\" WHERE \"post_1\".\"author_id\" = \"user_1\".\"id\")",
+        );
+        assert_contains(
+            &sql,
+            "(CASE WHEN (\"user_1\".\"age\" < $8) THEN $9 ELSE $10 END) = $11",
+        );
+    }
+
+    #[test]
+    fn test_param_positions() {
+        let user_id = || param("$userId", SqlValue::Int(None.into()));
+        let root = Root::builder()
+            .node(
+                Node::table("users")
+                    .alias("user_1")
+                    .field_name("users")
+                    .column("name")
+                    .filter(
+                        col("user_1", "id")
+                            .eq(user_id())
+                            .or(col("user_1", "name").eq(literal("root")))
+                            .or(col("user_1", "manager_id").eq(user_id())),
+                    ),
+            )
+            .build()
+            .unwrap();
+        let variables = Variables(hm! { "userId" => serde_json::json!(5) });
+        let compiled = CompiledSchema::new(root);
+        for builder in [
+            BuilderType::Sqlite,
+            BuilderType::Postgres,
+            BuilderType::MySql,
+        ] {
+            let options = Options {
+                builder,
+                ..Default::default()
+            };
+            let built = compiled
+                .build("{ users { name } }", Some(&variables), Some(&options))
+                .unwrap();
+            assert_eq!(
+                built.params,
+                vec![
+                    SqlValue::Int(Some(5).into()),
+                    SqlValue::Text(Some("root".into()).into()),
+                    SqlValue::Int(Some(5).into()),
+                ]
+            );
+            assert_eq!(
+                built.positions,
+                BTreeMap::from([("userId".to_string(), vec![0, 2])])
+            );
+        }
+
+        // Rendered integers too large for a param are errors rather than rounded
+        assert_eq!(
+            SqlValue::try_from(&sea_query::Value::BigInt(Some(1 << 40))),
+            Err("SQL parameter value BigInt(Some(1099511627776)) is out of range".to_string())
+        );
+        assert!(SqlValue::try_from(&sea_query::Value::BigUnsigned(Some(u64::MAX))).is_err());
+        assert_eq!(
+            SqlValue::try_from(&sea_query::Value::BigUnsigned(Some(7))),
+            Ok(SqlValue::Int(Some(7).into()))
+        );
+    }
+
+    #[test]
+    fn test_where_argument() {
+        let mut schema = get_schema().schema;
+        for node in schema.0.values_mut() {
+            node.filterable = true;
+        }
+
+        let query = "{ posts(where: { title: { contains: \"rust\" }, author: { name: { eq: \"Ann\" } } }) { title } }";
+        let built = build_sql_query_with_params(query, &schema, None).unwrap();
+        assert_contains(
+            &built.sql,
+            "WHERE EXISTS(SELECT 1 FROM \"users\" AS \"post_1_author\" WHERE (\"post_1\".author_id = \"post_1_author\".id) AND \"post_1_author\".\"name\" = $1) AND (\"post_1\".\"title\" LIKE ('%' || REPLACE(REPLACE(REPLACE($2, '!', '!!'), '%', '!%'), '_', '!_') || '%') ESCAPE '!')",
+        );
+        assert_eq!(
+            built.params,
+            vec![
+                SqlValue::Text(Some("Ann".into()).into()),
+                SqlValue::Text(Some("rust".into()).into()),
+            ]
+        );
+
+        let options = Options {
+            builder: BuilderType::MySql,
+            ..Default::default()
+        };
+        let built = build_sql_query_with_params(query, &schema, Some(&options)).unwrap();
+        assert_contains(
+            &built.sql,
+            "(`post_1`.`title` LIKE CONCAT('%', REPLACE(REPLACE(REPLACE(?, '!', '!!'), '%', '!%'), '_', '!_'), '%') ESCAPE '!')",
+        );
+        assert_eq!(built.params.len(), 2);
+
+        // Only the structure of the filter decides the cached SQL
+        let compiled = CompiledSchema::with_plan_cache(schema, 8);
+        let first = compiled.build(query, None, None).unwrap();
+        let second = compiled
+            .build(&query.replace("rust", "wasm"), None, None)
+            .unwrap();
+        assert_eq!(first.sql, second.sql);
+        assert_eq!(second.params[1], SqlValue::Text(Some("wasm".into()).into()));
+
+        let err = compiled
+            .build(
+                "{ posts(where: { body: { eq: 1 } }) { title } }",
+                None,
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Post has no column or join body to filter on"
+        );
+
+        // A filter variable compiles as if written inline, each scalar a variable of its own
+        let query = "query($where: PostWhere) { posts(where: $where) { title } }";
+        let variables = |filter: serde_json::Value| Variables(hm! { "where" => filter });
+        let sql = compiled
+            .build(
+                query,
+                Some(&variables(serde_json::json!({ "title": { "eq": "Hi" } }))),
+                None,
+            )
+            .unwrap();
+        assert_contains(&sql.sql, "WHERE \"post_1\".\"title\" = $1");
+        assert_eq!(sql.params, vec![SqlValue::Text(Some("Hi".into()).into())]);
+        assert_eq!(sql.positions["where_title_eq"], vec![0]);
+        let sql = compiled
+            .build(
+                query,
+                Some(&variables(serde_json::json!({ "title": { "eq": "Yo" } }))),
+                None,
+            )
+            .unwrap();
+        assert_eq!(sql.params, vec![SqlValue::Text(Some("Yo".into()).into())]);
+        let sql = compiled
+            .build(
+                query,
+                Some(&variables(serde_json::json!({ "title": { "eq": null } }))),
+                None,
+            )
+            .unwrap();
+        assert_contains(&sql.sql, "WHERE \"post_1\".\"title\" IS NULL");
+        // A filter that isn't given matches every row
+        assert_eq!(
+            compiled.build(query, None, None).unwrap().sql,
+            "SELECT \"post_1\".\"title\" AS \"post_1_title\" FROM \"posts\" AS \"post_1\""
+        );
+        let err = compiled
+            .build(query, Some(&variables(serde_json::json!("Hi"))), None)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "post_1_where must be an input object, got $where"
+        );
+
+        let sdl = print_sdl(&compiled.root);
+        assert_contains(&sdl, "  posts(where: PostWhere): [Post!]!\n");
+        assert_contains(
+            &sdl,
+            "input PostWhere {\n  AND: [PostWhere!]\n  NOT: PostWhere\n  OR: [PostWhere!]\n  author: UserWhere\n  title: StringFilter\n}",
+        );
+        assert_contains(
+            &sdl,
+            "input StringFilter {\n  contains: String\n  eq: String\n  gt: String\n  in: [String!]\n  isNull: Boolean\n  lt: String\n  neq: String\n}",
+        );
+
+        // A filter from the metadata is kept as a field named `where`, and applies alongside
+        // the argument
+        let mut schema = get_schema().schema;
+        let post = schema.0.get_mut("post_1").unwrap();
+        post.filterable = true;
+        post.fields.insert(
+            "where".into(),
+            Field::Where(WhereInfo {
+                expr: raw("\"post_1\".draft = false"),
+            }),
+        );
+        let built = build_sql_query_with_params(
+            "{ posts(where: { title: { eq: \"Hi\" } }) { title } }",
+            &schema,
+            None,
+        )
+        .unwrap();
+        assert_contains(
+            &built.sql,
+            "WHERE (\"post_1\".draft = false) AND \"post_1\".\"title\" = $1",
+        );
+
+        // Filtering on a join applies the joined node's filters, written against either alias
+        let mut schema = get_schema().schema;
+        for node in schema.0.values_mut() {
+            node.filterable = true;
+        }
+        schema.0.get_mut("user_1").unwrap().fields.insert(
+            "where".into(),
+            Field::Where(WhereInfo {
+                expr: raw("user_2.banned = false"),
+            }),
+        );
+        let built = build_sql_query_with_params(
+            "{ posts(where: { author: { name: { eq: \"Ann\" } } }) { title } }",
+            &schema,
+            None,
+        )
+        .unwrap();
+        assert_contains(
+            &built.sql,
+            "WHERE EXISTS(SELECT 1 FROM \"users\" AS \"post_1_author\" WHERE (\"post_1\".author_id = \"post_1_author\".id) AND (\"post_1_author\".banned = false) AND \"post_1_author\".\"name\" = $1)",
+        );
+    }
+
+    #[test]
+    fn test_order_by_argument() {
+        let mut schema = get_schema().schema;
+        for node in schema.0.values_mut() {
+            node.sortable = true;
+        }
+
+        let query = "{ posts(orderBy: [{ author: { field: NAME }, nulls: LAST }, { field: TITLE, direction: DESC }]) { title } }";
+        let built = build_sql_query_with_params(query, &schema, None).unwrap();
+        assert_contains(
+            &built.sql,
+            "LEFT JOIN \"users\" AS \"post_1_author\" ON \"post_1\".author_id = \"post_1_author\".id",
+        );
+        assert_contains(
+            &built.sql,
+            "ORDER BY \"post_1_author\".\"name\" ASC NULLS LAST, \"post_1\".\"title\" DESC",
+        );
+        assert!(built.params.is_empty());
+
+        let options = Options {
+            builder: BuilderType::MySql,
+            ..Default::default()
+        };
+        let built = build_sql_query_with_params(query, &schema, Some(&options)).unwrap();
+        assert_contains(
+            &built.sql,
+            "ORDER BY `post_1_author`.`name` IS NULL ASC, `post_1_author`.`name` ASC, `post_1`.`title` DESC",
+        );
+
+        // A to-one join the query selects is sorted through rather than joined again
+        let query =
+            "{ posts(limit: 2, orderBy: { author: { field: NAME } }) { title author { name } } }";
+        let sql = build_sql_query_with_params(query, &schema, None)
+            .unwrap()
+            .sql;
+        assert!(!sql.contains("post_1_author"), "{}", sql);
+        // The limit numbers the rows in the same order, through the same join
+        assert_contains(
+            &sql,
+            "ROW_NUMBER() OVER (  ORDER BY \"user_2\".\"name\" ASC ) AS \"__row\" FROM \"posts\" AS \"post_1\" LEFT JOIN \"users\" AS \"user_2\" ON \"post_1\".author_id = \"user_2\".id) AS \"post_1\" LEFT JOIN \"users\" AS \"user_2\"",
+        );
+        assert_contains(&sql, "ORDER BY \"user_2\".\"name\" ASC");
+
+        let err = build_sql_query_with_params(
+            "{ posts(orderBy: { field: BODY }) { title } }",
+            &schema,
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "Post has no column BODY to sort by");
+
+        let sdl = print_sdl(&schema);
+        assert_contains(&sdl, "  posts(orderBy: [PostOrderBy!]): [Post!]!\n");
+        assert_contains(
+            &sdl,
+            "input PostOrderBy {\n  author: UserOrderBy\n  direction: SortDirection\n  field: PostSortField\n  nulls: SortNulls\n}",
+        );
+        assert_contains(&sdl, "enum PostSortField {\n  TITLE\n}");
+        assert_contains(&sdl, "enum SortDirection {\n  ASC\n  DESC\n}");
+    }
+
+    #[test]
+    fn test_deterministic_order() {
+        let mut posts = Node::table("posts")
+            .alias("post_1")
+            .unique_key("id")
+            .column("title");
+        posts.fields.insert(
+            "newest".into(),
+            Field::OrderBy(OrderBy {
+                expr: ColumnInfo::from("created_at".to_string()),
+                direction: OrderDirection::Desc,
+            }),
+        );
+        let root = Root::builder()
+            .node(
+                Node::table("users")
+                    .alias("user_1")
+                    .field_name("users")
+                    .many()
+                    .unique_key("id")
+                    .sortable()
+                    .column("name")
+                    .join_many("posts", "post_1", on(parent("id").eq(child("author_id")))),
+            )
+            .node(posts)
+            .build()
+            .unwrap();
+
+        // Default sorts apply without being selected, children sort within their parents and
+        // unique keys break ties at every level
+        let query = "{ users { name posts { title } } }";
+        let sql = build_sql_query_with_params(query, &root, None).unwrap().sql;
+        assert_contains(
+            &sql,
+            "ORDER BY \"user_1\".\"id\" ASC, \"post_1\".\"created_at\" DESC, \"post_1\".\"id\" ASC",
+        );
+        let sql = build_sql_query_with_params(
+            "{ users(orderBy: { field: NAME }) { name } }",
+            &root,
+            None,
+        )
+        .unwrap()
+        .sql;
+        assert_contains(
+            &sql,
+            "ORDER BY \"user_1\".\"name\" ASC, \"user_1\".\"id\" ASC",
+        );
+
+        let options = Options {
+            unordered: true,
+            ..Default::default()
+        };
+        let sql = build_sql_query_with_params(query, &root, Some(&options))
+            .unwrap()
+            .sql;
+        assert!(!sql.contains("ORDER BY"), "{}", sql);
+    }
+
+    #[test]
+    fn test_polymorphic_nodes() {
+        let search = Node::table("search_results")
+            .alias("search_1")
+            .field_name("search")
+            .many()
+            .unique_key("id")
+            .graphql_type("SearchResult")
+            .member("post_1", parent("post_id").eq(child("id")))
+            .member("comment_1", parent("comment_id").eq(child("id")));
+        let root = Root::from(vec![
+            search.clone(),
+            Node::table("posts")
+                .alias("post_1")
+                .unique_key("id")
+                .column("title"),
+            Node::table("comments")
+                .alias("comment_1")
+                .unique_key("id")
+                .column("body"),
+            Node::table("content")
+                .alias("content_1")
+                .field_name("content")
+                .graphql_type("Content")
+                .column("title")
+                .single_table("type", [("post", "Article")]),
+        ]);
+        assert_eq!(validate_schema(&root), vec![]);
+
+        let sql = build_sql_query_with_params("{ search { ... on Post { title } } }", &root, None)
+            .unwrap()
+            .sql;
+        assert_contains(
+            &sql,
+            "LEFT JOIN \"posts\" AS \"post_1\" ON \"search_1\".\"post_id\" = \"post_1\".\"id\" LEFT JOIN \"comments\" AS \"comment_1\"",
+        );
+        assert_contains(&sql, "\"comment_1\".\"id\" AS \"comment_1_id\"");
+
+        // Fragments on a plain node's own type, or an interface it implements, always apply
+        let sql = build_sql_query_with_params(
+            "{ content { ... on Content { title } ... on Node { title } } }",
+            &root,
+            None,
+        )
+        .unwrap()
+        .sql;
+        assert_contains(&sql, "\"content_1\".\"type\" AS \"content_1_type\"");
+        assert_eq!(
+            hydrate_results(
+                "{ content { title type: __typename } }",
+                &rows(serde_json::json!([{ "content_1_title": "Hi", "content_1_type": "post" }])),
+                &root,
+                None
+            )
+            .unwrap(),
+            serde_json::json!({ "content": { "__typename": "Article", "title": "Hi", "type": "Article" } }),
+        );
+
+        let sdl = print_sdl(&root);
+        assert_contains(&sdl, "union SearchResult = Post | Comment\n");
+        assert_contains(&sdl, "interface Content {\n  title: String\n}");
+        assert_contains(
+            &sdl,
+            "type Article implements Content {\n  title: String\n}",
+        );
+
+        let mut root = root;
+        root.0.get_mut("comment_1").unwrap().unique_key = None;
+        assert_eq!(
+            validate_schema(&root),
+            vec![SchemaIssue::MemberWithoutUniqueKey {
+                node: "search_1".into(),
+                member: "comment_1".into(),
+            }]
+        );
+        let err =
+            build_sql_query_with_params("{ search { __typename } }", &root, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Comment, a member of SearchResult, needs a unique_key to tell which rows are one"
+        );
+    }
+
+    #[test]
+    fn test_typename() {
+        let root = Root::from(vec![
+            Node::table("people")
+                .alias("user_1")
+                .field_name("user")
+                .graphql_type("Person")
+                .column("name"),
+        ]);
+        let query = "{ user { __typename name } }";
+        let sql = build_sql_query_with_params(query, &root, None).unwrap().sql;
+        assert_eq!(
+            sql,
+            "SELECT \"user_1\".\"name\" AS \"user_1_name\" FROM \"people\" AS \"user_1\""
+        );
+        assert_eq!(
+            hydrate_results(
+                query,
+                &rows(serde_json::json!([{ "user_1_name": "Ann" }])),
+                &root,
+                None
+            )
+            .unwrap(),
+            serde_json::json!({ "user": { "__typename": "Person", "name": "Ann" } }),
+        );
+    }
+
+    #[test]
+    fn test_recursive_relations() {
+        let categories = |recursive: RecursiveInfo| {
+            Root::from(vec![
+                Node::table("categories")
+                    .alias("category_1")
+                    .field_name("category")
+                    .unique_key("id")
+                    .column("id")
+                    .column("name")
+                    .recursive("subcategories", recursive),
+            ])
+        };
+        let on = parent("id").eq(child("parent_id"));
+        let root = categories(RecursiveInfo::on(on.clone()).max_depth(3));
+        let query = "{ category { name subcategories { name depth } } }";
+        for (builder, q) in [
+            (BuilderType::Postgres, '"'),
+            (BuilderType::Sqlite, '"'),
+            (BuilderType::MySql, '`'),
+        ] {
+            let options = Options {
+                builder,
+                ..Default::default()
+            };
+            let sql = build_sql_query_with_params(query, &root, Some(&options))
+                .unwrap()
+                .sql;
+            assert_contains(
+                &sql,
+                &format!("WITH RECURSIVE {q}category_1_subcategories{q} ("),
+            );
+            assert_contains(&sql, "UNION ALL");
+            assert_contains(&sql, &format!("{q}depth{q} < (3)"));
+        }
+
+        // Flat lists come back ordered by depth
+        let rows = rows(serde_json::json!([
+            { "category_1_id": 1, "category_1_name": "Root", "category_1_2_id": 2, "category_1_2_name": "A", "category_1_subcategories_depth": 1, "category_1_subcategories_parent_key": 1 },
+            { "category_1_id": 1, "category_1_name": "Root", "category_1_2_id": 3, "category_1_2_name": "B", "category_1_subcategories_depth": 1, "category_1_subcategories_parent_key": 1 },
+            { "category_1_id": 1, "category_1_name": "Root", "category_1_2_id": 4, "category_1_2_name": "A1", "category_1_subcategories_depth": 2, "category_1_subcategories_parent_key": 2 },
+        ]));
+        assert_eq!(
+            hydrate_results(query, &rows, &root, None).unwrap(),
+            serde_json::json!({ "category": { "name": "Root", "subcategories": [
+                { "name": "A", "depth": 1 },
+                { "name": "B", "depth": 1 },
+                { "name": "A1", "depth": 2 },
+            ] } }),
+        );
+
+        let root = categories(RecursiveInfo::on(on).tree());
+        let query = "{ category { name subcategories { name subcategories { name } } } }";
+        let sql = build_sql_query_with_params(query, &root, None).unwrap().sql;
+        assert!(!sql.contains("category_1_subcategories\".\"depth\" <"));
+        assert_eq!(
+            hydrate_results(query, &rows, &root, None).unwrap(),
+            serde_json::json!({ "category": { "name": "Root", "subcategories": [
+                { "name": "A", "subcategories": [{ "name": "A1", "subcategories": [] }] },
+                { "name": "B", "subcategories": [] },
+            ] } }),
+        );
+
+        let err = build_sql_query(
+            "{ category { subcategories { name } } }",
+            Root::from(vec![
+                Node::table("categories")
+                    .field_name("category")
+                    .column("name")
+                    .recursive(
+                        "subcategories",
+                        RecursiveInfo::on(parent("id").eq(child("parent_id"))),
+                    ),
+            ]),
+            None,
+        )
+        .unwrap_err();
+        assert_contains(&err.to_string(), "needs a unique_key");
+    }
+
+    #[test]
+    fn test_mutations() {
+        let root = Root::from(vec![
+            Node::table("posts")
+                .alias("post_1")
+                .field_name("posts")
+                .unique_key("id")
+                .mutable()
+                .column("id")
+                .column("title")
+                .filter(raw("post_1.deleted_at IS NULL"))
+                .join_one("author", "user_1", on(parent("author_id").eq(child("id")))),
+            Node::table("users").alias("user_1").column("name"),
+        ]);
+        let build = |query: &str, builder: BuilderType| {
+            let options = Options {
+                builder,
+                ..Default::default()
+            };
+            build_sql_query_with_params(query, &root, Some(&options))
+        };
+
+        // Postgres selects the written rows from the write as a CTE
+        let create = "mutation { createPost(input: { title: \"Hi\" }) { id title } }";
+        let sql = build(create, BuilderType::Postgres).unwrap();
+        assert_eq!(
+            sql.sql,
+            "WITH \"post_1\" AS (INSERT INTO \"posts\" (\"title\") VALUES ($1) RETURNING *) SELECT \"post_1\".\"id\" AS \"post_1_id\", \"post_1\".\"title\" AS \"post_1_title\" FROM \"post_1\" AS \"post_1\" WHERE post_1.deleted_at IS NULL ORDER BY \"post_1\".\"id\" ASC"
+        );
+        assert_eq!(
+            sql.params,
+            vec![SqlValue::Text(Some("Hi".to_string()).into())]
+        );
+        assert_eq!(
+            hydrate_results(
+                create,
+                &rows(serde_json::json!([{ "post_1_id": 7, "post_1_title": "Hi" }])),
+                &root,
+                None
+            )
+            .unwrap(),
+            serde_json::json!({ "createPost": { "id": 7, "title": "Hi" } }),
+        );
+
+        let update = "mutation($title: String = \"Hi\") { updatePost(id: 3, patch: { title: $title }) { title } }";
+        assert_eq!(
+            build(update, BuilderType::Postgres).unwrap().sql,
+            "WITH \"post_1\" AS (UPDATE \"posts\" AS \"post_1\" SET \"title\" = $1 WHERE \"post_1\".\"id\" = $2 AND (post_1.deleted_at IS NULL) RETURNING *) SELECT \"post_1\".\"title\" AS \"post_1_title\", \"post_1\".\"id\" AS \"post_1_id\" FROM \"post_1\" AS \"post_1\" WHERE (post_1.deleted_at IS NULL) AND \"post_1\".\"id\" = $3 ORDER BY \"post_1\".\"id\" ASC"
+        );
+        let sql = build(
+            "mutation { deletePost(id: 3) { author { name } } }",
+            BuilderType::Postgres,
+        )
+        .unwrap();
+        assert_contains(
+            &sql.sql,
+            "RETURNING *) SELECT \"user_1\".\"name\" AS \"user_1_name\", \"post_1\".\"id\" AS \"post_1_id\" FROM \"post_1\" AS \"post_1\" LEFT JOIN \"users\" AS \"user_1\" ON",
+        );
+
+        // MySQL and SQLite read the row back after an insert or update, and before a delete
+        let sql = build(update, BuilderType::Sqlite).unwrap();
+        assert_eq!(
+            sql.sql,
+            "UPDATE \"posts\" AS \"post_1\" SET \"title\" = ? WHERE \"post_1\".\"id\" = ? AND (post_1.deleted_at IS NULL)"
+        );
+        assert_contains(
+            &sql.then[0].sql,
+            "SELECT \"post_1\".\"title\" AS \"post_1_title\"",
+        );
+        // SQLite finds an inserted row by the key the insert returns, which needn't be the rowid
+        let sql = build(create, BuilderType::Sqlite).unwrap();
+        assert_eq!(
+            sql.sql,
+            "INSERT INTO \"posts\" (\"title\") VALUES (?) RETURNING \"id\" AS \"post_1_id\""
+        );
+        assert_contains(&sql.then[0].sql, "\"post_1\".\"id\" = ?");
+        assert_eq!(
+            sql.then[0].keys,
+            BTreeMap::from([("post_1_id".to_string(), "post_1_id".to_string())])
+        );
+        let sql = build(create, BuilderType::MySql).unwrap();
+        assert_eq!(sql.sql, "INSERT INTO `posts` (`title`) VALUES (?)");
+        assert_contains(&sql.then[0].sql, "`post_1`.`id` = (LAST_INSERT_ID())");
+        let sql = build("mutation { deletePost(id: 3) { id } }", BuilderType::MySql).unwrap();
+        assert_contains(&sql.sql, "SELECT `post_1`.`id` AS `post_1_id` FROM `posts`");
+        assert_eq!(
+            sql.then[0].sql,
+            "DELETE FROM `posts` AS `post_1` WHERE `post_1`.`id` = ? AND (post_1.deleted_at IS NULL)"
+        );
+        assert_eq!(sql.then[0].positions["post_1_id"], vec![0]);
+        assert_contains(
+            &build(
+                "mutation { deletePost(id: 3) { author { name } } }",
+                BuilderType::MySql,
+            )
+            .unwrap()
+            .sql,
+            "LEFT JOIN `users` AS `user_1`",
+        );
+
+        for (query, message) in [
+            (
+                "mutation { deletePost(id: 3) { id } deletePost(id: 4) { id } }",
+                "A mutation can only have one root field, but it has 2",
+            ),
+            (
+                "mutation { createPost(input: $input) { id } }",
+                "The input argument of createPost is $input, which isn't given",
+            ),
+            (
+                "mutation { createPost(input: { body: \"Hi\" }) { id } }",
+                "Post has no column body to set",
+            ),
+            ("mutation { deletePost { id } }", "missing its id argument"),
+            (
+                "mutation { createUser(input: {}) { name } }",
+                "no mutable node",
+            ),
+        ] {
+            let err = build(query, BuilderType::Postgres).unwrap_err();
+            assert_contains(&err.to_string(), message);
+        }
+
+        let sdl = print_sdl(&root);
+        assert_contains(
+            &sdl,
+            "type Mutation {\n  createPost(input: PostInput!): Post\n  deletePost(id: ID!): Post\n  updatePost(id: ID!, patch: PostInput!): Post\n  upsertPost(input: PostInput!): Post\n}",
+        );
+        assert_contains(&sdl, "input PostInput {\n  id: ID\n  title: String\n}");
+        let parsed = parse_sdl(
+            r#"
+type Post @sqlTable(name: "posts", alias: "post_1", uniqueKey: "id") @sqlMutable {
+  id: ID!
+}
+type Query {
+  posts: [Post!]!
+}
+"#,
+        )
+        .unwrap();
+        assert!(parsed.0["post_1"].mutable);
+
+        // Cached plans hoist the input's literals like any other argument
+        let compiled = CompiledSchema::with_plan_cache(root, 4);
+        for title in ["Hi", "Yo"] {
+            let query = format!(
+                "mutation {{ createPost(input: {{ title: \"{}\" }}) {{ id }} }}",
+                title
+            );
+            let sql = compiled.build(&query, None, None).unwrap();
+            assert_eq!(
+                sql.params,
+                vec![SqlValue::Text(Some(title.to_string()).into())]
+            );
+        }
+
+        // An input variable compiles as if written inline, and is hydrated without it
+        let create = "mutation($input: PostInput!) { createPost(input: $input) { id } }";
+        for title in ["Hi", "Yo"] {
+            let variables = Variables(hm! { "input" => serde_json::json!({ "title": title }) });
+            let sql = compiled.build(create, Some(&variables), None).unwrap();
+            assert_contains(
+                &sql.sql,
+                "WITH \"post_1\" AS (INSERT INTO \"posts\" (\"title\") VALUES ($1) RETURNING *)",
+            );
+            assert_eq!(sql.positions["input_title"], vec![0]);
+            assert_eq!(
+                sql.params,
+                vec![SqlValue::Text(Some(title.to_string()).into())]
+            );
+        }
+        assert_eq!(
+            compiled
+                .hydrate(create, &rows(serde_json::json!([{ "post_1_id": 7 }])), None)
+                .unwrap(),
+            serde_json::json!({ "createPost": { "id": 7 } }),
+        );
+    }
+
+    #[test]
+    fn test_upserts() {
+        let post = Node::table("posts")
+            .alias("post_1")
+            .field_name("posts")
+            .unique_key("id")
+            .mutable()
+            .column("id")
+            .column("title")
+            .column("body");
+        let build = |root: &Root, query: &str, builder: BuilderType| {
+            let options = Options {
+                builder,
+                ..Default::default()
+            };
+            build_sql_query_with_params(query, root, Some(&options))
+        };
+        let upsert =
+            "mutation { upsertPost(input: { id: 3, title: \"Hi\", body: \"Yo\" }) { id } }";
+
+        let root = Root::from(vec![post.clone()]);
+        assert_eq!(
+            build(&root, upsert, BuilderType::Postgres).unwrap().sql,
+            "WITH \"post_1\" AS (INSERT INTO \"posts\" (\"body\", \"id\", \"title\") VALUES ($1, $2, $3) ON CONFLICT (\"id\") DO UPDATE SET \"body\" = \"excluded\".\"body\", \"title\" = \"excluded\".\"title\" RETURNING *) SELECT \"post_1\".\"id\" AS \"post_1_id\" FROM \"post_1\" AS \"post_1\" ORDER BY \"post_1\".\"id\" ASC"
+        );
+        assert_eq!(
+            build(&root, upsert, BuilderType::Sqlite).unwrap().sql,
+            "INSERT INTO \"posts\" (\"body\", \"id\", \"title\") VALUES (?, ?, ?) ON CONFLICT (\"id\") DO UPDATE SET \"body\" = \"excluded\".\"body\", \"title\" = \"excluded\".\"title\""
+        );
+        // MySQL infers the conflict from the key, and like SQLite reads the row back by the
+        // input's key
+        let sql = build(&root, upsert, BuilderType::MySql).unwrap();
+        assert_eq!(
+            sql.sql,
+            "INSERT INTO `posts` (`body`, `id`, `title`) VALUES (?, ?, ?) ON DUPLICATE KEY UPDATE `body` = VALUES(`body`), `title` = VALUES(`title`)"
+        );
+        assert_contains(&sql.then[0].sql, "WHERE `post_1`.`id` = ?");
+        assert_eq!(sql.then[0].positions["post_1_input_id"], vec![0]);
+
+        // Only the upsert columns are updated, and the key when the input sets none of them
+        let root = Root::from(vec![post.upsert_columns(["title"])]);
+        assert_contains(
+            &build(&root, upsert, BuilderType::Postgres).unwrap().sql,
+            "DO UPDATE SET \"title\" = \"excluded\".\"title\" RETURNING *)",
+        );
+        assert_contains(
+            &build(
+                &root,
+                "mutation { upsertPost(input: { id: 3, body: \"Yo\" }) { id } }",
+                BuilderType::Sqlite,
+            )
+            .unwrap()
+            .sql,
+            "DO UPDATE SET \"id\" = \"excluded\".\"id\"",
+        );
+
+        let err = build(
+            &root,
+            "mutation { upsertPost(input: { title: \"Hi\" }) { id } }",
+            BuilderType::Postgres,
+        )
+        .unwrap_err();
+        assert_contains(&err.to_string(), "must set the key, id");
+
+        let parsed = parse_sdl(
+            r#"
+type Post @sqlTable(name: "posts", alias: "post_1", uniqueKey: "id") @sqlMutable(upsertColumns: ["title"]) {
+  id: ID!
+  title: String
+}
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            parsed.0["post_1"].upsert_columns,
+            Some(vec!["title".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_nested_inserts() {
+        let root = Root::from(vec![
+            Node::table("users")
+                .alias("user_1")
+                .field_name("users")
+                .unique_key("id")
+                .mutable()
+                .column("id")
+                .column("name")
+                .join_many("posts", "post_1", on(parent("id").eq(child("author_id"))))
+                .join_one("profile", "profile_1", on(parent("bio_id").eq(child("id")))),
+            Node::table("posts")
+                .alias("post_1")
+                .unique_key("id")
+                .mutable()
+                .column("id")
+                .column("title")
+                .join_many(
+                    "comments",
+                    "comment_1",
+                    on(child("post_id").eq(parent("id"))),
+                ),
+            Node::table("comments")
+                .alias("comment_1")
+                .unique_key("id")
+                .mutable()
+                .column("body"),
+            Node::table("profiles")
+                .alias("profile_1")
+                .unique_key("id")
+                .mutable()
+                .column("bio"),
+        ]);
+        let build = |query: &str, builder: BuilderType| {
+            let options = Options {
+                builder,
+                ..Default::default()
+            };
+            build_sql_query_with_params(query, &root, Some(&options))
+        };
+        let create = "mutation { createUser(input: { name: \"Ann\", posts: [{ title: \"Hi\", comments: [{ body: \"Yo\" }] }, { title: \"Again\" }] }) { id name } }";
+
+        // Postgres chains the inserts as CTEs, each selecting its parent's key
+        let sql = build(create, BuilderType::Postgres).unwrap();
+        assert_eq!(
+            sql.sql,
+            "WITH \"user_1\" AS (INSERT INTO \"users\" (\"name\") VALUES ($1) RETURNING *) , \"user_1_posts_0\" AS (INSERT INTO \"posts\" (\"title\", \"author_id\") SELECT $2, \"user_1\".\"id\" FROM \"user_1\" RETURNING *) , \"user_1_posts_0_comments_0\" AS (INSERT INTO \"comments\" (\"body\", \"post_id\") SELECT $3, \"user_1_posts_0\".\"id\" FROM \"user_1_posts_0\") , \"user_1_posts_1\" AS (INSERT INTO \"posts\" (\"title\", \"author_id\") SELECT $4, \"user_1\".\"id\" FROM \"user_1\") SELECT \"user_1\".\"id\" AS \"user_1_id\", \"user_1\".\"name\" AS \"user_1_name\" FROM \"user_1\" AS \"user_1\" ORDER BY \"user_1\".\"id\" ASC"
+        );
+        assert_eq!(sql.positions["user_1_posts_1_input_title"], vec![3]);
+        assert!(sql.then.is_empty());
+
+        // Elsewhere each row is a statement, its foreign key filled from a returned key
+        let sql = build(create, BuilderType::Sqlite).unwrap();
+        assert_eq!(
+            sql.sql,
+            "INSERT INTO \"users\" (\"name\") VALUES (?) RETURNING \"id\" AS \"user_1_id\""
+        );
+        assert_eq!(
+            sql.then
+                .iter()
+                .map(|then| then.sql.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "SELECT \"user_1\".\"id\" AS \"user_1_id\", \"user_1\".\"name\" AS \"user_1_name\" FROM \"users\" AS \"user_1\" WHERE \"user_1\".\"id\" = ? ORDER BY \"user_1\".\"id\" ASC",
+                "INSERT INTO \"posts\" (\"title\", \"author_id\") VALUES (?, ?) RETURNING \"id\" AS \"user_1_posts_0_id\"",
+                "INSERT INTO \"comments\" (\"body\", \"post_id\") VALUES (?, ?)",
+                "INSERT INTO \"posts\" (\"title\", \"author_id\") VALUES (?, ?)",
+            ]
+        );
+        assert_eq!(
+            sql.then[2].keys,
+            BTreeMap::from([(
+                "user_1_posts_0_comments_0_post_id".to_string(),
+                "user_1_posts_0_id".to_string()
+            )])
+        );
+        assert_eq!(
+            sql.then[2].positions["user_1_posts_0_comments_0_post_id"],
+            vec![1]
+        );
+        let sql = build(create, BuilderType::MySql).unwrap();
+        assert_eq!(
+            sql.then
+                .iter()
+                .map(|then| then.sql.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "SELECT `user_1`.`id` AS `user_1_id`, `user_1`.`name` AS `user_1_name` FROM `users` AS `user_1` WHERE `user_1`.`id` = (LAST_INSERT_ID()) ORDER BY `user_1`.`id` ASC",
+                "INSERT INTO `posts` (`title`, `author_id`) VALUES (?, ?)",
+                "SELECT LAST_INSERT_ID() AS `user_1_posts_0_id`",
+                "INSERT INTO `comments` (`body`, `post_id`) VALUES (?, ?)",
+                "INSERT INTO `posts` (`title`, `author_id`) VALUES (?, ?)",
+            ]
+        );
+
+        // A key the input sets is bound straight into the rows referencing it
+        let sql = build(
+            "mutation { createUser(input: { id: 5, posts: [{ title: \"Hi\" }] }) { id } }",
+            BuilderType::Sqlite,
+        )
+        .unwrap();
+        assert!(sql.then[1].keys.is_empty());
+        assert_eq!(sql.then[1].positions["user_1_input_id"], vec![1]);
+
+        for (query, message) in [
+            (
+                "mutation { createUser(input: { profile: { bio: \"Hi\" } }) { id } }",
+                "User.profile can only be created along with User when it joins on $parent.<unique key>",
+            ),
+            (
+                "mutation { createUser(input: { posts: [{ title: \"Hi\", author_id: 2 }] }) { id } }",
+                "Post has no column author_id to set",
+            ),
+            (
+                "mutation { createUser(input: { posts: \"Hi\" }) { id } }",
+                "The posts of User must be a list of input objects",
+            ),
+            (
+                "mutation { upsertUser(input: { id: 5, posts: [] }) { id } }",
+                "User has no column posts to set",
+            ),
+        ] {
+            let err = build(query, BuilderType::Postgres).unwrap_err();
+            assert_contains(&err.to_string(), message);
+        }
+
+        assert_contains(
+            &print_sdl(&root),
+            "input UserInput {\n  id: ID\n  name: String\n  posts: [PostInput!]\n}",
+        );
+
+        // Cached plans hoist the literals of every row
+        let compiled = CompiledSchema::with_plan_cache(root, 4);
+        for title in ["Hi", "Yo"] {
+            let query = format!(
+                "mutation {{ createUser(input: {{ name: \"Ann\", posts: [{{ title: \"{}\" }}] }}) {{ id }} }}",
+                title
+            );
+            let sql = compiled.build(&query, None, None).unwrap();
+            assert_eq!(
+                sql.params[1],
+                SqlValue::Text(Some(title.to_string()).into())
+            );
+        }
+    }
 }
@@ -0,0 +1,144 @@
+// Copyright (c) 2025 John Johnson
+//
+// Licensed under either of
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - MIT license (https://opensource.org/licenses/MIT)
+// at your option.
+use std::{collections::HashMap, fs, path::Path, process::ExitCode};
+
+use super_join::{
+    BuilderType, CompiledSchema, Options, Root, Row, Variables,
+    core::{sdl::parse_sdl, validate::validate_schema},
+};
+
+const USAGE: &str = "\
+Compiles GraphQL queries to SQL with super-join metadata.
+
+Usage:
+  super-join build <metadata> <query.graphql> [variables.json] [--dialect postgres|mysql|sqlite] [--ast]
+  super-join validate-schema <metadata>
+  super-join hydrate <metadata> <query.graphql> <rows.json>
+  super-join metadata <metadata>
+
+<metadata> is either JSON metadata or, when it ends in .graphql or .gql, type definitions
+annotated with @sqlTable, @sqlColumn and @sqlJoin. `metadata` prints it as versioned JSON.";
+
+fn main() -> ExitCode {
+    match run(std::env::args().skip(1).collect()) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Vec<String>) -> Result<ExitCode, String> {
+    let mut positional = vec![];
+    let mut flags = HashMap::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(ExitCode::SUCCESS);
+            }
+            "--ast" => {
+                flags.insert("ast", String::new());
+            }
+            "--dialect" => {
+                let dialect = args.next().ok_or("--dialect needs a value")?;
+                flags.insert("dialect", dialect);
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown flag {}", flag)),
+            _ => positional.push(arg),
+        }
+    }
+
+    match positional.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["build", metadata, query, ref variables @ ..] if variables.len() <= 1 => {
+            let options = Options {
+                builder: dialect(flags.get("dialect").map(String::as_str))?,
+                ..Default::default()
+            };
+            let variables = match variables.first() {
+                Some(path) => Some(Variables(parse_json(path)?)),
+                None => None,
+            };
+            let schema = CompiledSchema::new(read_metadata(metadata)?);
+            let query = read(query)?;
+
+            let built = schema
+                .build(&query, variables.as_ref(), Some(&options))
+                .map_err(|err| err.to_string())?;
+            println!("{}", built.sql);
+            println!("{}", to_json(&built.params)?);
+            if flags.contains_key("ast") {
+                let sql_ast = schema
+                    .plan(&query, variables.as_ref(), Some(&options))
+                    .map_err(|err| err.to_string())?;
+                println!("{}", to_json(&sql_ast)?);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        ["validate-schema", metadata] => {
+            let issues = validate_schema(&read_metadata(metadata)?);
+            for issue in &issues {
+                println!("{}", issue);
+            }
+            Ok(if issues.is_empty() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            })
+        }
+        ["hydrate", metadata, query, rows] => {
+            let rows: Vec<Row> = parse_json(rows)?;
+            let hydrated = CompiledSchema::new(read_metadata(metadata)?)
+                .hydrate(&read(query)?, &rows, None)
+                .map_err(|err| err.to_string())?;
+            println!("{}", to_json(&hydrated)?);
+            Ok(ExitCode::SUCCESS)
+        }
+        ["metadata", metadata] => {
+            let json = read_metadata(metadata)?
+                .to_json()
+                .map_err(|err| err.to_string())?;
+            println!("{}", json);
+            Ok(ExitCode::SUCCESS)
+        }
+        _ => Err(format!("unexpected arguments\n\n{}", USAGE)),
+    }
+}
+
+fn dialect(name: Option<&str>) -> Result<BuilderType, String> {
+    match name {
+        None | Some("postgres") => Ok(BuilderType::Postgres),
+        Some("mysql") => Ok(BuilderType::MySql),
+        Some("sqlite") => Ok(BuilderType::Sqlite),
+        Some(other) => Err(format!(
+            "unknown dialect {}, expected postgres, mysql or sqlite",
+            other
+        )),
+    }
+}
+
+fn read_metadata(path: &str) -> Result<Root, String> {
+    match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+        Some("graphql" | "gql") => parse_sdl(&read(path)?).map_err(|err| err.to_string()),
+        _ => Root::from_json(&read(path)?)
+            .map_err(|err| format!("unable to parse {}: {}", path, err)),
+    }
+}
+
+fn read(path: &str) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|err| format!("unable to read {}: {}", path, err))
+}
+
+fn parse_json<T: serde::de::DeserializeOwned>(path: &str) -> Result<T, String> {
+    serde_json::from_str(&read(path)?).map_err(|err| format!("unable to parse {}: {}", path, err))
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<String, String> {
+    serde_json::to_string_pretty(value).map_err(|err| err.to_string())
+}
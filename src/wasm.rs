@@ -1,24 +1,166 @@
 use wasm_bindgen::prelude::*;
 
-use crate::core::schema::{Options, Root, RootInput};
+use crate::core::{
+    compiled::CompiledSchema,
+    cost::QueryCost,
+    error::Error,
+    hydrate::{Hydrated, Rows},
+    persisted::{Manifest, NamedQueries, QueryRegistry},
+    schema::{Options, Root, RootInput, Variables},
+    sql_schema::SqlQuery,
+    validate::SchemaIssues,
+    variables::InputVariables,
+};
 
 #[wasm_bindgen(js_name = buildSqlQuery)]
 pub fn build_sql_query(
     query: &str,
     metadata: RootInput,
     options: Option<Options>,
-) -> Result<String, JsValue> {
-    match crate::core::fns::build_sql_query(query, Root::from(metadata.0), options) {
-        Ok(sql) => Ok(sql),
-        Err(err) => Err(JsValue::from_str(&err)),
+) -> Result<String, Error> {
+    crate::core::fns::build_sql_query(query, Root::from(metadata.0), options)
+}
+
+/// Metadata deserialized and indexed once, for building many queries against it.
+#[wasm_bindgen]
+pub struct SuperJoin {
+    schema: CompiledSchema,
+}
+
+#[wasm_bindgen]
+impl SuperJoin {
+    /// Memoizes up to `plan_cache_size` query plans when given.
+    #[wasm_bindgen(constructor)]
+    pub fn new(metadata: RootInput, plan_cache_size: Option<usize>) -> SuperJoin {
+        let root = Root::from(metadata.0);
+        SuperJoin {
+            schema: match plan_cache_size {
+                Some(capacity) => CompiledSchema::with_plan_cache(root, capacity),
+                None => CompiledSchema::new(root),
+            },
+        }
+    }
+
+    pub fn build(
+        &self,
+        query: &str,
+        variables: Option<Variables>,
+        options: Option<Options>,
+    ) -> Result<SqlQuery, Error> {
+        self.schema
+            .build(query, variables.as_ref(), options.as_ref())
+    }
+
+    /// Nests the rows returned by `query`'s SQL into the shape of the query.
+    pub fn hydrate(
+        &self,
+        query: &str,
+        rows: Rows,
+        options: Option<Options>,
+    ) -> Result<Hydrated, Error> {
+        self.schema
+            .hydrate(query, &rows.0, options.as_ref())
+            .map(Hydrated)
+    }
+}
+
+#[wasm_bindgen(js_name = estimateQueryCost)]
+pub fn estimate_query_cost(
+    query: &str,
+    metadata: RootInput,
+    variables: Option<Variables>,
+) -> Result<QueryCost, Error> {
+    let root = Root::from(metadata.0);
+    let variables = InputVariables::new(variables.as_ref())?;
+    crate::core::fns::parse_gql(query)
+        .map_err(Error::from)
+        .and_then(|doc| crate::core::cost::estimate_cost(&doc, &root, &variables))
+}
+
+#[wasm_bindgen(js_name = queryHash)]
+pub fn query_hash(query: &str) -> String {
+    crate::core::persisted::query_hash(query)
+}
+
+/// Precompiled SQL for a fixed set of GraphQL operations, keyed by the hash of their text.
+#[wasm_bindgen]
+pub struct PersistedQueries {
+    registry: QueryRegistry,
+    root: Root,
+    options: Option<Options>,
+}
+
+#[wasm_bindgen]
+impl PersistedQueries {
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        queries: NamedQueries,
+        metadata: RootInput,
+        options: Option<Options>,
+        allow_list: Option<bool>,
+    ) -> Result<PersistedQueries, Error> {
+        let root = Root::from(metadata.0);
+        let manifest = Manifest::compile(&queries.0, &root, options.as_ref())?;
+        Ok(PersistedQueries {
+            registry: QueryRegistry::new(manifest, allow_list.unwrap_or(false)),
+            root,
+            options,
+        })
+    }
+
+    /// Loads a manifest written by `toJson`.
+    #[wasm_bindgen(js_name = fromJson)]
+    pub fn from_json(
+        json: &str,
+        metadata: RootInput,
+        options: Option<Options>,
+        allow_list: Option<bool>,
+    ) -> Result<PersistedQueries, Error> {
+        let manifest = Manifest::from_json(json)?;
+        Ok(PersistedQueries {
+            registry: QueryRegistry::new(manifest, allow_list.unwrap_or(false)),
+            root: Root::from(metadata.0),
+            options,
+        })
+    }
+
+    #[wasm_bindgen(js_name = toJson)]
+    pub fn to_json(&self) -> Result<String, Error> {
+        self.registry.manifest.to_json()
+    }
+
+    #[wasm_bindgen(js_name = buildByHash)]
+    pub fn build_by_hash(
+        &self,
+        hash: &str,
+        variables: Option<Variables>,
+    ) -> Result<SqlQuery, Error> {
+        self.registry.build_by_hash(hash, variables.as_ref())
+    }
+
+    pub fn build(&self, query: &str, variables: Option<Variables>) -> Result<SqlQuery, Error> {
+        self.registry
+            .build(query, variables.as_ref(), &self.root, self.options.as_ref())
     }
 }
 
-// #[wasm_bindgen(js_name = hydrateResults)]
-// pub fn hydrate_results(rows: JsValue, resolve_info: &str) -> Result<JsValue, JsValue> {
-//     // This is a stub for now; real hydration would map flat rows to nested JSON.
-//     match hydrate_results(rows, resolve_info) {
-//         Ok(sql) => Ok(JsValue::from_str(&sql)),
-//         Err(err) => Err(JsValue::from_str(&err)),
-//     }
-// }
+#[wasm_bindgen(js_name = printSdl)]
+pub fn print_sdl(metadata: RootInput) -> String {
+    crate::core::sdl::print_sdl(&Root::from(metadata.0))
+}
+
+#[wasm_bindgen(js_name = validateSchema)]
+pub fn validate_schema(metadata: RootInput) -> SchemaIssues {
+    SchemaIssues(crate::core::validate::validate_nodes(metadata.0).1)
+}
+
+#[wasm_bindgen(js_name = hydrateResults)]
+pub fn hydrate_results(
+    query: &str,
+    metadata: RootInput,
+    rows: Rows,
+    options: Option<Options>,
+) -> Result<Hydrated, Error> {
+    crate::core::hydrate::hydrate_results(query, &rows.0, &Root::from(metadata.0), options.as_ref())
+        .map(Hydrated)
+}
@@ -0,0 +1,137 @@
+//! Runs the `super-join` binary on files written to a temporary directory.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use serde_json::{Value, json};
+
+const SCHEMA: &str = r#"
+type User @sqlTable(name: "users", alias: "user_1", uniqueKey: "id") {
+  id: ID!
+  name: String
+  posts: [Post!]! @sqlJoin(on: "$parent.id = $child.author_id")
+}
+type Post @sqlTable(name: "posts", alias: "post_1", uniqueKey: "id") {
+  id: ID!
+  title: String
+}
+type Query {
+  user(id: Int): User
+}
+"#;
+
+/// A directory of its own for each test, so tests running at once don't share files.
+fn files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("super-join-cli-{}-{}", std::process::id(), test));
+    fs::create_dir_all(&dir).unwrap();
+    for (name, contents) in files {
+        fs::write(dir.join(name), contents).unwrap();
+    }
+    dir
+}
+
+/// Runs the binary in `dir` and returns whether it succeeded and what it printed.
+fn run(dir: &Path, args: &[&str]) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_super-join"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        output.status.success(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+#[test]
+fn test_build() {
+    let dir = files(
+        "build",
+        &[
+            ("schema.graphql", SCHEMA),
+            (
+                "query.graphql",
+                "query($id: Int) { user(id: $id) { name posts { title } } }",
+            ),
+            ("variables.json", r#"{ "id": 3 }"#),
+        ],
+    );
+    let (success, stdout) = run(
+        &dir,
+        &[
+            "build",
+            "schema.graphql",
+            "query.graphql",
+            "variables.json",
+            "--dialect",
+            "sqlite",
+        ],
+    );
+    assert!(success, "{}", stdout);
+    let (sql, params) = stdout.split_once('\n').unwrap();
+    assert_eq!(
+        sql,
+        "SELECT \"user_1\".\"name\" AS \"user_1_name\", \"post_1\".\"title\" AS \"post_1_title\", \"post_1\".\"id\" AS \"post_1_id\", \"user_1\".\"id\" AS \"user_1_id\" FROM \"users\" AS \"user_1\" LEFT JOIN \"posts\" AS \"post_1\" ON \"user_1\".id = \"post_1\".author_id WHERE \"user_1\".\"id\" = ? ORDER BY \"user_1\".\"id\" ASC, \"post_1\".\"id\" ASC"
+    );
+    assert_eq!(
+        serde_json::from_str::<Value>(params).unwrap(),
+        json!([{ "kind": "int", "value": 3 }])
+    );
+
+    // The AST follows as JSON
+    let (success, stdout) = run(&dir, &["build", "schema.graphql", "query.graphql", "--ast"]);
+    assert!(success);
+    let ast = stdout.splitn(3, '\n').nth(2).unwrap();
+    assert_eq!(
+        serde_json::from_str::<Value>(ast).unwrap()["alias"],
+        json!("user_1")
+    );
+
+    let (success, _) = run(
+        &dir,
+        &[
+            "build",
+            "schema.graphql",
+            "query.graphql",
+            "--dialect",
+            "oracle",
+        ],
+    );
+    assert!(!success);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_validate_and_hydrate() {
+    let dir = files(
+        "hydrate",
+        &[
+            ("schema.graphql", SCHEMA),
+            ("query.graphql", "{ user { name posts { title } } }"),
+            (
+                "rows.json",
+                r#"[
+                    { "user_1_id": 1, "user_1_name": "Ann", "post_1_id": 10, "post_1_title": "Hi" },
+                    { "user_1_id": 1, "user_1_name": "Ann", "post_1_id": 11, "post_1_title": "Again" }
+                ]"#,
+            ),
+        ],
+    );
+    assert_eq!(
+        run(&dir, &["validate-schema", "schema.graphql"]),
+        (true, String::new())
+    );
+
+    let (success, stdout) = run(
+        &dir,
+        &["hydrate", "schema.graphql", "query.graphql", "rows.json"],
+    );
+    assert!(success);
+    assert_eq!(
+        serde_json::from_str::<Value>(&stdout).unwrap(),
+        json!({ "user": { "name": "Ann", "posts": [{ "title": "Hi" }, { "title": "Again" }] } })
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,748 @@
+//! Runs the SQL super-join generates against an in-memory SQLite database and compares the
+//! hydrated rows, lists in order, to the GraphQL response they should produce.
+use rusqlite::{Connection, types::ValueRef};
+use serde_json::{Value, json};
+use super_join::{
+    BuilderType, ColumnInfo, CompiledSchema, Field, Node, Options, Root, Row, SqlExpr, SqlValue,
+    Variables, child,
+    core::{
+        schema::{OrderBy, OrderDirection},
+        sdl::parse_sdl,
+        shared_schema::ColumnRef,
+    },
+    exists, func, literal, param, parent,
+};
+
+const SCHEMA: &str = r#"
+type User @sqlTable(name: "users", alias: "user_1", uniqueKey: "id") @sqlFilterable @sqlSortable @sqlMutable {
+  id: ID!
+  name: String
+  posts: [Post!]! @sqlJoin(on: "\"user_1\".id = \"post_1\".author_id")
+}
+type Post @sqlTable(name: "posts", alias: "post_1", uniqueKey: "id") @sqlFilterable @sqlSortable @sqlMutable {
+  id: ID!
+  title: String
+  author: User @sqlJoin(on: "\"post_1\".author_id = \"user_2\".id", alias: "user_2")
+  comments: [Comment!]! @sqlJoin(on: "\"post_1\".id = \"comment_1\".post_id")
+}
+type Comment @sqlTable(name: "comments", alias: "comment_1", uniqueKey: "id") @sqlFilterable @sqlSortable @sqlMutable {
+  id: ID!
+  body: String
+  author: User @sqlJoin(on: "\"comment_1\".author_id = \"user_3\".id", alias: "user_3")
+}
+type Query {
+  users: [User!]!
+  posts: [Post!]!
+}
+"#;
+
+const FIXTURES: &str = "
+CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);
+CREATE TABLE posts (id INTEGER PRIMARY KEY, author_id INTEGER, title TEXT);
+CREATE TABLE comments (id INTEGER PRIMARY KEY, post_id INTEGER, author_id INTEGER, body TEXT);
+
+INSERT INTO users VALUES (1, 'Ann'), (2, 'Bob'), (3, 'Cy');
+INSERT INTO posts VALUES (10, 1, 'Hello'), (11, 1, 'Again'), (12, 2, 'Hi');
+INSERT INTO comments VALUES (100, 10, 2, 'Nice'), (101, 10, 3, 'Agreed'), (102, 12, 1, 'Welcome');
+
+CREATE TABLE search_results (id INTEGER PRIMARY KEY, post_id INTEGER, comment_id INTEGER);
+CREATE TABLE content (id INTEGER PRIMARY KEY, type TEXT, title TEXT, body TEXT);
+
+INSERT INTO search_results VALUES (1, 11, NULL), (2, NULL, 100);
+INSERT INTO content VALUES (1, 'post', 'Hello', NULL), (2, 'comment', NULL, 'Nice');
+
+CREATE TABLE categories (id INTEGER PRIMARY KEY, parent_id INTEGER, name TEXT);
+
+INSERT INTO categories VALUES
+  (1, NULL, 'Books'), (2, 1, 'Fiction'), (3, 1, 'Poetry'), (4, 2, 'Fantasy'), (5, 4, 'Epic');
+";
+
+/// `SCHEMA` with its join conditions written against `$parent` and `$child`, which nested
+/// inserts need to tell the foreign key of each row.
+fn placeholder_schema() -> Root {
+    let mut sdl = SCHEMA.to_string();
+    for (condition, placeholders) in [
+        (
+            r#"\"user_1\".id = \"post_1\".author_id"#,
+            "$parent.id = $child.author_id",
+        ),
+        (
+            r#"\"post_1\".author_id = \"user_2\".id"#,
+            "$parent.author_id = $child.id",
+        ),
+        (
+            r#"\"post_1\".id = \"comment_1\".post_id"#,
+            "$parent.id = $child.post_id",
+        ),
+        (
+            r#"\"comment_1\".author_id = \"user_3\".id"#,
+            "$parent.author_id = $child.id",
+        ),
+    ] {
+        assert!(sdl.contains(condition), "{}", condition);
+        sdl = sdl.replace(condition, placeholders);
+    }
+    parse_sdl(&sdl).unwrap()
+}
+
+struct Harness {
+    db: Connection,
+    schema: CompiledSchema,
+}
+
+impl Harness {
+    fn new() -> Self {
+        Harness::with_root(parse_sdl(SCHEMA).unwrap())
+    }
+
+    fn with_root(root: Root) -> Self {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(FIXTURES).unwrap();
+        Harness {
+            db,
+            schema: CompiledSchema::new(root),
+        }
+    }
+
+    /// Builds `query` for SQLite, runs it and hydrates the rows it returns.
+    ///
+    /// The statements after the first get their key params from the rows earlier ones
+    /// returned, and the one that isn't a write returns the rows hydrated.
+    fn run(&self, query: &str, variables: Option<Variables>) -> Value {
+        let options = Options {
+            builder: BuilderType::Sqlite,
+            ..Default::default()
+        };
+        let built = self
+            .schema
+            .build(query, variables.as_ref(), Some(&options))
+            .unwrap();
+
+        // The rows are those of the statement that isn't a write
+        let mut rows = None;
+        let mut returned = Row::new();
+        for statement in std::iter::once(&built).chain(&built.then) {
+            let mut params = statement.params.iter().map(to_sql).collect::<Vec<_>>();
+            for (name, column) in &statement.keys {
+                for &position in &statement.positions[name] {
+                    params[position] = from_json(&returned[column]);
+                }
+            }
+            let statement_rows = self.query(&statement.sql, params);
+            if let Some(row) = statement_rows.first() {
+                returned.extend(row.clone());
+            }
+            if !["INSERT", "UPDATE", "DELETE"]
+                .iter()
+                .any(|write| statement.sql.starts_with(write))
+            {
+                rows = Some(statement_rows);
+            }
+        }
+
+        self.schema
+            .hydrate(query, &rows.unwrap_or_default(), Some(&options))
+            .unwrap()
+    }
+
+    fn query(&self, sql: &str, params: Vec<rusqlite::types::Value>) -> Vec<Row> {
+        let mut statement = self
+            .db
+            .prepare(sql)
+            .unwrap_or_else(|err| panic!("{}\nin:\n{}", err, sql));
+        let columns = statement
+            .column_names()
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        statement
+            .query_map(rusqlite::params_from_iter(params), |row| {
+                let mut hydrated = Row::new();
+                for (i, column) in columns.iter().enumerate() {
+                    hydrated.insert(column.clone(), to_json(row.get_ref(i)?));
+                }
+                Ok(hydrated)
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    fn assert_query(&self, query: &str, variables: Option<Variables>, expected: Value) {
+        assert_eq!(self.run(query, variables), expected);
+    }
+}
+
+fn to_sql(value: &SqlValue) -> rusqlite::types::Value {
+    match value {
+        SqlValue::Int(int) => int
+            .value
+            .map_or(rusqlite::types::Value::Null, |int| int.into()),
+        SqlValue::Float(float) => float
+            .value
+            .map_or(rusqlite::types::Value::Null, |float| float.into()),
+        SqlValue::Text(text) => text
+            .value
+            .clone()
+            .map_or(rusqlite::types::Value::Null, |text| text.into()),
+        SqlValue::Bool(bool) => bool
+            .value
+            .map_or(rusqlite::types::Value::Null, |bool| bool.into()),
+    }
+}
+
+fn from_json(value: &Value) -> rusqlite::types::Value {
+    match value {
+        Value::Number(number) if number.is_i64() => number.as_i64().unwrap().into(),
+        Value::String(text) => text.clone().into(),
+        other => panic!("expected a returned key, got {}", other),
+    }
+}
+
+fn to_json(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(int) => json!(int),
+        ValueRef::Real(float) => json!(float),
+        ValueRef::Text(text) => json!(String::from_utf8_lossy(text)),
+        ValueRef::Blob(blob) => json!(blob),
+    }
+}
+
+#[test]
+fn test_nested_lists() {
+    let harness = Harness::new();
+    harness.assert_query(
+        "{ users { name posts { title } } }",
+        None,
+        json!({ "users": [
+            { "name": "Ann", "posts": [{ "title": "Hello" }, { "title": "Again" }] },
+            { "name": "Bob", "posts": [{ "title": "Hi" }] },
+            { "name": "Cy", "posts": [] },
+        ] }),
+    );
+
+    // Sibling lists repeat each user once per pair of their rows, which without a unique key
+    // are grouped back together by the selected columns
+    let mut root = parse_sdl(SCHEMA).unwrap();
+    root.0.get_mut("user_1").unwrap().unique_key = None;
+    Harness::with_root(root).assert_query(
+        "{ users(orderBy: [{ field: NAME }]) { name posts { title } again: posts { title } } }",
+        None,
+        json!({ "users": [
+            {
+                "name": "Ann",
+                "posts": [{ "title": "Hello" }, { "title": "Again" }],
+                "again": [{ "title": "Hello" }, { "title": "Again" }],
+            },
+            { "name": "Bob", "posts": [{ "title": "Hi" }], "again": [{ "title": "Hi" }] },
+            { "name": "Cy", "posts": [], "again": [] },
+        ] }),
+    );
+}
+
+#[test]
+fn test_limits() {
+    let harness = Harness::new();
+    harness.assert_query(
+        "{ users(limit: 2) { name posts(first: 1, orderBy: [{ field: TITLE, direction: DESC }]) { title comments(limit: 1) { body } } } }",
+        None,
+        json!({ "users": [
+            { "name": "Ann", "posts": [{ "title": "Hello", "comments": [{ "body": "Nice" }] }] },
+            { "name": "Bob", "posts": [{ "title": "Hi", "comments": [{ "body": "Welcome" }] }] },
+        ] }),
+    );
+    // Filtered out rows are not counted
+    harness.assert_query(
+        "query($limit: Int) { users(limit: $limit, where: { name: { neq: \"Ann\" } }) { name posts(limit: 1, where: { title: { eq: \"Again\" } }) { title } } }",
+        Some(Variables([("limit".to_string(), json!(1))].into())),
+        json!({ "users": [{ "name": "Bob", "posts": [] }] }),
+    );
+    harness.assert_query(
+        "{ users(where: { name: { eq: \"Ann\" } }) { posts(limit: 1, where: { title: { eq: \"Again\" } }) { title } } }",
+        None,
+        json!({ "users": [{ "posts": [{ "title": "Again" }] }] }),
+    );
+}
+
+#[test]
+fn test_recursive_joins() {
+    let harness = Harness::new();
+    harness.assert_query(
+        "{ posts { title author { name } comments { body author { name } } } }",
+        None,
+        json!({ "posts": [
+            { "title": "Hello", "author": { "name": "Ann" }, "comments": [
+                { "body": "Nice", "author": { "name": "Bob" } },
+                { "body": "Agreed", "author": { "name": "Cy" } },
+            ] },
+            { "title": "Again", "author": { "name": "Ann" }, "comments": [] },
+            { "title": "Hi", "author": { "name": "Bob" }, "comments": [
+                { "body": "Welcome", "author": { "name": "Ann" } },
+            ] },
+        ] }),
+    );
+}
+
+#[test]
+fn test_join_placeholders() {
+    let query =
+        "{ users { name posts { title author { name } comments { body author { name } } } } }";
+    assert_eq!(
+        Harness::with_root(placeholder_schema()).run(query, None),
+        Harness::new().run(query, None),
+    );
+}
+
+#[test]
+fn test_response_aliases() {
+    let harness = Harness::new();
+    harness.assert_query(
+        "{ posts { headline: title writer: author { id handle: name } } }",
+        None,
+        json!({ "posts": [
+            { "headline": "Hello", "writer": { "id": 1, "handle": "Ann" } },
+            { "headline": "Again", "writer": { "id": 1, "handle": "Ann" } },
+            { "headline": "Hi", "writer": { "id": 2, "handle": "Bob" } },
+        ] }),
+    );
+}
+
+#[test]
+fn test_filters() {
+    let mut root = parse_sdl(SCHEMA).unwrap();
+    let comment = root.0.remove("comment_1").unwrap();
+    let body = SqlExpr::Column(ColumnRef::from(String::from("body")));
+    root.0.insert(
+        "comment_1".into(),
+        comment.filter(body.neq(literal("Agreed"))),
+    );
+    let harness = Harness::with_root(root);
+
+    // A filtered join keeps its parents
+    harness.assert_query(
+        "{ posts { title comments { body } } }",
+        None,
+        json!({ "posts": [
+            { "title": "Hello", "comments": [{ "body": "Nice" }] },
+            { "title": "Again", "comments": [] },
+            { "title": "Hi", "comments": [{ "body": "Welcome" }] },
+        ] }),
+    );
+
+    harness.assert_query(
+        "{ users(id: 2) { name } }",
+        None,
+        json!({ "users": [{ "name": "Bob" }] }),
+    );
+    harness.assert_query(
+        "query($id: Int) { users(id: $id) { name posts(title: \"Again\") { id } } }",
+        Some(serde_json::from_value(json!({ "id": 1 })).unwrap()),
+        json!({ "users": [{ "name": "Ann", "posts": [{ "id": 11 }] }] }),
+    );
+}
+
+#[test]
+fn test_expressions() {
+    let column = |name: &str| SqlExpr::Column(ColumnRef::from(name.to_string()));
+    let filtered = |filter: SqlExpr| {
+        let mut root = parse_sdl(SCHEMA).unwrap();
+        let user = root.0.remove("user_1").unwrap();
+        root.0.insert("user_1".into(), user.filter(filter));
+        Harness::with_root(root)
+    };
+
+    // SQLite has no ILIKE, so it is emulated with LOWER
+    filtered(column("name").ilike("b%")).assert_query(
+        "{ users { name } }",
+        None,
+        json!({ "users": [{ "name": "Bob" }] }),
+    );
+    filtered(exists(
+        "posts",
+        "post_9",
+        column("author_id").eq(SqlExpr::Column(ColumnRef {
+            column: "id".into(),
+            table: Some("user_1".into()),
+        })),
+    ))
+    .assert_query(
+        "{ users { name } }",
+        None,
+        json!({ "users": [{ "name": "Ann" }, { "name": "Bob" }] }),
+    );
+    filtered(
+        func("length", vec![column("name")])
+            .between(
+                param("min", SqlValue::Int(Some(3).into())),
+                param("max", SqlValue::Int(Some(3).into())),
+            )
+            .and(column("id").not_in(vec![literal("1")])),
+    )
+    .assert_query(
+        "{ users { name } }",
+        None,
+        json!({ "users": [{ "name": "Bob" }] }),
+    );
+}
+
+#[test]
+fn test_where_argument() {
+    let harness = Harness::new();
+    harness.assert_query(
+        "{ posts(where: { title: { contains: \"i\" } }) { title } }",
+        None,
+        json!({ "posts": [{ "title": "Again" }, { "title": "Hi" }] }),
+    );
+    // LIKE wildcards in the text match only themselves
+    for text in ["%", "_", "H_"] {
+        harness.assert_query(
+            &format!(
+                "{{ posts(where: {{ title: {{ contains: \"{}\" }} }}) {{ title }} }}",
+                text
+            ),
+            None,
+            json!({ "posts": [] }),
+        );
+    }
+    harness.assert_query(
+        "{ posts(where: { author: { name: { eq: \"Ann\" } } }) { title } }",
+        None,
+        json!({ "posts": [{ "title": "Hello" }, { "title": "Again" }] }),
+    );
+    harness.assert_query(
+        "{ users(where: { OR: [{ id: { in: [1, 3] } }, { NOT: { posts: { title: { neq: \"Hi\" } } } }] }) { name } }",
+        None,
+        json!({ "users": [{ "name": "Ann" }, { "name": "Bob" }, { "name": "Cy" }] }),
+    );
+    harness.assert_query(
+        "{ users(where: { name: { isNull: false }, id: { gt: 1, lt: 3 } }) { name } }",
+        None,
+        json!({ "users": [{ "name": "Bob" }] }),
+    );
+    // A filtered list keeps its parents, and values can be variables
+    harness.assert_query(
+        "query($title: String) { users { name posts(where: { title: { eq: $title } }) { title } } }",
+        Some(serde_json::from_value(json!({ "title": "Hello" })).unwrap()),
+        json!({ "users": [
+            { "name": "Ann", "posts": [{ "title": "Hello" }] },
+            { "name": "Bob", "posts": [] },
+            { "name": "Cy", "posts": [] },
+        ] }),
+    );
+    // So can the whole filter
+    harness.assert_query(
+        "query($where: UserWhere) { users(where: $where) { name } }",
+        Some(
+            serde_json::from_value(
+                json!({ "where": { "OR": [{ "id": { "eq": 3 } }, { "name": { "eq": "Ann" } }] } }),
+            )
+            .unwrap(),
+        ),
+        json!({ "users": [{ "name": "Ann" }, { "name": "Cy" }] }),
+    );
+}
+
+#[test]
+fn test_order_by_argument() {
+    let harness = Harness::new();
+    let run = |query| harness.run(query, None);
+    assert_eq!(
+        run("{ posts(orderBy: { field: TITLE, direction: DESC }) { title } }"),
+        json!({ "posts": [{ "title": "Hi" }, { "title": "Hello" }, { "title": "Again" }] }),
+    );
+    // Nested lists sort within their parents
+    assert_eq!(
+        run(
+            "{ users(orderBy: { field: NAME }) { name posts(orderBy: { field: TITLE }) { title } } }"
+        ),
+        json!({ "users": [
+            { "name": "Ann", "posts": [{ "title": "Again" }, { "title": "Hello" }] },
+            { "name": "Bob", "posts": [{ "title": "Hi" }] },
+            { "name": "Cy", "posts": [] },
+        ] }),
+    );
+    assert_eq!(
+        run(
+            "{ posts(orderBy: [{ author: { field: NAME }, direction: DESC }, { field: TITLE }]) { title } }"
+        ),
+        json!({ "posts": [{ "title": "Hi" }, { "title": "Again" }, { "title": "Hello" }] }),
+    );
+    // Sorting through a join the query also selects
+    assert_eq!(
+        run(
+            "{ posts(limit: 2, orderBy: [{ author: { field: NAME }, direction: DESC }, { field: TITLE }]) { title author { name } } }"
+        ),
+        json!({ "posts": [
+            { "title": "Hi", "author": { "name": "Bob" } },
+            { "title": "Again", "author": { "name": "Ann" } },
+        ] }),
+    );
+}
+
+#[test]
+fn test_deterministic_order() {
+    let mut root = parse_sdl(SCHEMA).unwrap();
+    let post = root.0.get_mut("post_1").unwrap();
+    post.fields.insert(
+        "latest".into(),
+        Field::OrderBy(OrderBy {
+            expr: ColumnInfo::from("id".to_string()),
+            direction: OrderDirection::Desc,
+        }),
+    );
+    let harness = Harness::with_root(root);
+
+    // Lists come back in their default sort, within the unique key order of their parents
+    harness.assert_query(
+        "{ users { name posts { id comments { id } } } }",
+        None,
+        json!({ "users": [
+            { "name": "Ann", "posts": [
+                { "id": 11, "comments": [] },
+                { "id": 10, "comments": [{ "id": 100 }, { "id": 101 }] },
+            ] },
+            { "name": "Bob", "posts": [{ "id": 12, "comments": [{ "id": 102 }] }] },
+            { "name": "Cy", "posts": [] },
+        ] }),
+    );
+}
+
+#[test]
+fn test_polymorphic_nodes() {
+    let mut root = parse_sdl(SCHEMA).unwrap();
+    for node in [
+        Node::table("search_results")
+            .alias("search_1")
+            .field_name("search")
+            .many()
+            .unique_key("id")
+            .graphql_type("SearchResult")
+            .member("post_1", parent("post_id").eq(child("id")))
+            .member("comment_1", parent("comment_id").eq(child("id"))),
+        Node::table("content")
+            .alias("content_1")
+            .field_name("content")
+            .many()
+            .unique_key("id")
+            .graphql_type("Content")
+            .column("id")
+            .column("title")
+            .column("body")
+            .single_table("type", [("post", "Post"), ("comment", "Comment")]),
+    ] {
+        root.0.insert(node.alias.clone(), node);
+    }
+    let harness = Harness::with_root(root);
+
+    harness.assert_query(
+        "{ search { ... on Post { title author { name } } ... on Comment { body } } }",
+        None,
+        json!({ "search": [
+            { "__typename": "Post", "title": "Again", "author": { "name": "Ann" } },
+            { "__typename": "Comment", "body": "Nice" },
+        ] }),
+    );
+    harness.assert_query(
+        "{ content { id ... on Post { title } ... on Comment { body } } }",
+        None,
+        json!({ "content": [
+            { "__typename": "Post", "id": 1, "title": "Hello" },
+            { "__typename": "Comment", "id": 2, "body": "Nice" },
+        ] }),
+    );
+}
+
+#[test]
+fn test_typename() {
+    let harness = Harness::new();
+    harness.assert_query(
+        "{ users(id: 1) { __typename kind: __typename posts(title: \"Hello\") { __typename title } } }",
+        None,
+        json!({ "users": [{
+            "__typename": "User",
+            "kind": "User",
+            "posts": [{ "__typename": "Post", "title": "Hello" }],
+        }] }),
+    );
+}
+
+#[test]
+fn test_recursive_relations() {
+    let categories = |directive: &str| {
+        parse_sdl(&format!(
+            r#"
+type Category @sqlTable(name: "categories", alias: "category_1", uniqueKey: "id") {{
+  id: ID!
+  name: String
+  subcategories: [Category!]! {}
+  depth: Int
+}}
+type Query {{
+  categories: [Category!]!
+}}
+"#,
+            directive
+        ))
+        .unwrap()
+    };
+
+    let harness = Harness::with_root(categories(
+        r#"@sqlRecursive(on: "$parent.id = $child.parent_id", maxDepth: 2)"#,
+    ));
+    harness.assert_query(
+        "{ categories(id: 1) { name subcategories { name depth } } }",
+        None,
+        json!({ "categories": [{ "name": "Books", "subcategories": [
+            { "name": "Fiction", "depth": 1 },
+            { "name": "Poetry", "depth": 1 },
+            { "name": "Fantasy", "depth": 2 },
+        ] }] }),
+    );
+
+    let harness = Harness::with_root(categories(
+        r#"@sqlRecursive(on: "$parent.id = $child.parent_id", tree: true)"#,
+    ));
+    harness.assert_query(
+        "{ categories(id: 2) { name subcategories { name subcategories { name } } } }",
+        None,
+        json!({ "categories": [{ "name": "Fiction", "subcategories": [
+            { "name": "Fantasy", "subcategories": [{ "name": "Epic", "subcategories": [] }] },
+        ] }] }),
+    );
+    harness.assert_query(
+        "{ categories(id: 5) { name subcategories { name } } }",
+        None,
+        json!({ "categories": [{ "name": "Epic", "subcategories": [] }] }),
+    );
+}
+
+#[test]
+fn test_mutations() {
+    let harness = Harness::new();
+    harness.assert_query(
+        "mutation { createPost(input: { title: \"New\" }) { id title } }",
+        None,
+        json!({ "createPost": { "id": 13, "title": "New" } }),
+    );
+    harness.assert_query(
+        "mutation($patch: PostInput!) { updatePost(id: 12, patch: $patch) { id title } }",
+        Some(serde_json::from_value(json!({ "patch": { "title": "Yo" } })).unwrap()),
+        json!({ "updatePost": { "id": 12, "title": "Yo" } }),
+    );
+    harness.assert_query(
+        "mutation { updatePost(id: 12, patch: { title: $title }) { id title } }",
+        Some(Variables([("title".to_string(), json!("Hey"))].into())),
+        json!({ "updatePost": { "id": 12, "title": "Hey" } }),
+    );
+    harness.assert_query(
+        "mutation { deletePost(id: 11) { title } }",
+        None,
+        json!({ "deletePost": { "title": "Again" } }),
+    );
+    harness.assert_query(
+        "mutation { deletePost(id: 11) { title } }",
+        None,
+        json!({ "deletePost": null }),
+    );
+    // Relations are read along with the written row
+    harness.assert_query(
+        "mutation { updatePost(id: 10, patch: { title: \"Howdy\" }) { title author { name } comments { body } } }",
+        None,
+        json!({ "updatePost": {
+            "title": "Howdy",
+            "author": { "name": "Ann" },
+            "comments": [{ "body": "Nice" }, { "body": "Agreed" }],
+        } }),
+    );
+    harness.assert_query(
+        "mutation { deletePost(id: 10) { title author { name } } }",
+        None,
+        json!({ "deletePost": { "title": "Howdy", "author": { "name": "Ann" } } }),
+    );
+    harness.assert_query(
+        "{ posts { id title } }",
+        None,
+        json!({ "posts": [
+            { "id": 12, "title": "Hey" },
+            { "id": 13, "title": "New" },
+        ] }),
+    );
+}
+
+#[test]
+fn test_generated_keys() {
+    // A key the database generates that isn't the rowid
+    let harness = Harness::with_root(
+        Root::builder()
+            .node(
+                Node::table("tags")
+                    .alias("tag_1")
+                    .field_name("tags")
+                    .many()
+                    .unique_key("code")
+                    .mutable()
+                    .column("code")
+                    .column("label"),
+            )
+            .build()
+            .unwrap(),
+    );
+    harness
+        .db
+        .execute_batch(
+            "CREATE TABLE tags (code TEXT PRIMARY KEY DEFAULT 'wasm', label TEXT);
+            INSERT INTO tags VALUES ('rust', 'Rust');",
+        )
+        .unwrap();
+    harness.assert_query(
+        "mutation { createTag(input: { label: \"Wasm\" }) { code label } }",
+        None,
+        json!({ "createTag": { "code": "wasm", "label": "Wasm" } }),
+    );
+}
+
+#[test]
+fn test_upserts() {
+    let harness = Harness::new();
+    harness.assert_query(
+        "mutation { upsertPost(input: { id: 13, title: \"New\" }) { id title } }",
+        None,
+        json!({ "upsertPost": { "id": 13, "title": "New" } }),
+    );
+    harness.assert_query(
+        "mutation { upsertPost(input: { id: 10, title: \"Bye\" }) { id title } }",
+        None,
+        json!({ "upsertPost": { "id": 10, "title": "Bye" } }),
+    );
+    harness.assert_query(
+        "{ posts { id title author { name } } }",
+        None,
+        json!({ "posts": [
+            { "id": 10, "title": "Bye", "author": { "name": "Ann" } },
+            { "id": 11, "title": "Again", "author": { "name": "Ann" } },
+            { "id": 12, "title": "Hi", "author": { "name": "Bob" } },
+            { "id": 13, "title": "New", "author": null },
+        ] }),
+    );
+}
+
+#[test]
+fn test_nested_inserts() {
+    let harness = Harness::with_root(placeholder_schema());
+    harness.assert_query(
+        "mutation { createUser(input: { name: \"Dee\", posts: [{ title: \"First\", comments: [{ body: \"Wow\" }, { body: \"Neat\" }] }, { title: \"Second\" }] }) { id name } }",
+        None,
+        json!({ "createUser": { "id": 4, "name": "Dee" } }),
+    );
+    harness.assert_query(
+        "{ users(where: { name: { eq: \"Dee\" } }) { name posts { id title comments { body } } } }",
+        None,
+        json!({ "users": [
+            { "name": "Dee", "posts": [
+                { "id": 13, "title": "First", "comments": [{ "body": "Wow" }, { "body": "Neat" }] },
+                { "id": 14, "title": "Second", "comments": [] },
+            ] },
+        ] }),
+    );
+}